@@ -0,0 +1,329 @@
+//! This module implements the accept mode, which takes over the new hashsums of files
+//! that verify reported in to_check_DIRECTORY.txt, after the changes have been reviewed
+
+extern crate chrono;
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use self::chrono::DateTime;
+
+use super::filesystem::FileSystem;
+use super::links::RecordedLinks;
+use super::statcache::StatCache;
+use super::sumfile::SumfileEntry;
+use super::util::DirectoryError;
+
+/// Shows the changed files listed in the to_check_DIRECTORY.txt of some directories
+/// and replaces their lines in _algorithm_sum.txt with their new hashsums once they are accepted.
+/// Accepted files are removed from to_check_DIRECTORY.txt, a directory without any remaining files
+/// is removed from the to_check_MONTH_YEAR.txt of every month, so that it is verified again.
+///
+/// # Arguments
+///
+/// * `opts` An Options object containing information about the program behavior
+///
+/// # Returns
+/// The exit code the program should return.
+pub fn accept_directories(opts: super::util::Options) -> i32 {
+    let _run_lock = match super::lock::lock_run(&opts.config, &mut |notice| {
+        if opts.loglevel_info() {
+            let now: DateTime<chrono::Local> = chrono::Local::now();
+            println!("[{}] {}", now, notice);
+        }
+    }) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 3;
+        }
+    };
+
+    let dirs_to_process = if opts.subdir_mode {
+        let filesystem = &opts.config.filesystem;
+        super::util::subdirectories(&opts.config)
+            .unwrap()
+            .into_iter()
+            .filter(|path| {
+                filesystem
+                    .metadata(&super::verify::to_check_report_path(&opts.config, path))
+                    .is_ok_and(|metadata| metadata.is_file)
+            })
+            .collect()
+    } else {
+        vec![opts.config.root.clone()]
+    };
+
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut exit_code = 0;
+
+    for workdir in dirs_to_process {
+        match accept_directory(&workdir, &opts, &mut input) {
+            Ok(true) => {}
+            Ok(false) => exit_code = 1,
+            Err(e) => {
+                let now: DateTime<chrono::Local> = chrono::Local::now();
+                eprintln!("[{}] {}: {}", now, workdir.to_str().unwrap(), e);
+                exit_code = e.exit_code();
+            }
+        }
+    }
+
+    exit_code
+}
+
+/// Shows the changed files listed in the to_check_DIRECTORY.txt of a directory and
+/// replaces their lines in _algorithm_sum.txt with their new hashsums once they are accepted
+///
+/// # Arguments
+/// * `workdir` Path to the directory that was verified
+/// * `opts` Options object containing the Config and whether to accept without asking
+/// * `input` The BufRead the answers are read from
+///
+/// # Returns
+/// Whether every file listed in to_check_DIRECTORY.txt was accepted.
+pub fn accept_directory(
+    workdir: &Path,
+    opts: &super::util::Options,
+    input: &mut dyn BufRead,
+) -> Result<bool, DirectoryError> {
+    let config = &opts.config;
+    let report_path = super::verify::to_check_report_path(config, workdir);
+    let report_error =
+        |e: io::Error| DirectoryError::Sumfile(format!("{}: {}", report_path.display(), e));
+    let report = match config.filesystem.read(&report_path) {
+        Ok(report) => String::from_utf8_lossy(&report).into_owned(),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+            if opts.loglevel_info() {
+                let now: DateTime<chrono::Local> = chrono::Local::now();
+                println!("[{}] {}: nothing to accept", now, workdir.to_str().unwrap());
+            }
+            return Ok(true);
+        }
+        Err(e) => return Err(report_error(e)),
+    };
+
+    let _lock = super::lock::lock_directory(workdir, config, &mut |notice| {
+        if opts.loglevel_info() {
+            let now: DateTime<chrono::Local> = chrono::Local::now();
+            println!("[{}] {}", now, notice);
+        }
+    })
+    .map_err(DirectoryError::Locked)?;
+
+    let sumfile_path = config
+        .sumfile_path(workdir)
+        .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+    let sumfile_error =
+        |e: io::Error| DirectoryError::Sumfile(format!("{}: {}", sumfile_path.display(), e));
+    let mut listed: HashMap<PathBuf, Vec<SumfileEntry>> = HashMap::new();
+    for entry in super::sumfile::read_entries(&sumfile_path, config).map_err(&sumfile_error)? {
+        if let Some(key) = super::util::normalize_path(Path::new(&entry.path)) {
+            listed.entry(key).or_default().push(entry);
+        }
+    }
+
+    let links_path = config
+        .links_path(workdir)
+        .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+    let stat_cache_path = config
+        .stat_cache_path(workdir)
+        .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+    let links = RecordedLinks::read(&*config.filesystem, &links_path)
+        .map_err(|e| DirectoryError::Sumfile(format!("{}: {}", links_path.display(), e)))?;
+
+    let mut replacements = HashMap::new();
+    let mut accepted_stats = StatCache::default();
+    let mut accepted = 0;
+    let mut remaining = Vec::new();
+
+    for line in report.lines().filter(|line| !line.is_empty()) {
+        let now: DateTime<chrono::Local> = chrono::Local::now();
+        let entries =
+            match super::util::normalize_path(Path::new(line)).and_then(|key| listed.get(&key)) {
+                Some(entries) => entries,
+                None => {
+                    // files that could not be hashed are listed together with the error
+                    eprintln!(
+                        "[{}] {}: {} is not listed in {}, keeping it",
+                        now,
+                        workdir.to_str().unwrap(),
+                        line,
+                        sumfile_path.to_str().unwrap()
+                    );
+                    remaining.push(line);
+                    continue;
+                }
+            };
+
+        let entry = &entries[0];
+        let (hash, size) = match super::util::calculate_hash(
+            entry.path.clone(),
+            workdir,
+            config.algorithm,
+            &*config.filesystem,
+            links.policy(&entry.path),
+        ) {
+            Ok(result) => result,
+            Err(e) => {
+                eprintln!("[{}] {}: {}", now, workdir.to_str().unwrap(), e);
+                remaining.push(line);
+                continue;
+            }
+        };
+
+        if hash == entry.hash {
+            if opts.loglevel_info() {
+                println!(
+                    "[{}] {}: {} matches its hashsum again",
+                    now,
+                    workdir.to_str().unwrap(),
+                    entry.path
+                );
+            }
+            continue;
+        }
+
+        let modified = config
+            .filesystem
+            .metadata(&workdir.join(&entry.path))
+            .ok()
+            .and_then(|metadata| metadata.modified)
+            .map(|modified| DateTime::<chrono::Local>::from(modified).to_string())
+            .unwrap_or_else(|| String::from("unknown"));
+        println!(
+            "[{}] {}: {}: {} -> {}, {}, modified {}",
+            now,
+            workdir.to_str().unwrap(),
+            entry.path,
+            entry.hash,
+            hash,
+            super::util::format_bytes(size),
+            modified
+        );
+
+        if opts.assume_yes || confirm(input) {
+            // a file listed in multiple lines gets the new hashsum in every line
+            for entry in entries.iter() {
+                replacements.insert(entry.line_number, format!("{}  {}", hash, entry.path));
+                accepted_stats.refresh(
+                    &*config.filesystem,
+                    workdir,
+                    &entry.path,
+                    &hash,
+                    links.policy(&entry.path),
+                );
+            }
+            accepted += 1;
+        } else {
+            remaining.push(line);
+        }
+    }
+
+    if !replacements.is_empty() {
+        super::sumfile::replace_lines(&*config.filesystem, &sumfile_path, &replacements)
+            .map_err(&sumfile_error)?;
+
+        // update must not report the accepted files as changed since they were hashed
+        let refreshed =
+            StatCache::read(&*config.filesystem, &stat_cache_path).and_then(|mut stat_cache| {
+                stat_cache.stats.append(&mut accepted_stats.stats);
+                stat_cache.write(&*config.filesystem, &stat_cache_path)
+            });
+        if let Err(e) = refreshed {
+            let now: DateTime<chrono::Local> = chrono::Local::now();
+            eprintln!(
+                "[{}] {}: could not update {}: {}",
+                now,
+                workdir.to_str().unwrap(),
+                stat_cache_path.to_str().unwrap(),
+                e
+            );
+        }
+    }
+
+    if remaining.is_empty() {
+        config
+            .filesystem
+            .remove_file(&report_path)
+            .map_err(&report_error)?;
+        // the directory may have been checked in an earlier month
+        let state_error = |e: io::Error| DirectoryError::Sumfile(e.to_string());
+        for to_check_path in super::verify::to_check_paths(config).map_err(state_error)? {
+            remove_line(
+                &*config.filesystem,
+                &to_check_path,
+                workdir.to_str().unwrap(),
+            )
+            .map_err(|e| DirectoryError::Sumfile(format!("{}: {}", to_check_path.display(), e)))?;
+        }
+    } else {
+        let mut contents = remaining.join("\n");
+        contents.push('\n');
+        config
+            .filesystem
+            .write_atomic(&report_path, contents.as_bytes())
+            .map_err(&report_error)?;
+    }
+
+    if opts.loglevel_info() {
+        let now: DateTime<chrono::Local> = chrono::Local::now();
+        println!(
+            "[{}] {}: accepted {} files, {} files left to check",
+            now,
+            workdir.to_str().unwrap(),
+            accepted,
+            remaining.len()
+        );
+    }
+
+    Ok(remaining.is_empty())
+}
+
+/// Asks whether to accept a new hashsum and reads the answer
+///
+/// # Arguments
+/// * `input` The BufRead the answer is read from
+///
+/// # Returns
+/// true if the answer was yes, false for every other answer or if nothing could be read.
+fn confirm(input: &mut dyn BufRead) -> bool {
+    print!("Accept the new hashsum? [y/N] ");
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    match input.read_line(&mut answer) {
+        Ok(0) | Err(_) => {
+            println!();
+            false
+        }
+        Ok(_) => {
+            let answer = answer.trim().to_lowercase();
+            answer == "y" || answer == "yes"
+        }
+    }
+}
+
+/// Removes every line equal to the given one from a text file, if it exists
+///
+/// # Arguments
+/// * `filesystem` The filesystem containing the text file
+/// * `path` Path to the text file
+/// * `line` The line to remove
+fn remove_line(filesystem: &dyn FileSystem, path: &Path, line: &str) -> io::Result<()> {
+    let contents = match filesystem.read(path) {
+        Ok(contents) => String::from_utf8_lossy(&contents).into_owned(),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+
+    let kept: String = contents
+        .lines()
+        .filter(|l| *l != line)
+        .map(|l| format!("{}\n", l))
+        .collect();
+
+    filesystem.write_atomic(path, kept.as_bytes())
+}
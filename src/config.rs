@@ -0,0 +1,301 @@
+//! This module describes the configuration of the hashing engine, independent of the command line
+
+extern crate digest;
+extern crate md5;
+extern crate num_cpus;
+extern crate regex;
+extern crate sha1;
+extern crate sha2;
+
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use self::digest::{Digest, DynDigest};
+use self::md5::Md5;
+use self::regex::Regex;
+use self::sha1::Sha1;
+use self::sha2::{Sha224, Sha256, Sha384, Sha512};
+
+use super::filesystem::{FileSystem, OsFs};
+use super::selection::Selection;
+use super::util::normalize_path;
+
+/// A hashing algorithm supported by arkhash
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Algorithm {
+    Md5,
+    Sha1,
+    Sha224,
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Algorithm {
+    /// Returns the algorithm with the given name, eg "sha1", ignoring case
+    ///
+    /// # Arguments
+    /// * `name` The name of the algorithm
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        match name.to_lowercase().as_ref() {
+            "md5" => Some(Algorithm::Md5),
+            "sha1" => Some(Algorithm::Sha1),
+            "sha224" => Some(Algorithm::Sha224),
+            "sha256" => Some(Algorithm::Sha256),
+            "sha384" => Some(Algorithm::Sha384),
+            "sha512" => Some(Algorithm::Sha512),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the algorithm, which is also the prefix of its _algorithm_sum.txt
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::Md5 => "md5",
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha224 => "sha224",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha384 => "sha384",
+            Algorithm::Sha512 => "sha512",
+        }
+    }
+
+    /// Returns the number of hexadecimal digits of a hashsum
+    pub fn hex_length(self) -> usize {
+        match self {
+            Algorithm::Md5 => 32,
+            Algorithm::Sha1 => 40,
+            Algorithm::Sha224 => 56,
+            Algorithm::Sha256 => 64,
+            Algorithm::Sha384 => 96,
+            Algorithm::Sha512 => 128,
+        }
+    }
+
+    /// Returns the name of the file the hashsums are stored in, eg "sha1sum.txt"
+    pub fn sumfile_name(self) -> String {
+        format!("{}sum.txt", self.name())
+    }
+
+    /// Creates a hasher for the algorithm
+    pub fn hasher(self) -> Box<dyn DynDigest> {
+        match self {
+            Algorithm::Md5 => Box::new(Md5::new()),
+            Algorithm::Sha1 => Box::new(Sha1::new()),
+            Algorithm::Sha224 => Box::new(Sha224::new()),
+            Algorithm::Sha256 => Box::new(Sha256::new()),
+            Algorithm::Sha384 => Box::new(Sha384::new()),
+            Algorithm::Sha512 => Box::new(Sha512::new()),
+        }
+    }
+
+    /// Creates a regex that identifies hashsum and path from a hashsum line
+    pub fn line_regex(self) -> Regex {
+        Regex::new(&format!(
+            r"([[:xdigit:]]{{{}}})\s\s(.*)$",
+            self.hex_length()
+        ))
+        .unwrap()
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// How symbolic links are treated when walking directories and hashing files
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SymlinkPolicy {
+    /// Symbolic links are neither hashed nor followed
+    Skip,
+    /// Symbolic links are hashed and walked like the files and directories they point to
+    Follow,
+    /// The path a symbolic link points to is hashed instead of the contents of the target
+    Record,
+}
+
+impl SymlinkPolicy {
+    /// Returns the policy with the given name, eg "follow", ignoring case
+    ///
+    /// # Arguments
+    /// * `name` The name of the policy
+    pub fn from_name(name: &str) -> Option<SymlinkPolicy> {
+        match name.to_lowercase().as_ref() {
+            "skip" => Some(SymlinkPolicy::Skip),
+            "follow" => Some(SymlinkPolicy::Follow),
+            "record" => Some(SymlinkPolicy::Record),
+            _ => None,
+        }
+    }
+}
+
+/// Everything the engine needs to know to read, write and verify hashsums
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// The hashing algorithm to use
+    pub algorithm: Algorithm,
+    /// The folder to operate on. Subdirectories are mirrored relative to it below `db_dir`.
+    pub root: PathBuf,
+    /// The directory mirroring `root`, that stores the _algorithm_sum.txt files,
+    /// lockfiles and verify state instead of the directories themselves
+    pub db_dir: Option<PathBuf>,
+    /// Maximum number of worker threads to spawn, 0 for one per logical cpu
+    pub num_threads: usize,
+    /// Whether malformed lines in _algorithm_sum.txt files are an error
+    pub strict: bool,
+    /// Whether to wait for locks held by other processes instead of failing
+    pub wait_for_lock: bool,
+    /// The filesystem the files, _algorithm_sum.txt files and lockfiles are accessed through
+    pub filesystem: Arc<dyn FileSystem>,
+    /// Set to stop updating and verifying: queued files are skipped, files being hashed are finished
+    pub cancelled: Arc<AtomicBool>,
+    /// How symbolic links are treated when walking directories and hashing files
+    pub symlinks: SymlinkPolicy,
+    /// Whether directories on other filesystems than the walked directory are skipped, like mounted drives
+    pub one_file_system: bool,
+    /// The size, name and age a file needs to be updated or verified
+    pub selection: Selection,
+    /// Files modified less than this long ago are not hashed by update, because they may still be written
+    pub settle: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            algorithm: Algorithm::Sha1,
+            root: PathBuf::from("."),
+            db_dir: None,
+            num_threads: 0,
+            strict: false,
+            wait_for_lock: false,
+            filesystem: Arc::new(OsFs),
+            cancelled: Arc::new(AtomicBool::new(false)),
+            symlinks: SymlinkPolicy::Skip,
+            one_file_system: false,
+            selection: Selection::default(),
+            settle: None,
+        }
+    }
+}
+
+impl Config {
+    /// Creates a Config with default settings operating on the given folder
+    ///
+    /// # Arguments
+    /// * `root` The folder to operate on
+    pub fn new<P: AsRef<Path>>(root: P) -> Config {
+        Config {
+            root: root.as_ref().to_path_buf(),
+            ..Config::default()
+        }
+    }
+
+    /// Stops every update and verify using this Config or one of its clones
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether `cancel` was called
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of worker threads to spawn
+    pub fn threads(&self) -> usize {
+        match self.num_threads {
+            0 => num_cpus::get(),
+            n => n,
+        }
+    }
+
+    /// Returns the path to the _algorithm_sum.txt file of a directory.
+    ///
+    /// # Arguments
+    /// * `workdir` Path to the directory the hashsums belong to
+    pub fn sumfile_path(&self, workdir: &Path) -> io::Result<PathBuf> {
+        Ok(self
+            .database_dir(workdir)?
+            .join(self.algorithm.sumfile_name()))
+    }
+
+    /// Returns the path to the stat cache of a directory, a hidden file next to its _algorithm_sum.txt
+    /// recording the size and modification time of the listed files.
+    ///
+    /// # Arguments
+    /// * `workdir` Path to the directory the hashsums belong to
+    pub fn stat_cache_path(&self, workdir: &Path) -> io::Result<PathBuf> {
+        Ok(self
+            .database_dir(workdir)?
+            .join(format!(".{}.stat", self.algorithm.sumfile_name())))
+    }
+
+    /// Returns the path to the list of symbolic links of a directory that were hashed by their target path,
+    /// a hidden file next to its _algorithm_sum.txt
+    ///
+    /// # Arguments
+    /// * `workdir` Path to the directory the hashsums belong to
+    pub fn links_path(&self, workdir: &Path) -> io::Result<PathBuf> {
+        Ok(self
+            .database_dir(workdir)?
+            .join(format!(".{}.links", self.algorithm.sumfile_name())))
+    }
+
+    /// Returns the directory that stores the _algorithm_sum.txt file and lockfile of a directory.
+    /// This is the directory itself, or the directory at the same relative position below `db_dir`.
+    ///
+    /// # Arguments
+    /// * `workdir` Path to the directory the hashsums belong to
+    ///
+    /// # Returns
+    /// An error if `db_dir` is given and the directory is not inside the folder to operate on,
+    /// as it has no position below `db_dir` then.
+    pub fn database_dir(&self, workdir: &Path) -> io::Result<PathBuf> {
+        let db_dir = match self.db_dir {
+            None => return Ok(workdir.to_path_buf()),
+            Some(ref db_dir) => db_dir,
+        };
+
+        // the folder may be given as "photos" and its subdirectories as "./photos/2024"
+        let relative = workdir
+            .strip_prefix(&self.root)
+            .ok()
+            .map(Path::to_path_buf)
+            .or_else(|| {
+                let workdir = normalize_path(workdir)?;
+                let root = normalize_path(&self.root)?;
+                workdir.strip_prefix(root).ok().map(Path::to_path_buf)
+            });
+
+        match relative {
+            Some(ref relative) if relative.as_os_str().is_empty() => Ok(db_dir.clone()),
+            Some(relative) => Ok(db_dir.join(relative)),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} is not inside {}, so its hashsums can not be stored below {}",
+                    workdir.display(),
+                    self.root.display(),
+                    db_dir.display()
+                ),
+            )),
+        }
+    }
+
+    /// Returns the path to a file that stores the state of verify runs, like known_good_MONTH_YEAR.txt.
+    /// These files are written to the current directory, or to `db_dir` if given.
+    ///
+    /// # Arguments
+    /// * `filename` The name of the state file
+    pub fn state_file_path(&self, filename: &str) -> PathBuf {
+        match self.db_dir {
+            None => PathBuf::from(filename),
+            Some(ref db_dir) => db_dir.join(filename),
+        }
+    }
+}
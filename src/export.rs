@@ -0,0 +1,294 @@
+//! This module implements the export mode, which converts the _algorithm_sum.txt files
+//! into formats that can be consumed by other tools
+
+extern crate chrono;
+
+use std::env;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+
+use self::chrono::DateTime;
+
+use super::config::Algorithm;
+use super::util::ExportFormat;
+
+/// A single hashsum that is going to be exported
+struct ExportEntry {
+    /// The path to the file, relative to the folder that is exported
+    path: String,
+    /// The size of the file in bytes
+    size: u64,
+    /// The hashsum of the file as listed in _algorithm_sum.txt
+    hash: String,
+}
+
+/// Exports the hashsums of some directories in the format given by the options
+///
+/// # Arguments
+///
+/// * `opts` An Options object containing information about the program behavior
+///
+/// # Returns
+/// The exit code the program should return, 1 if any listed file was skipped
+/// and 3 if a directory is locked by another process.
+pub fn export_directories(opts: super::util::Options) -> i32 {
+    if opts.export_format == ExportFormat::Hashdeep && hashdeep_algorithm(&opts).is_none() {
+        eprintln!(
+            "hashdeep does not support the algorithm {}, use md5, sha1 or sha256",
+            opts.config.algorithm
+        );
+        return 1;
+    }
+
+    let dirs_to_process = if opts.subdir_mode {
+        super::util::gather_sumfile_directories(&opts.config)
+    } else {
+        vec![(opts.config.root.clone(), String::from("."))]
+    };
+
+    let mut entries = Vec::new();
+    let mut skipped = 0;
+    for (workdir, prefix) in dirs_to_process {
+        // no other process may change _algorithm_sum.txt while it is exported
+        let _lock = match super::lock::lock_directory(&workdir, &opts.config, &mut |notice| {
+            if opts.loglevel_info() {
+                let now: DateTime<chrono::Local> = chrono::Local::now();
+                eprintln!("[{}] {}", now, notice);
+            }
+        }) {
+            Ok(lock) => lock,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 3;
+            }
+        };
+
+        let sumfile_path = match opts.config.sumfile_path(&workdir) {
+            Ok(sumfile_path) => sumfile_path,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        };
+        let is_file = opts
+            .config
+            .filesystem
+            .metadata(&sumfile_path)
+            .is_ok_and(|metadata| metadata.is_file);
+        if !is_file {
+            eprintln!("Could not open {}", sumfile_path.to_str().unwrap());
+            return 1;
+        }
+
+        let sumfile_entries = match super::sumfile::read_entries(&sumfile_path, &opts.config) {
+            Ok(sumfile_entries) => sumfile_entries,
+            Err(e) => {
+                eprintln!("{}", e);
+                return 1;
+            }
+        };
+
+        for sumfile_entry in sumfile_entries {
+            let path = join_export_path(&prefix, &sumfile_entry.path);
+
+            match opts
+                .config
+                .filesystem
+                .metadata(&workdir.join(&sumfile_entry.path))
+            {
+                Ok(metadata) => entries.push(ExportEntry {
+                    path,
+                    size: metadata.len,
+                    hash: sumfile_entry.hash,
+                }),
+                Err(e) => {
+                    eprintln!("{}: {}: skipped", e, path);
+                    skipped += 1;
+                }
+            }
+        }
+    }
+
+    let output: Box<dyn Write> = match opts.output {
+        Some(ref output_path) => match fs::File::create(output_path) {
+            Ok(f) => Box::new(f),
+            Err(e) => {
+                eprintln!("Could not create {}: {}", output_path, e);
+                return 1;
+            }
+        },
+        None => Box::new(io::stdout()),
+    };
+    let mut output = BufWriter::new(output);
+
+    let written = match opts.export_format {
+        ExportFormat::Hashdeep => write_hashdeep(&mut output, &entries, &opts),
+        ExportFormat::Json => write_json(&mut output, &entries, &opts),
+        ExportFormat::Csv => write_csv(&mut output, &entries, &opts),
+    };
+
+    if let Err(e) = written.and_then(|_| output.flush()) {
+        eprintln!("Error writing export: {}", e);
+        return 1;
+    }
+
+    // the export is still written, but it is incomplete
+    if skipped > 0 {
+        eprintln!(
+            "{} listed files could not be read and are missing in the export",
+            skipped
+        );
+        return 1;
+    }
+
+    0
+}
+
+/// Joins the path of a file from _algorithm_sum.txt to the prefix of its directory
+///
+/// # Arguments
+/// * `prefix` The path of the directory relative to the exported folder, eg "./2017"
+/// * `path` The path of the file relative to its directory, eg "./a.jpg"
+fn join_export_path(prefix: &str, path: &str) -> String {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    format!("{}/{}", prefix, path)
+}
+
+/// Returns the name hashdeep uses for the algorithm, or None if hashdeep does not support it
+///
+/// # Arguments
+/// * `opts` Options object containing the algorithm
+fn hashdeep_algorithm(opts: &super::util::Options) -> Option<&'static str> {
+    match opts.config.algorithm {
+        Algorithm::Md5 | Algorithm::Sha1 | Algorithm::Sha256 => Some(opts.config.algorithm.name()),
+        _ => None,
+    }
+}
+
+/// Writes the entries in the hashdeep file format, which can be audited with `hashdeep -a -k`
+///
+/// # Arguments
+/// * `output` The Writer the export is written to
+/// * `entries` The entries to export
+/// * `opts` Options object containing the algorithm and program name
+fn write_hashdeep<W: Write>(
+    output: &mut W,
+    entries: &[ExportEntry],
+    opts: &super::util::Options,
+) -> io::Result<()> {
+    let invoked_from = env::current_dir()
+        .map(|dir| dir.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    writeln!(output, "%%%% HASHDEEP-1.0")?;
+    writeln!(
+        output,
+        "%%%% size,{},filename",
+        hashdeep_algorithm(opts).unwrap()
+    )?;
+    writeln!(output, "## Invoked from: {}", invoked_from)?;
+    writeln!(
+        output,
+        "## $ {}",
+        env::args().collect::<Vec<String>>().join(" ")
+    )?;
+    writeln!(output, "##")?;
+
+    for entry in entries {
+        writeln!(output, "{},{},{}", entry.size, entry.hash, entry.path)?;
+    }
+
+    Ok(())
+}
+
+/// Writes the entries as a JSON object keyed by path
+///
+/// # Arguments
+/// * `output` The Writer the export is written to
+/// * `entries` The entries to export
+/// * `opts` Options object containing the algorithm
+fn write_json<W: Write>(
+    output: &mut W,
+    entries: &[ExportEntry],
+    opts: &super::util::Options,
+) -> io::Result<()> {
+    write!(output, "{{")?;
+
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 {
+            write!(output, ",")?;
+        }
+        write!(
+            output,
+            "\n  {}: {{\"size\": {}, \"{}\": \"{}\"}}",
+            escape_json(&entry.path),
+            entry.size,
+            opts.config.algorithm,
+            entry.hash
+        )?;
+    }
+
+    writeln!(output, "\n}}")
+}
+
+/// Writes the entries as CSV with a header line
+///
+/// # Arguments
+/// * `output` The Writer the export is written to
+/// * `entries` The entries to export
+/// * `opts` Options object containing the algorithm
+fn write_csv<W: Write>(
+    output: &mut W,
+    entries: &[ExportEntry],
+    opts: &super::util::Options,
+) -> io::Result<()> {
+    writeln!(output, "path,size,{}", opts.config.algorithm)?;
+
+    for entry in entries {
+        writeln!(
+            output,
+            "{},{},{}",
+            escape_csv(&entry.path),
+            entry.size,
+            entry.hash
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Returns the string as a quoted JSON string literal
+///
+/// # Arguments
+/// * `value` The string to quote
+fn escape_json(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len() + 2);
+    escaped.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped.push('"');
+    escaped
+}
+
+/// Returns the string as a CSV field, quoting it if necessary
+///
+/// # Arguments
+/// * `value` The string to quote
+fn escape_csv(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        String::from(value)
+    }
+}
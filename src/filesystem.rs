@@ -0,0 +1,938 @@
+//! This module describes the filesystem operations the engine needs, so that it can run on the
+//! real filesystem as well as on an in-memory one that simulates corrupted and unreadable files
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// A file that is neither a regular file, a directory nor a symbolic link
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpecialFile {
+    /// A named pipe
+    Fifo,
+    /// A unix domain socket
+    Socket,
+    /// A block device node, eg a disk
+    BlockDevice,
+    /// A character device node, eg a terminal
+    CharDevice,
+}
+
+impl fmt::Display for SpecialFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SpecialFile::Fifo => write!(f, "a FIFO"),
+            SpecialFile::Socket => write!(f, "a socket"),
+            SpecialFile::BlockDevice => write!(f, "a block device"),
+            SpecialFile::CharDevice => write!(f, "a character device"),
+        }
+    }
+}
+
+/// The properties of a file or directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Metadata {
+    /// Whether this is a directory
+    pub is_dir: bool,
+    /// Whether this is a regular file
+    pub is_file: bool,
+    /// Whether this is a symbolic link. Only set for the entries returned by `read_dir`,
+    /// `metadata` follows symbolic links.
+    pub is_symlink: bool,
+    /// The size of the file in bytes
+    pub len: u64,
+    /// The time of the last modification, if known
+    pub modified: Option<SystemTime>,
+    /// The device containing the file, 0 if unknown
+    pub device: u64,
+    /// The number identifying the file on its device, 0 if unknown
+    pub inode: u64,
+    /// The kind of special file, if this is one. Opening a FIFO or device node can block or never end.
+    pub special: Option<SpecialFile>,
+}
+
+impl From<fs::Metadata> for Metadata {
+    #[cfg(unix)]
+    fn from(metadata: fs::Metadata) -> Metadata {
+        use std::os::unix::fs::{FileTypeExt, MetadataExt};
+
+        let file_type = metadata.file_type();
+        let special = if file_type.is_fifo() {
+            Some(SpecialFile::Fifo)
+        } else if file_type.is_socket() {
+            Some(SpecialFile::Socket)
+        } else if file_type.is_block_device() {
+            Some(SpecialFile::BlockDevice)
+        } else if file_type.is_char_device() {
+            Some(SpecialFile::CharDevice)
+        } else {
+            None
+        };
+
+        Metadata {
+            is_dir: metadata.is_dir(),
+            is_file: metadata.is_file(),
+            is_symlink: metadata.file_type().is_symlink(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            device: metadata.dev(),
+            inode: metadata.ino(),
+            special,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn from(metadata: fs::Metadata) -> Metadata {
+        Metadata {
+            is_dir: metadata.is_dir(),
+            is_file: metadata.is_file(),
+            is_symlink: metadata.file_type().is_symlink(),
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            device: 0,
+            inode: 0,
+            special: None,
+        }
+    }
+}
+
+/// An entry of a directory as returned by `read_dir`
+#[derive(Debug)]
+pub struct DirEntry {
+    /// The path to the entry, the path of the directory joined with the name of the entry
+    pub path: PathBuf,
+    /// The properties of the entry, without following symbolic links, or the error reading them failed with,
+    /// eg because the entry was removed while the directory was listed
+    pub metadata: io::Result<Metadata>,
+}
+
+/// The filesystem operations used for listing, hashing and reading and writing _algorithm_sum.txt files and lockfiles
+pub trait FileSystem: fmt::Debug + Send + Sync {
+    /// Returns the entries of a directory. An entry whose properties can not be read is returned
+    /// together with the error, instead of failing the whole listing.
+    ///
+    /// # Arguments
+    /// * `path` Path to the directory
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>>;
+
+    /// Returns the properties of a file or directory, following symbolic links
+    ///
+    /// # Arguments
+    /// * `path` Path to the file or directory
+    fn metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Returns the properties of a file or directory without following a symbolic link at its path
+    ///
+    /// # Arguments
+    /// * `path` Path to the file, directory or symbolic link
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata>;
+
+    /// Returns the path a symbolic link points to
+    ///
+    /// # Arguments
+    /// * `path` Path to the symbolic link
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf>;
+
+    /// Opens a file for reading
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>>;
+
+    /// Reads the whole contents of a file
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut contents = Vec::new();
+        self.open(path)?.read_to_end(&mut contents)?;
+
+        Ok(contents)
+    }
+
+    /// Creates a file with the given contents, failing with `AlreadyExists` if it exists
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    /// * `contents` The contents of the new file
+    fn create_new(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// Appends to a file, creating it if it does not exist, and syncs it to disk
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    /// * `contents` The bytes to append
+    fn append(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// Atomically replaces the contents of a file, creating it if it does not exist
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    /// * `contents` The new contents of the file
+    fn write_atomic(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// Removes a file
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Creates a directory and all of its missing parents
+    ///
+    /// # Arguments
+    /// * `path` Path to the directory
+    fn create_dir_all(&self, path: &Path) -> io::Result<()>;
+}
+
+/// Returns the path of the temporary file used to atomically replace a file
+///
+/// # Arguments
+/// * `path` Path to the file that is going to be replaced
+fn temporary_path(path: &Path) -> PathBuf {
+    let filename = path.file_name().unwrap().to_string_lossy();
+    path.with_file_name(format!(".{}.tmp", filename))
+}
+
+/// Syncs the directory containing a file, so that a rename of the file is persisted
+///
+/// # Arguments
+/// * `path` Path to the file
+#[cfg(unix)]
+fn sync_parent_directory(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if parent.as_os_str().is_empty() => File::open(".")?.sync_all(),
+        Some(parent) => File::open(parent)?.sync_all(),
+        None => Ok(()),
+    }
+}
+
+/// Syncs the directory containing a file, so that a rename of the file is persisted.
+/// Directories can not be synced on windows.
+///
+/// # Arguments
+/// * `path` Path to the file
+#[cfg(windows)]
+fn sync_parent_directory(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// The real filesystem of the operating system
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsFs;
+
+impl FileSystem for OsFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let mut entries = Vec::new();
+
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            entries.push(DirEntry {
+                path: entry.path(),
+                metadata: entry.metadata().map(Metadata::from),
+            });
+        }
+
+        Ok(entries)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        fs::metadata(path).map(Metadata::from)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        fs::symlink_metadata(path).map(Metadata::from)
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::read_link(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(File::open(path)?))
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn create_new(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+
+        if let Err(e) = file.write_all(contents) {
+            let _ = fs::remove_file(path);
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    fn append(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.write_all(contents)?;
+        file.sync_data()
+    }
+
+    fn write_atomic(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        // the contents are written to a temporary file, synced to disk and renamed over the original file,
+        // so that the file contains either the old or the new contents even if the program is interrupted
+        let temporary_path = temporary_path(path);
+
+        let result = File::create(&temporary_path)
+            .and_then(|mut file| {
+                file.write_all(contents)?;
+                file.sync_all()
+            })
+            .and_then(|_| fs::rename(&temporary_path, path));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temporary_path);
+            return result;
+        }
+
+        sync_parent_directory(path)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(path)
+    }
+}
+
+/// A file or directory of a MemoryFs
+#[derive(Debug, Clone)]
+enum MemoryNode {
+    /// A directory, its entries are the nodes whose paths start with its path
+    Directory,
+    /// A regular file
+    File {
+        /// The contents of the file
+        contents: Vec<u8>,
+        /// The time of the last modification
+        modified: SystemTime,
+        /// The error every read of the file fails with, if reading it is simulated to fail
+        read_error: Option<io::ErrorKind>,
+        /// The maximum number of bytes a single read of the file returns, if reads are simulated to be short
+        chunk_size: Option<usize>,
+    },
+    /// A symbolic link
+    Symlink {
+        /// The path the link points to, relative to the directory containing the link
+        target: PathBuf,
+    },
+    /// A FIFO, socket or device node, which can not be read
+    Special(SpecialFile),
+}
+
+/// A filesystem that only exists in memory. Besides the usual operations it can simulate
+/// bit flips, read errors and vanishing files, eg to test how corruption is detected.
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    /// The files and directories, by their normalised paths
+    nodes: Mutex<BTreeMap<PathBuf, MemoryNode>>,
+    /// The nodes whose properties can not be read, by their normalised paths, with the kind of error reading them fails with
+    metadata_errors: Mutex<BTreeMap<PathBuf, io::ErrorKind>>,
+    /// The files that can not be replaced atomically, by their normalised paths, with the kind of error it fails with
+    atomic_write_errors: Mutex<BTreeMap<PathBuf, io::ErrorKind>>,
+}
+
+impl MemoryFs {
+    /// Creates an empty MemoryFs
+    pub fn new() -> MemoryFs {
+        MemoryFs::default()
+    }
+
+    /// Creates or replaces a file, creating its missing parent directories
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    /// * `contents` The contents of the file
+    pub fn write_file<P: AsRef<Path>>(&self, path: P, contents: &[u8]) {
+        let path = node_path(path.as_ref());
+        let mut nodes = self.nodes.lock().unwrap();
+
+        insert_parents(&mut nodes, &path);
+        nodes.insert(
+            path,
+            MemoryNode::File {
+                contents: contents.to_vec(),
+                modified: SystemTime::now(),
+                read_error: None,
+                chunk_size: None,
+            },
+        );
+    }
+
+    /// Creates or replaces a symbolic link, creating its missing parent directories
+    ///
+    /// # Arguments
+    /// * `target` The path the link points to, relative to the directory containing the link
+    /// * `path` Path to the link
+    pub fn symlink<P: AsRef<Path>, Q: AsRef<Path>>(&self, target: P, path: Q) {
+        let path = node_path(path.as_ref());
+        let mut nodes = self.nodes.lock().unwrap();
+
+        insert_parents(&mut nodes, &path);
+        nodes.insert(
+            path,
+            MemoryNode::Symlink {
+                target: target.as_ref().to_path_buf(),
+            },
+        );
+    }
+
+    /// Creates or replaces a special file, creating its missing parent directories.
+    /// Opening it fails, as if it blocked forever.
+    ///
+    /// # Arguments
+    /// * `path` Path to the special file
+    /// * `kind` The kind of special file
+    pub fn special_file<P: AsRef<Path>>(&self, path: P, kind: SpecialFile) {
+        let path = node_path(path.as_ref());
+        let mut nodes = self.nodes.lock().unwrap();
+
+        insert_parents(&mut nodes, &path);
+        nodes.insert(path, MemoryNode::Special(kind));
+    }
+
+    /// Inverts a single bit of a file without changing its modification time, like bit rot would
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    /// * `offset` The position of the byte in the file
+    /// * `bit` The position of the bit in the byte, 0 to 7
+    pub fn flip_bit<P: AsRef<Path>>(&self, path: P, offset: usize, bit: u8) -> io::Result<()> {
+        self.with_file(path.as_ref(), |contents| match contents.get_mut(offset) {
+            Some(byte) => {
+                *byte ^= 1 << (bit % 8);
+                Ok(())
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "offset lies behind the end of the file",
+            )),
+        })
+    }
+
+    /// Changes the modification time of a file, like `touch -d` would
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    /// * `time` The new modification time
+    pub fn set_modified<P: AsRef<Path>>(&self, path: P, time: SystemTime) -> io::Result<()> {
+        let path = node_path(path.as_ref());
+        let mut nodes = self.nodes.lock().unwrap();
+
+        match nodes.get_mut(&path) {
+            Some(MemoryNode::File { modified, .. }) => {
+                *modified = time;
+                Ok(())
+            }
+            _ => Err(not_found(&path)),
+        }
+    }
+
+    /// Makes every following read of a file fail, like a damaged sector would
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    /// * `kind` The kind of error the reads fail with
+    pub fn fail_reads<P: AsRef<Path>>(&self, path: P, kind: io::ErrorKind) -> io::Result<()> {
+        let path = node_path(path.as_ref());
+        let mut nodes = self.nodes.lock().unwrap();
+
+        match nodes.get_mut(&path) {
+            Some(MemoryNode::File { read_error, .. }) => {
+                *read_error = Some(kind);
+                Ok(())
+            }
+            _ => Err(not_found(&path)),
+        }
+    }
+
+    /// Makes every following read of a file return at most a few bytes, like reads from a network filesystem may
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    /// * `chunk_size` The maximum number of bytes a single read returns
+    pub fn short_reads<P: AsRef<Path>>(&self, path: P, chunk_size: usize) -> io::Result<()> {
+        let path = node_path(path.as_ref());
+        let mut nodes = self.nodes.lock().unwrap();
+
+        match nodes.get_mut(&path) {
+            Some(MemoryNode::File {
+                chunk_size: chunk, ..
+            }) => {
+                *chunk = Some(chunk_size);
+                Ok(())
+            }
+            _ => Err(not_found(&path)),
+        }
+    }
+
+    /// Makes every following attempt to read the properties of a file or directory fail,
+    /// like for a file that is removed while its directory is listed
+    ///
+    /// # Arguments
+    /// * `path` Path to the file or directory
+    /// * `kind` The kind of error reading the properties fails with
+    pub fn fail_metadata<P: AsRef<Path>>(&self, path: P, kind: io::ErrorKind) -> io::Result<()> {
+        let path = node_path(path.as_ref());
+        if !self.nodes.lock().unwrap().contains_key(&path) {
+            return Err(not_found(&path));
+        }
+
+        self.metadata_errors.lock().unwrap().insert(path, kind);
+        Ok(())
+    }
+
+    /// Makes every following atomic replacement of a file fail, while appending to it still works,
+    /// like in a directory where no temporary file can be created
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    /// * `kind` The kind of error replacing the file fails with
+    pub fn fail_atomic_writes<P: AsRef<Path>>(&self, path: P, kind: io::ErrorKind) {
+        let path = node_path(path.as_ref());
+        self.atomic_write_errors.lock().unwrap().insert(path, kind);
+    }
+
+    /// Returns the properties of a node, unless reading them is simulated to fail
+    ///
+    /// # Arguments
+    /// * `path` The key of the node
+    /// * `node` The file, directory or symbolic link
+    fn node_metadata(&self, path: &Path, node: &MemoryNode) -> io::Result<Metadata> {
+        match self.metadata_errors.lock().unwrap().get(path) {
+            Some(kind) => Err(io::Error::new(
+                *kind,
+                format!("simulated metadata error: {}", path.display()),
+            )),
+            None => Ok(node_metadata(path, node)),
+        }
+    }
+
+    /// Calls a function with the contents of a file to modify them
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    /// * `modify` Gets called with the contents of the file
+    fn with_file<F>(&self, path: &Path, modify: F) -> io::Result<()>
+    where
+        F: FnOnce(&mut Vec<u8>) -> io::Result<()>,
+    {
+        let path = node_path(path);
+        let mut nodes = self.nodes.lock().unwrap();
+
+        match nodes.get_mut(&path) {
+            Some(MemoryNode::File { contents, .. }) => modify(contents),
+            _ => Err(not_found(&path)),
+        }
+    }
+}
+
+impl FileSystem for MemoryFs {
+    fn read_dir(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let nodes = self.nodes.lock().unwrap();
+        let directory = resolve(&nodes, path)?;
+
+        match nodes.get(&directory) {
+            Some(MemoryNode::Directory) => {}
+            Some(_) => {
+                return Err(io::Error::other(format!(
+                    "not a directory: {}",
+                    path.display()
+                )))
+            }
+            None if directory.as_os_str().is_empty() => {}
+            None => return Err(not_found(path)),
+        }
+
+        let mut entries = Vec::new();
+        for (node_path, node) in nodes.iter() {
+            if node_path.parent() == Some(directory.as_path()) && node_path != &directory {
+                entries.push(DirEntry {
+                    path: path.join(node_path.file_name().unwrap()),
+                    metadata: self.node_metadata(node_path, node),
+                });
+            }
+        }
+
+        Ok(entries)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let nodes = self.nodes.lock().unwrap();
+        let node = resolve(&nodes, path)?;
+
+        match nodes.get(&node) {
+            Some(metadata) => self.node_metadata(&node, metadata),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<Metadata> {
+        let nodes = self.nodes.lock().unwrap();
+        let node = node_path(path);
+        let parent = resolve(&nodes, node.parent().unwrap_or_else(|| Path::new("")))?;
+        let node = match node.file_name() {
+            Some(name) => parent.join(name),
+            None => node,
+        };
+
+        match nodes.get(&node) {
+            Some(metadata) => self.node_metadata(&node, metadata),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn read_link(&self, path: &Path) -> io::Result<PathBuf> {
+        let nodes = self.nodes.lock().unwrap();
+
+        match nodes.get(&node_path(path)) {
+            Some(MemoryNode::Symlink { target }) => Ok(target.clone()),
+            Some(_) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("not a symbolic link: {}", path.display()),
+            )),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + Send>> {
+        let nodes = self.nodes.lock().unwrap();
+
+        match nodes.get(&resolve(&nodes, path)?) {
+            Some(MemoryNode::File {
+                read_error: Some(kind),
+                ..
+            }) => Ok(Box::new(FailingReader { kind: *kind })),
+            Some(MemoryNode::File {
+                contents,
+                chunk_size: Some(chunk_size),
+                ..
+            }) => Ok(Box::new(ShortReader {
+                contents: Cursor::new(contents.clone()),
+                chunk_size: *chunk_size,
+            })),
+            Some(MemoryNode::File { contents, .. }) => Ok(Box::new(Cursor::new(contents.clone()))),
+            Some(MemoryNode::Directory) => Err(is_a_directory(path)),
+            Some(MemoryNode::Special(kind)) => Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!("{} is {}", path.display(), kind),
+            )),
+            Some(MemoryNode::Symlink { .. }) | None => Err(not_found(path)),
+        }
+    }
+
+    fn create_new(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let node = node_path(path);
+        let mut nodes = self.nodes.lock().unwrap();
+
+        if nodes.contains_key(&node) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("file exists: {}", path.display()),
+            ));
+        }
+        check_parent(&nodes, &node)?;
+
+        nodes.insert(
+            node,
+            MemoryNode::File {
+                contents: contents.to_vec(),
+                modified: SystemTime::now(),
+                read_error: None,
+                chunk_size: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn append(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let node = node_path(path);
+        let mut nodes = self.nodes.lock().unwrap();
+
+        if !nodes.contains_key(&node) {
+            check_parent(&nodes, &node)?;
+        }
+
+        match nodes.entry(node).or_insert_with(|| MemoryNode::File {
+            contents: Vec::new(),
+            modified: SystemTime::now(),
+            read_error: None,
+            chunk_size: None,
+        }) {
+            MemoryNode::File {
+                contents: existing,
+                modified,
+                ..
+            } => {
+                existing.extend_from_slice(contents);
+                *modified = SystemTime::now();
+                Ok(())
+            }
+            MemoryNode::Directory => Err(is_a_directory(path)),
+            MemoryNode::Symlink { .. } | MemoryNode::Special(_) => Err(io::Error::other(format!(
+                "not a regular file: {}",
+                path.display()
+            ))),
+        }
+    }
+
+    fn write_atomic(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let node = node_path(path);
+        if let Some(kind) = self.atomic_write_errors.lock().unwrap().get(&node) {
+            return Err(io::Error::new(
+                *kind,
+                format!("simulated write error: {}", path.display()),
+            ));
+        }
+        let mut nodes = self.nodes.lock().unwrap();
+
+        check_parent(&nodes, &node)?;
+        if let Some(MemoryNode::Directory) = nodes.get(&node) {
+            return Err(is_a_directory(path));
+        }
+
+        nodes.insert(
+            node,
+            MemoryNode::File {
+                contents: contents.to_vec(),
+                modified: SystemTime::now(),
+                read_error: None,
+                chunk_size: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let node = node_path(path);
+        let mut nodes = self.nodes.lock().unwrap();
+
+        match nodes.get(&node) {
+            Some(MemoryNode::File { .. })
+            | Some(MemoryNode::Symlink { .. })
+            | Some(MemoryNode::Special(_)) => {
+                nodes.remove(&node);
+                Ok(())
+            }
+            Some(MemoryNode::Directory) => Err(is_a_directory(path)),
+            None => Err(not_found(path)),
+        }
+    }
+
+    fn create_dir_all(&self, path: &Path) -> io::Result<()> {
+        let node = node_path(path);
+        let mut nodes = self.nodes.lock().unwrap();
+
+        if let Some(MemoryNode::File { .. })
+        | Some(MemoryNode::Symlink { .. })
+        | Some(MemoryNode::Special(_)) = nodes.get(&node)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("file exists: {}", path.display()),
+            ));
+        }
+
+        insert_parents(&mut nodes, &node);
+        if !node.as_os_str().is_empty() {
+            nodes.insert(node, MemoryNode::Directory);
+        }
+
+        Ok(())
+    }
+}
+
+/// A Reader that fails every read, returned for files of a MemoryFs whose reads are simulated to fail
+struct FailingReader {
+    /// The kind of error every read fails with
+    kind: io::ErrorKind,
+}
+
+impl Read for FailingReader {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::Error::new(self.kind, "simulated read error"))
+    }
+}
+
+/// A Reader returning at most a few bytes per read,
+/// returned for files of a MemoryFs whose reads are simulated to be short
+struct ShortReader {
+    /// The contents of the file
+    contents: Cursor<Vec<u8>>,
+    /// The maximum number of bytes a single read returns
+    chunk_size: usize,
+}
+
+impl Read for ShortReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf.len().min(self.chunk_size);
+        self.contents.read(&mut buf[..len])
+    }
+}
+
+/// Returns the key of a path in a MemoryFs, eg "./a/../b.jpg" results in "b.jpg"
+///
+/// # Arguments
+/// * `path` The path to the file or directory
+fn node_path(path: &Path) -> PathBuf {
+    super::util::normalize_path(path).unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Returns the key of the node a path refers to in a MemoryFs, following every symbolic link in it
+///
+/// # Arguments
+/// * `nodes` The nodes of the MemoryFs
+/// * `path` The path to resolve
+fn resolve(nodes: &BTreeMap<PathBuf, MemoryNode>, path: &Path) -> io::Result<PathBuf> {
+    // the same limit as the one of Linux
+    const MAX_SYMLINKS: usize = 40;
+    let mut resolved = node_path(path);
+
+    for _ in 0..MAX_SYMLINKS {
+        let mut link = None;
+        for ancestor in resolved
+            .ancestors()
+            .collect::<Vec<&Path>>()
+            .into_iter()
+            .rev()
+        {
+            if let Some(MemoryNode::Symlink { target }) = nodes.get(ancestor) {
+                link = Some((ancestor.to_path_buf(), target.clone()));
+                break;
+            }
+        }
+
+        match link {
+            None => return Ok(resolved),
+            Some((link, target)) => {
+                let rest = resolved.strip_prefix(&link).unwrap().to_path_buf();
+                let parent = link.parent().unwrap_or_else(|| Path::new(""));
+                resolved = node_path(&parent.join(target).join(rest));
+            }
+        }
+    }
+
+    Err(io::Error::other(format!(
+        "too many levels of symbolic links: {}",
+        path.display()
+    )))
+}
+
+/// Returns the properties of a node of a MemoryFs
+///
+/// # Arguments
+/// * `path` The key of the node, its hash serves as inode
+/// * `node` The file, directory or symbolic link
+fn node_metadata(path: &Path, node: &MemoryNode) -> Metadata {
+    let mut hasher = DefaultHasher::new();
+    path.hash(&mut hasher);
+    let inode = hasher.finish();
+
+    match node {
+        MemoryNode::Directory => Metadata {
+            is_dir: true,
+            is_file: false,
+            is_symlink: false,
+            len: 0,
+            modified: None,
+            device: 0,
+            inode,
+            special: None,
+        },
+        MemoryNode::File {
+            contents, modified, ..
+        } => Metadata {
+            is_dir: false,
+            is_file: true,
+            is_symlink: false,
+            len: contents.len() as u64,
+            modified: Some(*modified),
+            device: 0,
+            inode,
+            special: None,
+        },
+        MemoryNode::Symlink { target } => Metadata {
+            is_dir: false,
+            is_file: false,
+            is_symlink: true,
+            len: target.as_os_str().len() as u64,
+            modified: None,
+            device: 0,
+            inode,
+            special: None,
+        },
+        MemoryNode::Special(kind) => Metadata {
+            is_dir: false,
+            is_file: false,
+            is_symlink: false,
+            len: 0,
+            modified: None,
+            device: 0,
+            inode,
+            special: Some(*kind),
+        },
+    }
+}
+
+/// Inserts every missing parent directory of a path into the nodes of a MemoryFs
+///
+/// # Arguments
+/// * `nodes` The nodes of the MemoryFs
+/// * `path` The normalised path whose parents are created
+fn insert_parents(nodes: &mut BTreeMap<PathBuf, MemoryNode>, path: &Path) {
+    for parent in path.ancestors().skip(1) {
+        if !parent.as_os_str().is_empty() {
+            nodes
+                .entry(parent.to_path_buf())
+                .or_insert(MemoryNode::Directory);
+        }
+    }
+}
+
+/// Returns an error if the parent directory of a path does not exist in a MemoryFs
+///
+/// # Arguments
+/// * `nodes` The nodes of the MemoryFs
+/// * `path` The normalised path whose parent is checked
+fn check_parent(nodes: &BTreeMap<PathBuf, MemoryNode>, path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => match nodes.get(parent) {
+            Some(MemoryNode::Directory) => Ok(()),
+            _ => Err(not_found(parent)),
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Returns the error for a missing file or directory
+///
+/// # Arguments
+/// * `path` The path that does not exist
+fn not_found(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("no such file or directory: {}", path.display()),
+    )
+}
+
+/// Returns the error for a directory that is accessed like a file
+///
+/// # Arguments
+/// * `path` The path to the directory
+fn is_a_directory(path: &Path) -> io::Error {
+    io::Error::other(format!("is a directory: {}", path.display()))
+}
@@ -1,118 +1,441 @@
-//! This module implements a filter for a BufReader that filters out filenames
+//! This module implements a filter for a BufRead that filters out filenames
 //! that have already been hashed at some point. It does this via reading the _algorithm_sum.txt file.
 
-extern crate regex;
+extern crate chrono;
 
 use std::collections::HashMap;
-use std::fs::OpenOptions;
-use std::io::{BufRead, BufReader, Read};
-
-/// The structure that gets wrapped around a BufReader to filter it
-pub struct Filter<T> {
-    /// The filenames that were already hashed in the past, gathered through reading _algorithm_sum.txt
-    already_calculated_files: HashMap<String, bool>,
-    /// The BufReader that will be read and filtered
-    input: BufReader<T>,
-    /// The algorithm that was used to hash the files eg "sha1"
-    algorithm: String,
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use self::chrono::DateTime;
+
+use super::config::{Algorithm, Config};
+use super::filesystem::FileSystem;
+use super::ignore::IgnoreTree;
+use super::util::FilterOutput;
+
+/// The status of a path read by the Filter
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FileStatus {
+    /// The file is not listed in _algorithm_sum.txt
+    New,
+    /// The file is listed in _algorithm_sum.txt
+    Hashed,
+    /// The file is listed in _algorithm_sum.txt, but was modified after _algorithm_sum.txt was last written
+    Modified,
+}
+
+impl fmt::Display for FileStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileStatus::New => write!(f, "new"),
+            FileStatus::Hashed => write!(f, "hashed"),
+            FileStatus::Modified => write!(f, "modified"),
+        }
+    }
 }
 
-impl<T> Filter<T> {
+/// The structure that gets wrapped around a BufRead to filter it
+pub struct Filter<R> {
+    /// The filenames that were already hashed in the past, gathered through reading _algorithm_sum.txt,
+    /// together with the time _algorithm_sum.txt was last modified.
+    /// The paths are normalised relative to the directory of _algorithm_sum.txt, see `path_key`.
+    already_calculated_files: HashMap<String, Option<SystemTime>>,
+    /// The patterns of the .arkignore files, matching paths are never returned
+    ignore: IgnoreTree,
+    /// The directory the paths in the input are relative to
+    directory: PathBuf,
+    /// Absolute forms of the directory of _algorithm_sum.txt, used to make absolute input paths relative
+    base_directories: Vec<PathBuf>,
+    /// The BufRead that will be read and filtered
+    input: R,
+    /// The algorithm that was used to hash the files eg sha1
+    algorithm: Algorithm,
+    /// The filesystem containing the files and _algorithm_sum.txt
+    filesystem: Arc<dyn FileSystem>,
+    /// The byte that separates the paths in the input
+    delimiter: u8,
+    /// Which paths are returned and whether they are annotated with their status
+    output: FilterOutput,
+    /// Whether the lock of a directory is taken while its _algorithm_sum.txt is read
+    lock: bool,
+    /// The records of the input that were skipped because they are not valid UTF-8,
+    /// with the invalid bytes replaced
+    invalid_records: Vec<String>,
+    /// The error reading the input failed with, after which no more paths are returned
+    read_error: Option<io::Error>,
+}
+
+impl<R: BufRead> Filter<R> {
     /// Creates a new instance of Filter
     ///
     /// # Arguments
     ///
-    /// * `input` The BufReader that will be filtered through this object
-    /// * `sumfile_path` The path to the _algorithm_sum.txt file that contains the already calculated hashsums
-    /// * `opts` A reference to the Options object containing information about the program behavior
+    /// * `input` The BufRead that will be filtered through this object
+    /// * `directory` The directory whose _algorithm_sum.txt contains the already calculated hashsums
+    /// * `config` A reference to the Config object containing the algorithm
+    ///
+    /// # Errors
+    ///
+    /// If the _algorithm_sum.txt file is locked by another process, can not be read
+    /// or contains malformed lines in strict mode, an Err will be returned instead of a Filter.
+    pub fn new(input: R, directory: &Path, config: &Config) -> Result<Self, String> {
+        let mut filter = Filter::empty(input, directory, config);
+        filter.load_sumfile(directory, Path::new(""), config)?;
+
+        Ok(filter)
+    }
+
+    /// Creates a new instance of Filter without taking the lock of the directory while reading
+    /// its _algorithm_sum.txt, for callers that already hold the lock or must not create a lockfile
+    ///
+    /// # Arguments
+    ///
+    /// * `input` The BufRead that will be filtered through this object
+    /// * `directory` The directory whose _algorithm_sum.txt contains the already calculated hashsums
+    /// * `config` A reference to the Config object containing the algorithm
     ///
     /// # Errors
     ///
-    /// If the _algorithm_sum.txt file can not be read or the algorithm can not be recognized,
+    /// If the _algorithm_sum.txt file can not be read or contains malformed lines in strict mode,
     /// an Err will be returned instead of a Filter.
-    pub fn new(
-        input: BufReader<T>,
-        sumfile_path: &str,
-        opts: &super::util::Options,
-    ) -> Result<Self, &'static str> {
-        let mut already_calculated_files = HashMap::new();
-
-        match OpenOptions::new()
-            .read(true)
-            .append(true)
-            .create(true)
-            .open(format!("{}/{}sum.txt", sumfile_path, opts.algorithm))
-        {
-            Err(_) => Err("Could not open _algorithm_sum.txt"),
-
-            Ok(file) => {
-                let file_path_re = super::util::regex_from_opts(&opts)?;
-
-                for line in BufReader::new(file).lines() {
-                    if let Ok(line) = line {
-                        if let Some(captures) = file_path_re.captures(&line) {
-                            let path = &captures[2];
-                            already_calculated_files.insert(path.to_string(), true);
-                        } else {
-                            continue;
-                        }
-                    } else {
-                        continue;
-                    }
+    pub fn new_unlocked(input: R, directory: &Path, config: &Config) -> Result<Self, String> {
+        let mut filter = Filter::empty(input, directory, config);
+        filter.lock = false;
+        filter.load_sumfile(directory, Path::new(""), config)?;
+
+        Ok(filter)
+    }
+
+    /// Creates a new instance of Filter that knows the hashsums of every subdirectory, like in subdir mode.
+    /// The paths in the input are relative to the folder containing the subdirectories.
+    ///
+    /// # Arguments
+    ///
+    /// * `input` The BufRead that will be filtered through this object
+    /// * `config` A reference to the Config object containing the folder whose subdirectories
+    ///   contain _algorithm_sum.txt files
+    ///
+    /// # Errors
+    ///
+    /// If one of the _algorithm_sum.txt files is locked by another process, can not be read
+    /// or contains malformed lines in strict mode, an Err will be returned instead of a Filter.
+    pub fn new_subdir(input: R, config: &Config) -> Result<Self, String> {
+        let mut filter = Filter::empty(input, &config.root, config);
+
+        for (directory, relative) in super::util::gather_sumfile_directories(config) {
+            if let Some(key) = path_key(Path::new(&relative)) {
+                if !filter.ignore.is_ignored(&directory, true) {
+                    filter.load_sumfile(&directory, Path::new(&key), config)?;
                 }
+            }
+        }
+
+        Ok(filter)
+    }
+
+    /// Creates a new instance of Filter that does not know any hashsums yet
+    ///
+    /// # Arguments
+    ///
+    /// * `input` The BufRead that will be filtered through this object
+    /// * `directory` The directory the paths in the input are relative to
+    /// * `config` A reference to the Config object containing the algorithm
+    fn empty(input: R, directory: &Path, config: &Config) -> Self {
+        Filter {
+            already_calculated_files: HashMap::new(),
+            ignore: IgnoreTree::new(Arc::clone(&config.filesystem), &config.root),
+            directory: directory.to_path_buf(),
+            base_directories: base_directories(directory),
+            input,
+            algorithm: config.algorithm,
+            filesystem: Arc::clone(&config.filesystem),
+            delimiter: b'\n',
+            output: FilterOutput::New,
+            lock: true,
+            invalid_records: Vec::new(),
+            read_error: None,
+        }
+    }
+
+    /// Reads the _algorithm_sum.txt of a directory and remembers the paths listed in it.
+    /// Unless created by `new_unlocked`, the lock of the directory is held while reading,
+    /// so that no update or accept of another process changes the file in the meantime.
+    ///
+    /// # Arguments
+    ///
+    /// * `directory` The directory whose _algorithm_sum.txt is read
+    /// * `prefix` The path of the directory relative to the directory of the Filter
+    /// * `config` A reference to the Config object containing the algorithm
+    fn load_sumfile(
+        &mut self,
+        directory: &Path,
+        prefix: &Path,
+        config: &Config,
+    ) -> Result<(), String> {
+        let _lock = if self.lock {
+            let lock = super::lock::lock_directory(directory, config, &mut |notice| {
+                let now: DateTime<chrono::Local> = chrono::Local::now();
+                eprintln!("[{}] {}", now, notice);
+            })
+            .map_err(|e| e.to_string())?;
+            Some(lock)
+        } else {
+            None
+        };
 
-                Ok(Filter {
-                    already_calculated_files,
-                    input,
-                    algorithm: opts.algorithm.clone(),
-                })
+        let sumfile = config.sumfile_path(directory).map_err(|e| e.to_string())?;
+        let entries = super::sumfile::read_entries(&sumfile, config).map_err(|e| e.to_string())?;
+        let sumfile_modified = config
+            .filesystem
+            .metadata(&sumfile)
+            .ok()
+            .and_then(|metadata| metadata.modified);
+
+        for entry in entries {
+            if let Some(key) = path_key(&prefix.join(&entry.path)) {
+                self.already_calculated_files.insert(key, sumfile_modified);
             }
         }
+
+        Ok(())
     }
 
-    /// Returns whether the given line contains the path to the database file
+    /// Sets which paths are returned: only new paths (the default), only hashed paths,
+    /// or every path prefixed with its status
     ///
     /// # Arguments
     ///
-    /// * `line` The String to be checked
-    /// * `algorithm` The used algorithm
-    #[cfg(unix)]
-    fn is_own_database_file(line: &str, algorithm: &str) -> bool {
-        line == format!("./{}sum.txt", algorithm)
+    /// * `output` The kind of output
+    pub fn set_output(&mut self, output: FilterOutput) {
+        self.output = output;
+    }
+
+    /// Sets the byte that separates the paths in the input, eg b'\0' for the output of `find -print0`
+    ///
+    /// # Arguments
+    ///
+    /// * `delimiter` The byte that terminates every path
+    pub fn set_delimiter(&mut self, delimiter: u8) {
+        self.delimiter = delimiter;
+    }
+
+    /// Returns the records of the input read so far that were skipped because they are not valid UTF-8,
+    /// with the invalid bytes replaced by U+FFFD
+    pub fn invalid_records(&self) -> &[String] {
+        &self.invalid_records
+    }
+
+    /// Returns the error reading the input failed with, if it could not be read to its end
+    pub fn read_error(&self) -> Option<&io::Error> {
+        self.read_error.as_ref()
+    }
+
+    /// Reads the next path from the input, without its delimiter.
+    /// Paths that are not valid UTF-8 are skipped and remembered in `invalid_records`.
+    ///
+    /// # Returns
+    ///
+    /// None if the input is exhausted or can not be read,
+    /// in which case the error is remembered in `read_error`.
+    fn read_record(&mut self) -> Option<String> {
+        loop {
+            let mut record = Vec::new();
+            match self.input.read_until(self.delimiter, &mut record) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(e) => {
+                    self.read_error = Some(e);
+                    return None;
+                }
+            }
+
+            if record.last() == Some(&self.delimiter) {
+                record.pop();
+            }
+            if self.delimiter == b'\n' && record.last() == Some(&b'\r') {
+                record.pop();
+            }
+
+            match String::from_utf8(record) {
+                Ok(record) => return Some(record),
+                Err(e) => self
+                    .invalid_records
+                    .push(String::from_utf8_lossy(e.as_bytes()).to_string()),
+            }
+        }
+    }
+
+    /// Returns the normalised path of an input line relative to the directory of _algorithm_sum.txt.
+    /// Relative paths are interpreted relative to that directory, absolute paths have to lie inside of it.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` The path as read from the input
+    ///
+    /// # Returns
+    ///
+    /// None if the path points outside of the directory of _algorithm_sum.txt.
+    fn relative_key(&self, line: &str) -> Option<String> {
+        let path = Path::new(line);
+        if !path.is_absolute() {
+            return path_key(path);
+        }
+
+        let path = super::util::normalize_path(path)?;
+        self.base_directories
+            .iter()
+            .find_map(|base| path.strip_prefix(base).ok())
+            .and_then(path_key)
+    }
+
+    /// Returns the status of an input line
+    ///
+    /// # Arguments
+    ///
+    /// * `line` The path as read from the input
+    /// * `key` The normalised path relative to the directory of _algorithm_sum.txt, if it lies inside of it
+    fn status(&self, line: &str, key: Option<&String>) -> FileStatus {
+        let sumfile_modified = match key.and_then(|key| self.already_calculated_files.get(key)) {
+            None => return FileStatus::New,
+            Some(sumfile_modified) => sumfile_modified,
+        };
+
+        // checking the modification time is only necessary if it is printed
+        if self.output != FilterOutput::Annotate {
+            return FileStatus::Hashed;
+        }
+
+        let path = Path::new(line);
+        let path = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.directory.join(path)
+        };
+        let file_modified = self
+            .filesystem
+            .metadata(&path)
+            .ok()
+            .and_then(|metadata| metadata.modified);
+
+        match (file_modified, sumfile_modified) {
+            (Some(file_modified), Some(sumfile_modified)) if file_modified > *sumfile_modified => {
+                FileStatus::Modified
+            }
+            _ => FileStatus::Hashed,
+        }
+    }
+
+    /// Returns whether the given normalised path is matched by the .arkignore files of its parent directories
+    ///
+    /// # Arguments
+    ///
+    /// * `key` The normalised path to be checked
+    fn is_ignored(&mut self, key: &str) -> bool {
+        // the input consists of files, directories are only checked as their parents
+        self.ignore.is_ignored(&self.directory.join(key), false)
     }
 
-    /// Returns whether the given line contains the path to the database file
+    /// Returns whether the given normalised path is the path to a database file,
+    /// either directly in the directory of the Filter or in one of its subdirectories
     ///
     /// # Arguments
     ///
-    /// * `line` The String to be checked
+    /// * `key` The normalised path to be checked
     /// * `algorithm` The used algorithm
-    #[cfg(windows)]
-    fn is_own_database_file(line: &str, algorithm: &str) -> bool {
-        line == format!(".\\{}sum.txt", algorithm)
+    fn is_own_database_file(key: &str, algorithm: Algorithm) -> bool {
+        let filename = algorithm.sumfile_name();
+        key == filename
+            || (key.ends_with(&format!("/{}", filename)) && key.matches('/').count() == 1)
+    }
+}
+
+/// Returns a normalised form of a relative path, that is equal for every spelling of the same path,
+/// eg "a.jpg", "./a.jpg" and "./sub/..//a.jpg" all result in "a.jpg".
+///
+/// # Arguments
+///
+/// * `path` The relative path to normalise
+///
+/// # Returns
+///
+/// None if the path leaves the directory it is relative to.
+fn path_key(path: &Path) -> Option<String> {
+    let normalized = super::util::normalize_path(path)?;
+    let components: Vec<String> = normalized
+        .iter()
+        .map(|component| component.to_string_lossy().to_string())
+        .collect();
+
+    Some(components.join("/"))
+}
+
+/// Returns the absolute paths a directory can be referred to by: relative to the current directory
+/// as reported by the operating system and by $PWD, which keeps symlinks, as well as the canonical path.
+///
+/// # Arguments
+///
+/// * `directory` The directory containing _algorithm_sum.txt
+pub fn base_directories(directory: &Path) -> Vec<PathBuf> {
+    let mut candidates = Vec::new();
+
+    if directory.is_absolute() {
+        candidates.push(directory.to_path_buf());
+    } else {
+        if let Ok(current_dir) = env::current_dir() {
+            candidates.push(current_dir.join(directory));
+        }
+        if let Some(pwd) = env::var_os("PWD") {
+            candidates.push(Path::new(&pwd).join(directory));
+        }
+    }
+    if let Ok(canonical) = fs::canonicalize(directory) {
+        candidates.push(canonical);
+    }
+
+    let mut base_directories = Vec::new();
+    for candidate in candidates {
+        if let Some(candidate) = super::util::normalize_path(&candidate) {
+            if !base_directories.contains(&candidate) {
+                base_directories.push(candidate);
+            }
+        }
     }
+
+    base_directories
 }
 
-impl<T: Read> Iterator for Filter<T> {
+impl<R: BufRead> Iterator for Filter<R> {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
-        for line in self.input.by_ref().lines() {
-            match line {
-                Err(_) => continue,
-                Ok(line) => {
-                    let contained = self.already_calculated_files.contains_key(&line);
-                    if contained {
-                        continue;
-                    }
-
-                    if Filter::<T>::is_own_database_file(&line, &self.algorithm) {
-                        continue;
-                    }
-
-                    return Some(line);
+        while let Some(line) = self.read_record() {
+            let key = self.relative_key(&line);
+
+            if let Some(ref key) = key {
+                if Filter::<R>::is_own_database_file(key, self.algorithm) || self.is_ignored(key) {
+                    continue;
                 }
             }
+
+            if let Some(filename) = Path::new(&line).file_name() {
+                if super::util::is_internal_file(&filename.to_string_lossy()) {
+                    continue;
+                }
+            }
+
+            let status = self.status(&line, key.as_ref());
+            match self.output {
+                FilterOutput::New if status == FileStatus::New => return Some(line),
+                FilterOutput::Hashed if status != FileStatus::New => return Some(line),
+                FilterOutput::Annotate => return Some(format!("{}\t{}", status, line)),
+                _ => continue,
+            }
         }
 
         None
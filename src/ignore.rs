@@ -0,0 +1,410 @@
+//! This module implements the .arkignore files, which exclude files and directories from
+//! updating, verifying and filtering. The patterns follow the rules of .gitignore files:
+//! globs, `**`, negation with `!`, directory patterns ending with `/` and anchored patterns.
+//! Like .gitignore, every directory can contain an .arkignore applying to its own subtree.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use super::filesystem::FileSystem;
+
+/// The name of the file containing the patterns
+pub const IGNORE_FILE_NAME: &str = ".arkignore";
+
+/// A single pattern of an .arkignore file
+#[derive(Debug, Clone)]
+pub struct IgnoreRule {
+    /// The .arkignore file containing the pattern
+    pub source: PathBuf,
+    /// The number of the line containing the pattern, starting at 1
+    pub line_number: usize,
+    /// The pattern as it is written in the file
+    pub pattern: String,
+    /// The normalised directory containing the .arkignore file, the pattern is relative to it
+    base: PathBuf,
+    /// The glob of every path component, `**` matches any number of components
+    segments: Vec<String>,
+    /// Whether a matching path is included again instead of ignored
+    negated: bool,
+    /// Whether the pattern only matches directories, because it ended with a `/`
+    directory_only: bool,
+}
+
+impl IgnoreRule {
+    /// Parses a line of an .arkignore file
+    ///
+    /// # Arguments
+    /// * `directory` The directory containing the .arkignore file
+    /// * `line_number` The number of the line, starting at 1
+    /// * `line` The line to parse
+    ///
+    /// # Returns
+    /// None if the line is blank or a comment.
+    fn parse(directory: &Path, line_number: usize, line: &str) -> Option<IgnoreRule> {
+        let pattern = trim_trailing_spaces(line.trim_end_matches('\r'));
+        if pattern.is_empty() || pattern.starts_with('#') {
+            return None;
+        }
+
+        let mut line = pattern;
+        let negated = line.starts_with('!');
+        if negated || line.starts_with("\\!") || line.starts_with("\\#") {
+            line = &line[1..];
+        }
+
+        let directory_only = line.ends_with('/');
+        let line = line.trim_end_matches('/');
+
+        // "./name" was the way to list a directory before patterns were supported
+        let explicit_current_dir = line.starts_with("./");
+        let line = line.trim_start_matches("./");
+
+        // a pattern without a slash matches at any depth, otherwise it is relative to the .arkignore
+        let anchored = explicit_current_dir || line.contains('/');
+        let mut segments: Vec<String> = line
+            .split('/')
+            .filter(|segment| !segment.is_empty() && *segment != ".")
+            .map(String::from)
+            .collect();
+        if segments.is_empty() {
+            return None;
+        }
+        if !anchored {
+            segments.insert(0, String::from("**"));
+        }
+
+        Some(IgnoreRule {
+            source: directory.join(IGNORE_FILE_NAME),
+            line_number,
+            pattern: pattern.to_string(),
+            base: super::util::normalize_path(directory).unwrap_or_else(|| directory.to_path_buf()),
+            segments,
+            negated,
+            directory_only,
+        })
+    }
+
+    /// Returns whether the pattern matches a path, regardless of negation
+    ///
+    /// # Arguments
+    /// * `path` The normalised path to check
+    /// * `is_dir` Whether the path is a directory
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.directory_only && !is_dir {
+            return false;
+        }
+
+        match path.strip_prefix(&self.base) {
+            Ok(relative) if relative.components().next().is_some() => {
+                let components: Vec<String> = relative
+                    .iter()
+                    .map(|component| component.to_string_lossy().to_string())
+                    .collect();
+                match_segments(&self.segments, &components)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for IgnoreRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}",
+            self.source.display(),
+            self.line_number,
+            self.pattern
+        )
+    }
+}
+
+/// The patterns of one or more .arkignore files. Every pattern is relative to the directory of its file,
+/// patterns of deeper directories are added later and take precedence like in nested .gitignore files.
+#[derive(Debug, Clone, Default)]
+pub struct IgnoreRules {
+    /// The patterns in the order they are listed, later patterns take precedence
+    rules: Vec<IgnoreRule>,
+}
+
+impl IgnoreRules {
+    /// Parses the contents of an .arkignore file
+    ///
+    /// # Arguments
+    /// * `directory` The directory containing the .arkignore file, the patterns are relative to it
+    /// * `contents` The contents of the .arkignore file
+    pub fn parse(directory: &Path, contents: &str) -> IgnoreRules {
+        IgnoreRules {
+            rules: contents
+                .lines()
+                .enumerate()
+                .filter_map(|(i, line)| IgnoreRule::parse(directory, i + 1, line))
+                .collect(),
+        }
+    }
+
+    /// Reads the .arkignore file of a directory. A missing file results in rules ignoring nothing.
+    ///
+    /// # Arguments
+    /// * `filesystem` The filesystem containing the directory
+    /// * `directory` The directory containing the .arkignore file
+    pub fn read(filesystem: &dyn FileSystem, directory: &Path) -> IgnoreRules {
+        match filesystem.read(&directory.join(IGNORE_FILE_NAME)) {
+            Ok(contents) => IgnoreRules::parse(directory, &String::from_utf8_lossy(&contents)),
+            Err(_) => IgnoreRules::default(),
+        }
+    }
+
+    /// Reads the .arkignore files of a directory and every directory between it and the root
+    ///
+    /// # Arguments
+    /// * `filesystem` The filesystem containing the directories
+    /// * `root` The topmost directory whose .arkignore is read
+    /// * `directory` The deepest directory whose .arkignore is read, inside of root
+    pub fn read_nested(filesystem: &dyn FileSystem, root: &Path, directory: &Path) -> IgnoreRules {
+        let mut rules = IgnoreRules::read(filesystem, root);
+
+        if let Ok(relative) = directory.strip_prefix(root) {
+            let mut current = root.to_path_buf();
+            for component in relative.components() {
+                current.push(component);
+                rules.extend(IgnoreRules::read(filesystem, &current));
+            }
+        }
+
+        rules
+    }
+
+    /// Adds the patterns of a deeper .arkignore file, which take precedence over the existing ones
+    ///
+    /// # Arguments
+    /// * `nested` The patterns to add
+    pub fn extend(&mut self, nested: IgnoreRules) {
+        self.rules.extend(nested.rules);
+    }
+
+    /// Returns whether there are no patterns at all
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns whether a path is ignored, either by itself or because one of its parent directories is.
+    /// Paths outside of the directories of the .arkignore files are never ignored.
+    ///
+    /// # Arguments
+    /// * `path` The path to check, in the same form as the directories of the .arkignore files,
+    ///   eg "./sub/a.jpg" for "."
+    /// * `is_dir` Whether the path is a directory
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.ignored_by(path, is_dir).is_some()
+    }
+
+    /// Returns the pattern that ignores a path, either by itself or because one of its parent directories is
+    ///
+    /// # Arguments
+    /// * `path` The path to check, in the same form as the directories of the .arkignore files
+    /// * `is_dir` Whether the path is a directory
+    pub fn ignored_by(&self, path: &Path, is_dir: bool) -> Option<&IgnoreRule> {
+        if self.rules.is_empty() {
+            return None;
+        }
+        let path = super::util::normalize_path(path)?;
+
+        // like in git, a file inside an ignored directory can not be included again
+        let mut parents: Vec<&Path> = path.ancestors().skip(1).collect();
+        parents.reverse();
+        for parent in parents {
+            if let Some(rule) = self.last_match(parent, true) {
+                return Some(rule);
+            }
+        }
+
+        self.last_match(&path, is_dir)
+    }
+
+    /// Returns the last pattern matching a path, if it ignores the path
+    ///
+    /// # Arguments
+    /// * `path` The normalised path to check
+    /// * `is_dir` Whether the path is a directory
+    fn last_match(&self, path: &Path, is_dir: bool) -> Option<&IgnoreRule> {
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .filter(|rule| !rule.negated)
+    }
+}
+
+/// The .arkignore files of a directory tree, read once per directory when paths inside of it are checked
+pub struct IgnoreTree {
+    /// The topmost directory whose .arkignore is read
+    root: PathBuf,
+    /// The filesystem containing the directories
+    filesystem: Arc<dyn FileSystem>,
+    /// The patterns applying to every normalised directory checked so far
+    directories: HashMap<PathBuf, IgnoreRules>,
+}
+
+impl IgnoreTree {
+    /// Creates an IgnoreTree that has not read any .arkignore file yet
+    ///
+    /// # Arguments
+    /// * `filesystem` The filesystem containing the directories
+    /// * `root` The topmost directory whose .arkignore is read
+    pub fn new(filesystem: Arc<dyn FileSystem>, root: &Path) -> IgnoreTree {
+        IgnoreTree {
+            root: super::util::normalize_path(root).unwrap_or_else(|| root.to_path_buf()),
+            filesystem,
+            directories: HashMap::new(),
+        }
+    }
+
+    /// Returns whether a path is ignored by the .arkignore files of its parent directories
+    ///
+    /// # Arguments
+    /// * `path` The path to check, in the same form as the root
+    /// * `is_dir` Whether the path is a directory
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        self.ignored_by(path, is_dir).is_some()
+    }
+
+    /// Returns the pattern of the .arkignore files of its parent directories that ignores a path
+    ///
+    /// # Arguments
+    /// * `path` The path to check, in the same form as the root
+    /// * `is_dir` Whether the path is a directory
+    pub fn ignored_by(&mut self, path: &Path, is_dir: bool) -> Option<&IgnoreRule> {
+        let path = super::util::normalize_path(path)?;
+        let parent = path.parent()?;
+
+        self.rules(parent).ignored_by(&path, is_dir)
+    }
+
+    /// Returns the patterns applying to the contents of a directory, reading the .arkignore files
+    /// of the directory and its parents if they have not been read yet
+    ///
+    /// # Arguments
+    /// * `directory` The normalised directory
+    fn rules(&mut self, directory: &Path) -> &IgnoreRules {
+        if !self.directories.contains_key(directory) {
+            let rules = match directory.parent() {
+                Some(parent) if directory != self.root && directory.starts_with(&self.root) => {
+                    let mut rules = self.rules(parent).clone();
+                    rules.extend(IgnoreRules::read(&*self.filesystem, directory));
+                    rules
+                }
+                _ => IgnoreRules::read(&*self.filesystem, &self.root),
+            };
+            self.directories.insert(directory.to_path_buf(), rules);
+        }
+
+        &self.directories[directory]
+    }
+}
+
+/// Removes trailing spaces, unless they are escaped with a backslash
+///
+/// # Arguments
+/// * `line` The line to trim
+fn trim_trailing_spaces(line: &str) -> &str {
+    let mut end = line.len();
+    while line[..end].ends_with(' ') && !line[..end - 1].ends_with('\\') {
+        end -= 1;
+    }
+
+    &line[..end]
+}
+
+/// Returns whether the components of a path match the segments of a pattern
+///
+/// # Arguments
+/// * `segments` The globs of the pattern, `**` matches any number of components
+/// * `components` The components of the path
+fn match_segments(segments: &[String], components: &[String]) -> bool {
+    match segments.first() {
+        None => components.is_empty(),
+        Some(segment) if segment == "**" => {
+            (0..=components.len()).any(|i| match_segments(&segments[1..], &components[i..]))
+        }
+        Some(segment) => {
+            !components.is_empty()
+                && glob_matches(segment, &components[0])
+                && match_segments(&segments[1..], &components[1..])
+        }
+    }
+}
+
+/// Returns whether a file name matches a glob supporting `*`, `?`, `[...]` and backslash escapes
+///
+/// # Arguments
+/// * `glob` The glob, eg "*.cr2"
+/// * `name` The name of a single path component
+pub fn glob_matches(glob: &str, name: &str) -> bool {
+    glob_match(
+        &glob.chars().collect::<Vec<char>>(),
+        &name.chars().collect::<Vec<char>>(),
+    )
+}
+
+/// Returns whether a name matches a glob supporting `*`, `?`, `[...]` and backslash escapes
+///
+/// # Arguments
+/// * `pattern` The glob
+/// * `name` The name of a single path component
+fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| glob_match(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && glob_match(&pattern[1..], &name[1..]),
+        Some('[') => match match_class(&pattern[1..], name.first()) {
+            Some((matched, rest)) => matched && glob_match(rest, &name[1..]),
+            // an unclosed bracket is an ordinary character
+            None => name.first() == Some(&'[') && glob_match(&pattern[1..], &name[1..]),
+        },
+        Some('\\') if pattern.len() > 1 => {
+            name.first() == Some(&pattern[1]) && glob_match(&pattern[2..], &name[1..])
+        }
+        Some(c) => name.first() == Some(c) && glob_match(&pattern[1..], &name[1..]),
+    }
+}
+
+/// Matches a character against a character class like `[a-z]` or `[!0-9]`
+///
+/// # Arguments
+/// * `class` The pattern following the opening bracket
+/// * `c` The character to match
+///
+/// # Returns
+/// Whether the character matched and the rest of the pattern after the closing bracket,
+/// None if the class is not closed.
+fn match_class<'a>(class: &'a [char], c: Option<&char>) -> Option<(bool, &'a [char])> {
+    let negated = class.first() == Some(&'!') || class.first() == Some(&'^');
+    let mut i = if negated { 1 } else { 0 };
+    let start = i;
+    let mut matched = false;
+
+    loop {
+        let mut low = *class.get(i)?;
+        if low == ']' && i > start {
+            break;
+        }
+        if low == '\\' {
+            i += 1;
+            low = *class.get(i)?;
+        }
+
+        if class.get(i + 1) == Some(&'-') && class.get(i + 2).is_some_and(|&high| high != ']') {
+            let high = class[i + 2];
+            matched |= c.is_some_and(|&c| low <= c && c <= high);
+            i += 3;
+        } else {
+            matched |= c == Some(&low);
+            i += 1;
+        }
+    }
+
+    Some((c.is_some() && matched != negated, &class[i + 1..]))
+}
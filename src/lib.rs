@@ -0,0 +1,56 @@
+//! arkhash keeps the hashsums of the files in an archive in _algorithm_sum.txt files
+//! and uses them to detect files that changed unexpectedly.
+//!
+//! The building blocks are configured by a `Config` and return their results instead of printing them:
+//! * `Manifest` reads and writes _algorithm_sum.txt files
+//! * `Filter` filters paths that have already been hashed out of any `BufRead`
+//! * `Updater` hashes new files of a directory and appends them to its _algorithm_sum.txt
+//! * `Verifier` compares the files of a directory to its _algorithm_sum.txt
+//! * `Reporter` receives the events that occur while updating or verifying a directory
+//! * `FileSystem` is the filesystem all of them operate on, `MemoryFs` simulates one in memory
+//!
+//! The `arkhash` binary is a thin command line wrapper around them, its modes are not part of the API.
+
+mod accept;
+mod config;
+mod export;
+mod filesystem;
+mod filter;
+mod ignore;
+mod links;
+mod lint;
+mod lock;
+mod progress;
+mod report;
+mod selection;
+mod statcache;
+mod sumfile;
+mod update;
+mod util;
+mod verify;
+
+pub use config::{Algorithm, Config, SymlinkPolicy};
+pub use filesystem::{DirEntry, FileSystem, MemoryFs, Metadata, OsFs, SpecialFile};
+pub use filter::{FileStatus, Filter};
+pub use ignore::{IgnoreRule, IgnoreRules};
+pub use links::RecordedLinks;
+pub use lock::LockError;
+pub use report::{Event, MultiReporter, NullReporter, Reporter};
+pub use selection::Selection;
+pub use statcache::{StatCache, StatChange};
+pub use sumfile::{Manifest, SumfileDiagnostic, SumfileEntry};
+pub use update::{RehashReport, UpdatePlan, UpdateReport, Updater};
+pub use util::{DirectoryError, FilterOutput, HashError, HashOutput, SkipReason};
+pub use verify::{Verifier, VerifyReport};
+
+/// The option parsing and the modes of the `arkhash` binary, which print their results
+/// and return the exit code of the program. Not part of the library API.
+#[doc(hidden)]
+pub mod cli {
+    pub use accept::{accept_directories, accept_directory};
+    pub use export::export_directories;
+    pub use lint::{lint_directories, lint_directory};
+    pub use update::{rehash_directories, update_directories};
+    pub use util::{Mode, Options};
+    pub use verify::{to_check_path, to_check_report_path, verify_directories};
+}
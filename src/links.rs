@@ -0,0 +1,82 @@
+//! This module implements the list of recorded symbolic links, a hidden file next to every _algorithm_sum.txt.
+//! With SymlinkPolicy::Record a symbolic link is hashed by the path it points to. The line in _algorithm_sum.txt
+//! looks like any other one, so the list remembers which lines belong to links and where they pointed to,
+//! and verify, rehash and accept hash those files the same way again, no matter which policy they are run with.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use super::config::SymlinkPolicy;
+use super::filesystem::FileSystem;
+
+/// The symbolic links listed in an _algorithm_sum.txt that were hashed by the path they point to
+#[derive(Debug, Clone, Default)]
+pub struct RecordedLinks {
+    /// The path each link pointed to when it was hashed, by the path of the link as listed
+    pub targets: BTreeMap<String, String>,
+}
+
+impl RecordedLinks {
+    /// Reads a list of recorded links. A missing file is treated as an empty one.
+    ///
+    /// # Arguments
+    /// * `filesystem` The filesystem containing the list
+    /// * `path` Path to the list
+    pub fn read(filesystem: &dyn FileSystem, path: &Path) -> io::Result<RecordedLinks> {
+        let contents = match filesystem.read(path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(RecordedLinks::default())
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut links = RecordedLinks::default();
+        for line in String::from_utf8_lossy(&contents).lines() {
+            // the path of the link comes last, like in _algorithm_sum.txt
+            let mut fields = line.splitn(2, '\t');
+            if let (Some(target), Some(link)) = (fields.next(), fields.next()) {
+                links.targets.insert(link.to_string(), target.to_string());
+            }
+        }
+
+        Ok(links)
+    }
+
+    /// Atomically replaces a list of recorded links with the links of this RecordedLinks
+    ///
+    /// # Arguments
+    /// * `filesystem` The filesystem containing the list
+    /// * `path` Path to the list
+    pub fn write(&self, filesystem: &dyn FileSystem, path: &Path) -> io::Result<()> {
+        let mut contents = String::new();
+        for (link, target) in self.targets.iter() {
+            contents.push_str(&format!("{}\t{}\n", target, link));
+        }
+
+        filesystem.write_atomic(path, contents.as_bytes())
+    }
+
+    /// Returns how a listed file has to be hashed: recorded links by the path they point to,
+    /// every other file by its contents, even if it was replaced by a link since it was hashed
+    ///
+    /// # Arguments
+    /// * `path` The path of the file as listed in _algorithm_sum.txt
+    pub fn policy(&self, path: &str) -> SymlinkPolicy {
+        if self.targets.contains_key(path) {
+            SymlinkPolicy::Record
+        } else {
+            SymlinkPolicy::Follow
+        }
+    }
+}
+
+/// Returns whether the given filename belongs to a list of recorded links or its temporary file
+///
+/// # Arguments
+/// * `filename` The name of the file without any directories
+pub fn is_links_file(filename: &str) -> bool {
+    filename.starts_with('.')
+        && (filename.ends_with("sum.txt.links") || filename.ends_with("sum.txt.links.tmp"))
+}
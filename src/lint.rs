@@ -0,0 +1,150 @@
+//! This module implements the lint mode, which checks the _algorithm_sum.txt files
+//! for problems without hashing any files
+
+extern crate chrono;
+
+use std::path::Path;
+
+use self::chrono::DateTime;
+
+use super::config::SymlinkPolicy;
+use super::filesystem::FileSystem;
+
+/// Checks the _algorithm_sum.txt files of some directories and prints every problem found in them.
+/// Reports unparseable, blank, duplicate and truncated lines, as well as listed files that do not exist.
+///
+/// # Arguments
+///
+/// * `opts` An Options object containing information about the program behavior
+///
+/// # Returns
+/// The exit code the program should return.
+pub fn lint_directories(opts: super::util::Options) -> i32 {
+    let dirs_to_process = if opts.subdir_mode {
+        super::util::gather_sumfile_directories(&opts.config)
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect()
+    } else {
+        vec![opts.config.root.clone()]
+    };
+
+    let mut exit_code = 0;
+
+    for workdir in dirs_to_process {
+        let problems = lint_directory(&workdir, &opts);
+
+        if problems != 0 {
+            exit_code = 1;
+        }
+
+        if opts.loglevel_info() {
+            let now: DateTime<chrono::Local> = chrono::Local::now();
+            if problems == 0 {
+                println!("[{}] {}: lint: OK", now, workdir.to_str().unwrap());
+            } else {
+                println!(
+                    "[{}] {}: lint: {} problems",
+                    now,
+                    workdir.to_str().unwrap(),
+                    problems
+                );
+            }
+        }
+    }
+
+    exit_code
+}
+
+/// Checks the _algorithm_sum.txt of a directory and prints every problem found in it
+///
+/// # Arguments
+/// * `workdir` Path to the directory containing the _algorithm_sum.txt
+/// * `opts` Options object containing the algorithm
+///
+/// # Returns
+/// The number of problems found.
+pub fn lint_directory(workdir: &Path, opts: &super::util::Options) -> usize {
+    let sumfile_path = match opts.config.sumfile_path(workdir) {
+        Ok(sumfile_path) => sumfile_path,
+        Err(e) => {
+            println!("{}", e);
+            return 1;
+        }
+    };
+    let sumfile_name = sumfile_path.to_str().unwrap();
+
+    // no other process may change _algorithm_sum.txt while it is checked
+    let _lock = match super::lock::lock_directory(workdir, &opts.config, &mut |notice| {
+        if opts.loglevel_info() {
+            let now: DateTime<chrono::Local> = chrono::Local::now();
+            println!("[{}] {}", now, notice);
+        }
+    }) {
+        Ok(lock) => lock,
+        Err(e) => {
+            println!("{}", e);
+            return 1;
+        }
+    };
+
+    let filesystem = &*opts.config.filesystem;
+    if !is_file(filesystem, &sumfile_path) {
+        println!("{}: file does not exist", sumfile_name);
+        return 1;
+    }
+
+    let manifest =
+        match super::sumfile::Manifest::read(filesystem, &sumfile_path, opts.config.algorithm) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                println!("{}: {}", sumfile_name, e);
+                return 1;
+            }
+        };
+
+    for diagnostic in manifest.diagnostics.iter() {
+        println!("{}: {}", sumfile_name, diagnostic);
+    }
+
+    let mut missing = 0;
+    for entry in manifest.entries {
+        if !is_listed_file(filesystem, &workdir.join(&entry.path), opts.config.symlinks) {
+            println!(
+                "{}: line {}: listed file does not exist: {}",
+                sumfile_name, entry.line_number, entry.path
+            );
+            missing += 1;
+        }
+    }
+
+    manifest.diagnostics.len() + missing
+}
+
+/// Returns whether a path exists and is a regular file, following symbolic links
+///
+/// # Arguments
+/// * `filesystem` The filesystem containing the path
+/// * `path` The path to check
+fn is_file(filesystem: &dyn FileSystem, path: &Path) -> bool {
+    filesystem
+        .metadata(path)
+        .is_ok_and(|metadata| metadata.is_file)
+}
+
+/// Returns whether a file listed in an _algorithm_sum.txt exists.
+/// When symbolic links are recorded, a listed link is not followed and may point to a missing target.
+///
+/// # Arguments
+/// * `filesystem` The filesystem containing the path
+/// * `path` The path to check
+/// * `symlinks` How symbolic links are treated
+fn is_listed_file(filesystem: &dyn FileSystem, path: &Path, symlinks: SymlinkPolicy) -> bool {
+    if symlinks != SymlinkPolicy::Record {
+        return is_file(filesystem, path);
+    }
+
+    filesystem
+        .symlink_metadata(path)
+        .is_ok_and(|metadata| metadata.is_file || metadata.is_symlink)
+}
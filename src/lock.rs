@@ -0,0 +1,373 @@
+//! This module implements advisory lockfiles that prevent multiple instances of the program
+//! from operating on the same _algorithm_sum.txt at the same time
+
+#[cfg(unix)]
+extern crate libc;
+
+use std::env;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::config::Config;
+use super::filesystem::FileSystem;
+
+/// The name of the lockfile that is held for a whole run in the folder to operate on
+pub const GLOBAL_LOCK_NAME: &str = ".arkhash.lock";
+
+/// A lock that is held as long as this object lives. The lockfile gets removed when it is dropped.
+#[derive(Debug)]
+pub struct Lock {
+    /// Path to the lockfile
+    path: PathBuf,
+    /// The filesystem containing the lockfile
+    filesystem: Arc<dyn FileSystem>,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = self.filesystem.remove_file(&self.path);
+    }
+}
+
+/// The process that holds a lock, as written into the lockfile
+#[derive(Debug, PartialEq, Clone)]
+pub struct LockHolder {
+    /// The process id of the holder
+    pub pid: u32,
+    /// The hostname of the machine the holder runs on
+    pub hostname: String,
+}
+
+impl LockHolder {
+    /// Returns a LockHolder describing this process
+    fn current() -> LockHolder {
+        LockHolder {
+            pid: process::id(),
+            hostname: hostname(),
+        }
+    }
+
+    /// Parses the contents of a lockfile
+    ///
+    /// # Arguments
+    /// * `contents` The contents of the lockfile, "PID HOSTNAME"
+    fn parse(contents: &str) -> Option<LockHolder> {
+        let mut parts = contents.trim().splitn(2, ' ');
+        let pid = parts.next()?.parse().ok()?;
+        let hostname = parts.next()?.to_string();
+
+        Some(LockHolder { pid, hostname })
+    }
+
+    /// Returns whether the holder of the lock has terminated without removing the lockfile.
+    /// A lock held by a process on another host is never considered stale.
+    fn is_stale(&self) -> bool {
+        self.hostname == hostname() && !process_alive(self.pid)
+    }
+}
+
+impl fmt::Display for LockHolder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "process {} on host {}", self.pid, self.hostname)
+    }
+}
+
+/// An error that occurs when a lock can not be acquired
+#[derive(Debug)]
+pub enum LockError {
+    /// The lock is held by another process
+    Held {
+        path: PathBuf,
+        holder: Option<LockHolder>,
+    },
+    /// The lockfile could not be created
+    Io { path: PathBuf, source: io::Error },
+    /// Waiting for the lock was stopped, see `Config::cancel`
+    Cancelled { path: PathBuf },
+}
+
+impl fmt::Display for LockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockError::Held {
+                path,
+                holder: Some(holder),
+            } => write!(
+                f,
+                "{} is locked by {}, use --wait to wait for it",
+                path.to_str().unwrap(),
+                holder
+            ),
+            LockError::Held { path, holder: None } => write!(
+                f,
+                "{} is locked by another process, use --wait to wait for it",
+                path.to_str().unwrap()
+            ),
+            LockError::Io { path, source } => {
+                write!(
+                    f,
+                    "could not create lockfile {}: {}",
+                    path.to_str().unwrap(),
+                    source
+                )
+            }
+            LockError::Cancelled { path } => {
+                write!(f, "stopped waiting for {}", path.to_str().unwrap())
+            }
+        }
+    }
+}
+
+/// Something that happened while acquiring a lock, which might be worth telling the user
+#[derive(Debug)]
+pub enum LockNotice {
+    /// The lockfile of a terminated process is removed
+    RemovingStale { path: PathBuf, holder: LockHolder },
+    /// The lock is held by another process and will be waited for
+    Waiting {
+        path: PathBuf,
+        holder: Option<LockHolder>,
+    },
+}
+
+impl fmt::Display for LockNotice {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockNotice::RemovingStale { path, holder } => write!(
+                f,
+                "Removing stale lock {} of {}",
+                path.to_str().unwrap(),
+                holder
+            ),
+            LockNotice::Waiting {
+                path,
+                holder: Some(holder),
+            } => write!(
+                f,
+                "Waiting for lock {} held by {}",
+                path.to_str().unwrap(),
+                holder
+            ),
+            LockNotice::Waiting { path, holder: None } => {
+                write!(f, "Waiting for lock {}", path.to_str().unwrap())
+            }
+        }
+    }
+}
+
+/// Returns the path to the lockfile protecting the _algorithm_sum.txt of a directory
+///
+/// # Arguments
+/// * `workdir` Path to the directory containing the _algorithm_sum.txt
+/// * `config` Config object that contains the desired algorithm
+pub fn directory_lock_path(workdir: &Path, config: &Config) -> io::Result<PathBuf> {
+    Ok(config
+        .database_dir(workdir)?
+        .join(format!(".{}.lock", config.algorithm.sumfile_name())))
+}
+
+/// Returns whether the given filename belongs to a lockfile created by this program
+///
+/// # Arguments
+/// * `filename` The name of the file without any directories
+pub fn is_lockfile(filename: &str) -> bool {
+    filename == GLOBAL_LOCK_NAME
+        || (filename.starts_with('.') && filename.ends_with("sum.txt.lock"))
+}
+
+/// Acquires the lock for a whole run in the folder to operate on, or in the database directory if given
+///
+/// # Arguments
+/// * `config` Config object containing the folder and whether to wait for the lock
+/// * `on_notice` Gets called when a stale lock is removed or the lock is waited for
+pub fn lock_run(config: &Config, on_notice: &mut dyn FnMut(LockNotice)) -> Result<Lock, LockError> {
+    let directory = config
+        .database_dir(&config.root)
+        .map_err(|source| LockError::Io {
+            path: config.root.clone(),
+            source,
+        })?;
+    create_database_dir(&directory, config)?;
+
+    acquire(&directory.join(GLOBAL_LOCK_NAME), config, on_notice)
+}
+
+/// Acquires the lock protecting the _algorithm_sum.txt of a directory
+///
+/// # Arguments
+/// * `workdir` Path to the directory containing the _algorithm_sum.txt
+/// * `config` Config object containing the algorithm and whether to wait for the lock
+/// * `on_notice` Gets called when a stale lock is removed or the lock is waited for
+pub fn lock_directory(
+    workdir: &Path,
+    config: &Config,
+    on_notice: &mut dyn FnMut(LockNotice),
+) -> Result<Lock, LockError> {
+    let lock_error = |source| LockError::Io {
+        path: workdir.to_path_buf(),
+        source,
+    };
+    create_database_dir(&config.database_dir(workdir).map_err(lock_error)?, config)?;
+
+    acquire(
+        &directory_lock_path(workdir, config).map_err(lock_error)?,
+        config,
+        on_notice,
+    )
+}
+
+/// Creates a directory below the database directory, so the lockfile can be created in it.
+/// Does nothing without a database directory, the folder to operate on is never written to.
+///
+/// # Arguments
+/// * `directory` Path to the directory below the database directory
+/// * `config` Config object containing the database directory
+fn create_database_dir(directory: &Path, config: &Config) -> Result<(), LockError> {
+    if config.db_dir.is_none() {
+        return Ok(());
+    }
+
+    config
+        .filesystem
+        .create_dir_all(directory)
+        .map_err(|source| LockError::Io {
+            path: directory.to_path_buf(),
+            source,
+        })
+}
+
+/// Creates a lockfile at the given path.
+/// Stale lockfiles of terminated processes on this host are removed.
+/// If the lock is held by another process, this either fails or waits until the lock is released.
+///
+/// # Arguments
+/// * `path` Path to the lockfile
+/// * `config` Config object determining whether to wait
+/// * `on_notice` Gets called when a stale lock is removed or the lock is waited for
+pub fn acquire(
+    path: &Path,
+    config: &Config,
+    on_notice: &mut dyn FnMut(LockNotice),
+) -> Result<Lock, LockError> {
+    let filesystem = &config.filesystem;
+    let holder = LockHolder::current();
+    let lockfile_contents = format!("{} {}\n", holder.pid, holder.hostname);
+    let mut announced = false;
+
+    loop {
+        match filesystem.create_new(path, lockfile_contents.as_bytes()) {
+            Ok(()) => {
+                return Ok(Lock {
+                    path: path.to_path_buf(),
+                    filesystem: Arc::clone(filesystem),
+                });
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                let contents = read_lockfile(&**filesystem, path);
+                let holder = contents.as_ref().and_then(|c| LockHolder::parse(c));
+
+                if let Some(ref holder) = holder {
+                    if holder.is_stale() {
+                        on_notice(LockNotice::RemovingStale {
+                            path: path.to_path_buf(),
+                            holder: holder.clone(),
+                        });
+
+                        // only remove the lockfile if nobody replaced it in the meantime
+                        if read_lockfile(&**filesystem, path) == contents {
+                            let _ = filesystem.remove_file(path);
+                        }
+                        continue;
+                    }
+                }
+
+                if !config.wait_for_lock {
+                    return Err(LockError::Held {
+                        path: path.to_path_buf(),
+                        holder,
+                    });
+                }
+
+                if !announced {
+                    on_notice(LockNotice::Waiting {
+                        path: path.to_path_buf(),
+                        holder,
+                    });
+                    announced = true;
+                }
+
+                if config.is_cancelled() {
+                    return Err(LockError::Cancelled {
+                        path: path.to_path_buf(),
+                    });
+                }
+
+                thread::sleep(Duration::from_secs(1));
+            }
+            Err(source) => {
+                return Err(LockError::Io {
+                    path: path.to_path_buf(),
+                    source,
+                })
+            }
+        }
+    }
+}
+
+/// Reads the contents of a lockfile, returns None if it can not be read
+///
+/// # Arguments
+/// * `filesystem` The filesystem containing the lockfile
+/// * `path` Path to the lockfile
+fn read_lockfile(filesystem: &dyn FileSystem, path: &Path) -> Option<String> {
+    let contents = filesystem.read(path).ok()?;
+
+    String::from_utf8(contents).ok()
+}
+
+/// Returns the hostname of this machine
+#[cfg(unix)]
+fn hostname() -> String {
+    let mut buffer = [0u8; 256];
+    let result =
+        unsafe { libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) };
+
+    if result != 0 {
+        return env::var("HOSTNAME").unwrap_or_else(|_| String::from("localhost"));
+    }
+
+    let length = buffer.iter().position(|&b| b == 0).unwrap_or(buffer.len());
+    String::from_utf8_lossy(&buffer[..length]).to_string()
+}
+
+/// Returns the hostname of this machine
+#[cfg(windows)]
+fn hostname() -> String {
+    env::var("COMPUTERNAME").unwrap_or_else(|_| String::from("localhost"))
+}
+
+/// Returns whether a process with the given pid is running on this host
+///
+/// # Arguments
+/// * `pid` The process id to look for
+#[cfg(unix)]
+fn process_alive(pid: u32) -> bool {
+    let result = unsafe { libc::kill(pid as libc::pid_t, 0) };
+
+    result == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Returns whether a process with the given pid is running on this host.
+/// Processes can not be queried on windows, so every lock is assumed to be alive.
+///
+/// # Arguments
+/// * `pid` The process id to look for
+#[cfg(windows)]
+fn process_alive(_pid: u32) -> bool {
+    true
+}
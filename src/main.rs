@@ -1,10 +1,10 @@
-pub mod filter;
-pub mod update;
-pub mod util;
-pub mod verify;
+extern crate arkhash;
+
+use arkhash::cli;
+use arkhash::Filter;
 
 fn main() {
-    let opts = util::Options::new(std::env::args().collect());
+    let opts = cli::Options::new(std::env::args().collect());
 
     if opts.help {
         const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -16,13 +16,36 @@ Usage:
 Arguments:
  -a, --algo, --algorithm ALGORITHM      uses ALGORITHM to hash files (example: md5, default: sha1)
                                         (available: sha1, md5, sha224, sha256, sha384, sha512)
- -s, --subdir, --subdirectories         operate on the subdirectories of DIRECTORY (only for update and verify mode)
+ -s, --subdir, --subdirectories         operate on the subdirectories of DIRECTORY
  --loglevel LEVEL                       controls the output of the program (quiet/info/progress/debug)
-                                        progress currently only supported for verify mode
  --quiet                                sets the loglevel to quiet
  -T, --threads THREADS                  spawn a maximum of THREADS worker threads (default: 0: no cap)
+ --hashed                               print only paths that have been hashed before in filter mode
+ --annotate                             print every path with its status (new/hashed/modified) in filter mode
+ -0, --null                             read and write NUL-separated paths in filter and rehash mode (find -print0, xargs -0)
+ --db-dir DIRECTORY                     store hashsum files, lockfiles and verify state below DIRECTORY instead of the archive
+ --wait                                 wait for other instances working on the same directories instead of failing
+ --symlinks POLICY                      skip, follow or record (hash the target path of) symbolic links (default: skip)
+ -x, --one-file-system                  skip directories on other filesystems, like mounted drives
+ --min-size SIZE                        only update and verify files of at least SIZE bytes, eg 10M (K/M/G/T)
+ --max-size SIZE                        only update and verify files of at most SIZE bytes
+ --include GLOB                         only update and verify files whose name matches GLOB, eg '*.cr2' (repeatable)
+ --exclude GLOB                         do not update and verify files whose name matches GLOB, eg '*.tmp' (repeatable)
+ --newer-than AGE                       only update and verify files modified less than AGE ago (eg 7d) or after a date (2024-01-31)
+ --older-than AGE                       only update and verify files modified more than AGE ago or before a date
+ --settle SECONDS                       do not hash files in update mode that were modified less than SECONDS ago
+ --dry-run                              list the files update mode would hash with an estimated time, without writing anything
  -u, --update                           switch to update mode
+ --rehash [PATH...]                     hash the files at PATH again and replace their lines in the hashsum files,
+                                        reads the paths from STDIN if none are given
  -v, --verify                           switch to verify mode
+ -e, --export FORMAT                    switch to export mode, writing the hashsums as FORMAT (hashdeep/json/csv)
+ -o, --output FILE                      write the output of export mode to FILE instead of STDOUT
+ --lint                                 switch to lint mode, checking the hashsum files without hashing
+ --accept                               switch to accept mode, taking over the new hashsums of the files verify listed
+                                        in to_check_DIRECTORY.txt after asking for each of them
+ -y, --yes                              accept every change without asking in accept mode
+ --strict                               fail on unparseable, blank or duplicate lines in the hashsum files
  -h, --help                             show this help message
  -V, --version                          show version"
                , opts.program_name, VERSION, opts.program_name);
@@ -40,24 +63,62 @@ Arguments:
     }
 
     match opts.mode {
-        util::Mode::Filter => {
-            let reader = std::io::BufReader::new(std::io::stdin());
-            let filter = filter::Filter::new(reader, opts.folder.as_str(), &opts);
+        cli::Mode::Filter => {
+            let stdin = std::io::stdin();
+            let filter = if opts.subdir_mode {
+                Filter::new_subdir(stdin.lock(), &opts.config)
+            } else {
+                Filter::new(stdin.lock(), &opts.config.root, &opts.config)
+            };
 
             match filter {
-                Err(e) => panic!(e),
-                Ok(filter) => {
-                    for line in filter {
-                        println!("{}", line);
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+                Ok(mut filter) => {
+                    filter.set_output(opts.filter_output);
+                    if opts.null_data {
+                        filter.set_delimiter(b'\0');
+                        for line in filter.by_ref() {
+                            print!("{}\0", line);
+                        }
+                    } else {
+                        for line in filter.by_ref() {
+                            println!("{}", line);
+                        }
+                    }
+
+                    for record in filter.invalid_records() {
+                        eprintln!("{}: skipped, the path is not valid UTF-8", record);
+                    }
+                    if let Some(e) = filter.read_error() {
+                        eprintln!("Error reading the paths: {}", e);
+                        std::process::exit(1);
+                    }
+                    if !filter.invalid_records().is_empty() {
+                        std::process::exit(1);
                     }
                 }
             }
         }
-        util::Mode::Update => {
-            update::update_directories(opts);
+        cli::Mode::Update => {
+            std::process::exit(cli::update_directories(opts));
+        }
+        cli::Mode::Verify => {
+            std::process::exit(cli::verify_directories(opts));
+        }
+        cli::Mode::Export => {
+            std::process::exit(cli::export_directories(opts));
+        }
+        cli::Mode::Lint => {
+            std::process::exit(cli::lint_directories(opts));
+        }
+        cli::Mode::Rehash => {
+            std::process::exit(cli::rehash_directories(opts));
         }
-        util::Mode::Verify => {
-            std::process::exit(verify::verify_directories(opts));
+        cli::Mode::Accept => {
+            std::process::exit(cli::accept_directories(opts));
         }
     }
 }
@@ -0,0 +1,320 @@
+//! This module implements the progressbars of loglevel progress, shared by update and verify mode.
+//! Every directory gets its own line, below them a line shows the overall bytes, throughput and ETA.
+
+extern crate chrono;
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use self::chrono::DateTime;
+
+use super::report::{Event, Reporter};
+
+/// A Reporter printing a progressbar for every directory and a line with the overall progress,
+/// used in loglevel progress
+pub struct ProgressReporter {
+    /// The number of lines to scroll up before printing the progressbar of a directory
+    print_lines: HashMap<PathBuf, u32>,
+    /// Number of characters in the name of the longest folder, determines how many spaces are padded
+    longest_folder: usize,
+    /// Printed after the name of a directory once it is finished, eg "checked" or "updated"
+    verb: &'static str,
+    /// The bytes processed so far and the bytes to process of every started directory
+    totals: Mutex<HashMap<PathBuf, (u64, u64)>>,
+    /// The moment the reporter was started, used to calculate throughput and ETA
+    started: Instant,
+}
+
+impl ProgressReporter {
+    /// Creates a ProgressReporter printing a progressbar for every directory in directories
+    ///
+    /// # Arguments
+    /// * `directories` The directories that get a progressbar, the first one is printed right above the overall line
+    /// * `longest_folder` Number of characters in the name of the longest folder, determines how many spaces are padded
+    /// * `verb` Printed after the name of a directory once it is finished, eg "checked" or "updated"
+    pub fn new(directories: &[PathBuf], longest_folder: usize, verb: &'static str) -> Self {
+        // line 1 is the overall line, so the directories start at line 2
+        let print_lines = directories.iter().cloned().zip(2..).collect();
+
+        ProgressReporter {
+            print_lines,
+            longest_folder: longest_folder.max("total".len()),
+            verb,
+            totals: Mutex::new(HashMap::new()),
+            started: Instant::now(),
+        }
+    }
+
+    /// Disables echo on the terminal and reserves a line for every progressbar and the overall line
+    pub fn start(&self) {
+        // without a terminal, eg when run by cron, there is no echo to disable
+        let _ = super::util::terminal_noecho();
+        for _ in 0..=self.print_lines.len() {
+            println!();
+        }
+        self.print_totals(&self.totals.lock().unwrap()).unwrap();
+    }
+
+    /// Remembers the progress of a directory and prints it together with the overall progress
+    ///
+    /// # Arguments
+    /// * `directory` The directory that made progress
+    /// * `processed_bytes` Number of already processed bytes of the directory
+    /// * `total_bytes` Number of bytes of the directory that are going to be processed
+    fn update(&self, directory: &Path, processed_bytes: u64, total_bytes: u64) {
+        if let Some(&line) = self.print_lines.get(directory) {
+            // printing while holding the lock keeps the lines of different threads from interleaving
+            let mut totals = self.totals.lock().unwrap();
+            totals.insert(directory.to_path_buf(), (processed_bytes, total_bytes));
+            print_progress(
+                total_bytes,
+                processed_bytes,
+                line,
+                directory.to_str().unwrap(),
+                self.longest_folder,
+            )
+            .unwrap();
+            self.print_totals(&totals).unwrap();
+        }
+    }
+
+    /// Prints the overall bytes, throughput and estimated remaining time into the last line
+    ///
+    /// # Arguments
+    /// * `totals` The bytes processed so far and the bytes to process of every started directory
+    fn print_totals(&self, totals: &HashMap<PathBuf, (u64, u64)>) -> Result<(), io::Error> {
+        let processed: u64 = totals.values().map(|&(processed, _)| processed).sum();
+        let total: u64 = totals.values().map(|&(_, total)| total).sum();
+        let elapsed = self.started.elapsed().as_secs_f64();
+
+        let mut message = format!(
+            "{} of {}",
+            super::util::format_bytes(processed),
+            super::util::format_bytes(total)
+        );
+        if processed > 0 && elapsed > 0.0 {
+            let throughput = processed as f64 / elapsed;
+            let remaining = total.saturating_sub(processed) as f64 / throughput;
+            message = format!(
+                "{} at {}/s, ETA {}",
+                message,
+                super::util::format_bytes(throughput as u64),
+                super::util::format_duration(remaining.ceil() as u64)
+            );
+        }
+
+        print_message_aligned(1, &message, "total", self.longest_folder)
+    }
+
+    /// Prints a message in a new line above the progressbars, which move down by one line
+    ///
+    /// # Arguments
+    /// * `message` String to print
+    fn print_above(&self, message: &str) {
+        // printing while holding the lock keeps the progressbars from being drawn in between
+        let _totals = self.totals.lock().unwrap();
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        // a new line below the progressbars makes room for inserting one above them,
+        // the message is written to the same stream as the cursor movement to end up in that line
+        let _ = write!(
+            handle,
+            "\n\x1b[s\x1b[{}A\r\x1b[L{}\x1b[u",
+            self.print_lines.len() + 2,
+            message
+        );
+        let _ = handle.flush();
+    }
+}
+
+impl Drop for ProgressReporter {
+    fn drop(&mut self) {
+        super::util::terminal_restore();
+    }
+}
+
+impl Reporter for ProgressReporter {
+    fn report(&self, event: &Event) {
+        match *event {
+            Event::DirectoryStarted {
+                directory,
+                total_bytes,
+            } => self.update(directory, 0, total_bytes),
+            Event::Progress {
+                directory,
+                processed_bytes,
+                total_bytes,
+            } => self.update(directory, processed_bytes, total_bytes),
+            Event::DirectoryFinished {
+                directory,
+                failed_paths,
+            } => {
+                if let Some(&line) = self.print_lines.get(directory) {
+                    let _totals = self.totals.lock().unwrap();
+                    let message = if failed_paths.is_empty() {
+                        format!("{}: OK", self.verb)
+                    } else {
+                        format!("{}: FAILED", self.verb)
+                    };
+                    print_message_aligned(
+                        line,
+                        &message,
+                        directory.to_str().unwrap(),
+                        self.longest_folder,
+                    )
+                    .unwrap();
+                }
+            }
+            Event::DirectoryInterrupted { directory, .. } => {
+                if let Some(&line) = self.print_lines.get(directory) {
+                    let _totals = self.totals.lock().unwrap();
+                    print_message_aligned(
+                        line,
+                        &format!("{}: INTERRUPTED", self.verb),
+                        directory.to_str().unwrap(),
+                        self.longest_folder,
+                    )
+                    .unwrap();
+                }
+            }
+            Event::DirectoryFailed { directory, error } => {
+                let now: DateTime<chrono::Local> = chrono::Local::now();
+                self.print_above(&format!(
+                    "[{}] {}: {}",
+                    now,
+                    directory.to_str().unwrap(),
+                    error
+                ));
+            }
+            Event::Error { directory, error } => {
+                let now: DateTime<chrono::Local> = chrono::Local::now();
+                self.print_above(&format!(
+                    "[{}] {}: could not hash file! {}",
+                    now,
+                    directory.to_str().unwrap(),
+                    error
+                ));
+            }
+            Event::Warning { directory, message } => {
+                let now: DateTime<chrono::Local> = chrono::Local::now();
+                self.print_above(&format!(
+                    "[{}] {}: {}",
+                    now,
+                    directory.to_str().unwrap(),
+                    message
+                ));
+            }
+            Event::Changed {
+                directory,
+                path,
+                change,
+            } => {
+                let now: DateTime<chrono::Local> = chrono::Local::now();
+                self.print_above(&format!(
+                    "[{}] {}: {} {} since it was hashed",
+                    now,
+                    directory.to_str().unwrap(),
+                    path.to_str().unwrap(),
+                    change
+                ));
+            }
+            Event::Skipped {
+                directory,
+                path,
+                reason,
+            } if reason.is_warning() => {
+                let now: DateTime<chrono::Local> = chrono::Local::now();
+                self.print_above(&format!(
+                    "[{}] {}: skipping {}: {}",
+                    now,
+                    directory.to_str().unwrap(),
+                    path.to_str().unwrap(),
+                    reason
+                ));
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Produce a String containing workdir, progress percentage and progress bar, then printing it with print_message
+///
+/// # Arguments
+/// * `all_bytes` Number of bytes in this working directory that are going to be processed
+/// * `processed_bytes` Number of already processed bytes
+/// * `line` Number of lines to scroll up before printing the message
+/// * `workdir` String containing the current working directory, which is printed before the message
+/// * `longest_folder` Number of characters in the name of the longest folder, determines how many spaces are padded
+fn print_progress(
+    all_bytes: u64,
+    processed_bytes: u64,
+    line: u32,
+    workdir: &str,
+    longest_folder: usize,
+) -> Result<(), io::Error> {
+    let progress = if all_bytes == 0 {
+        1.0
+    } else {
+        processed_bytes as f64 / all_bytes as f64
+    };
+    let mut message = format!("{:05.2}% ", progress * 100.0);
+
+    let progress_bar = 60.0 * progress;
+    for i in 0..60 {
+        if (f64::from(i)) < progress_bar {
+            message = format!("{}#", message);
+        } else {
+            message = format!("{}_", message);
+        }
+    }
+
+    print_message_aligned(line, &message, workdir, longest_folder)
+}
+
+/// Print a message N lines above the current cursor.
+/// Cursor position is saved and restored after this operation.
+/// The line is cleared before printing.
+///
+/// # Arguments
+/// * `line` Number of lines to scroll up before printing the message
+/// * `message` String to print
+/// * `workdir` String containing the current working directory, which is printed before the message
+fn print_message(line: u32, message: &str, workdir: &str) -> Result<(), io::Error> {
+    let stdout = io::stdout();
+    let mut handle = stdout.lock();
+    handle.write_all(b"\x1b[s")?;
+    write!(handle, "\x1b[{}A\x1b[2K", line)?;
+    write!(handle, "{}: {}", workdir, message)?;
+    handle.write_all(b"\x1b[u")?;
+    io::stdout().flush()
+}
+
+/// Print a message N lines above the current cursor.
+/// Cursor position is saved and restored after this operation.
+/// The line is cleared before printing.
+/// The message gets padded to the left with spaces in order to align it with
+/// other messages on other lines, using longest_folder as an indicator of needed padding.
+///
+/// # Arguments
+/// * `line` Number of lines to scroll up before printing the message
+/// * `message` String to print
+/// * `workdir` String containing the current working directory, which is printed before the message
+/// * `longest_folder` length of the name of the longest folder in the current workset
+pub fn print_message_aligned(
+    line: u32,
+    message: &str,
+    workdir: &str,
+    longest_folder: usize,
+) -> Result<(), io::Error> {
+    let mut padding = String::new();
+    let mut i = workdir.len();
+    while i < longest_folder {
+        padding = format!("{} ", padding);
+        i += 1;
+    }
+    let to_print = &format!("{} {}", padding, message);
+    print_message(line, to_print, workdir)
+}
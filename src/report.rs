@@ -0,0 +1,162 @@
+//! This module describes the events that occur while updating or verifying directories
+//! and the Reporter trait that receives them, so the engine does not print anything itself
+
+use std::path::Path;
+
+use super::ignore::IgnoreRule;
+use super::lock::LockNotice;
+use super::statcache::StatChange;
+use super::util::{DirectoryError, HashError, HashOutput, SkipReason};
+
+/// Something that happened while updating or verifying a directory
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// The files of a directory are about to be hashed
+    DirectoryStarted {
+        /// The directory that is processed
+        directory: &'a Path,
+        /// The number of bytes that are going to be hashed
+        total_bytes: u64,
+    },
+    /// A file was hashed
+    FileHashed {
+        /// The directory the file belongs to
+        directory: &'a Path,
+        /// The hashsum of the file
+        output: &'a HashOutput,
+    },
+    /// A file does not match the hashsum listed in _algorithm_sum.txt, which is stored in `output.cmp`
+    Mismatch {
+        /// The directory the file belongs to
+        directory: &'a Path,
+        /// The new hashsum of the file
+        output: &'a HashOutput,
+    },
+    /// A file could not be hashed
+    Error {
+        /// The directory the file belongs to
+        directory: &'a Path,
+        /// The error that occured while hashing the file
+        error: &'a HashError,
+    },
+    /// More bytes of a directory have been hashed
+    Progress {
+        /// The directory that is processed
+        directory: &'a Path,
+        /// The number of bytes hashed so far
+        processed_bytes: u64,
+        /// The number of bytes that are going to be hashed
+        total_bytes: u64,
+    },
+    /// Every file of a directory has been processed
+    DirectoryFinished {
+        /// The directory that was processed
+        directory: &'a Path,
+        /// The paths of files that changed and descriptions of files that could not be hashed
+        failed_paths: &'a [String],
+    },
+    /// Processing of a directory was cancelled, see `Config::cancel`.
+    /// The files hashed before have been reported, the remaining ones were skipped.
+    DirectoryInterrupted {
+        /// The directory that was processed
+        directory: &'a Path,
+        /// The paths of files that changed and descriptions of files that could not be hashed
+        failed_paths: &'a [String],
+        /// The number of files that were skipped
+        skipped: usize,
+    },
+    /// A directory could not be processed at all
+    DirectoryFailed {
+        /// The directory that could not be processed
+        directory: &'a Path,
+        /// The reason it could not be processed
+        error: &'a DirectoryError,
+    },
+    /// A file or directory was skipped because a pattern of .arkignore matched it
+    Ignored {
+        /// The directory that is processed
+        directory: &'a Path,
+        /// The path that was skipped
+        path: &'a Path,
+        /// The pattern that matched the path
+        rule: &'a IgnoreRule,
+    },
+    /// A file or directory was skipped while walking the directory, eg a FIFO or a symbolic link
+    Skipped {
+        /// The directory that is processed
+        directory: &'a Path,
+        /// The path that was skipped
+        path: &'a Path,
+        /// Why the path was skipped
+        reason: &'a SkipReason,
+    },
+    /// The size or modification time of a listed file differs from the one recorded when it was hashed
+    Changed {
+        /// The directory that is processed
+        directory: &'a Path,
+        /// The path of the file as listed in _algorithm_sum.txt
+        path: &'a Path,
+        /// How the file changed
+        change: &'a StatChange,
+    },
+    /// Something went wrong that did not prevent the directory from being processed,
+    /// eg a truncated last line of _algorithm_sum.txt
+    Warning {
+        /// The directory the warning is about
+        directory: &'a Path,
+        /// A description of the problem
+        message: &'a str,
+    },
+    /// Something happened while acquiring a lock
+    Lock {
+        /// What happened
+        notice: &'a LockNotice,
+    },
+}
+
+/// Receives the events that occur while updating or verifying directories.
+/// A Reporter can be shared between the threads processing different directories.
+pub trait Reporter: Send + Sync {
+    /// Handles a single event
+    ///
+    /// # Arguments
+    /// * `event` The event that occured
+    fn report(&self, event: &Event);
+}
+
+/// A Reporter that ignores every event
+pub struct NullReporter;
+
+impl Reporter for NullReporter {
+    fn report(&self, _event: &Event) {}
+}
+
+/// A Reporter that passes every event on to several Reporters, in the order they were added
+#[derive(Default)]
+pub struct MultiReporter {
+    /// The Reporters receiving the events
+    reporters: Vec<Box<dyn Reporter>>,
+}
+
+impl MultiReporter {
+    /// Creates a MultiReporter without any Reporters
+    pub fn new() -> MultiReporter {
+        MultiReporter::default()
+    }
+
+    /// Adds a Reporter that receives every following event
+    ///
+    /// # Arguments
+    /// * `reporter` The Reporter to add
+    pub fn push(&mut self, reporter: Box<dyn Reporter>) {
+        self.reporters.push(reporter);
+    }
+}
+
+impl Reporter for MultiReporter {
+    fn report(&self, event: &Event) {
+        for reporter in self.reporters.iter() {
+            reporter.report(event);
+        }
+    }
+}
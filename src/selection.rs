@@ -0,0 +1,172 @@
+//! This module implements restricting update and verify to files with certain attributes:
+//! their size, their name and the time they were last modified
+
+extern crate chrono;
+
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use self::chrono::{DateTime, Local, NaiveDate, TimeZone};
+
+use super::filesystem::Metadata;
+
+/// The attributes a file needs to be updated or verified. Every criterion that is not set accepts all files.
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    /// The minimum size in bytes
+    pub min_size: Option<u64>,
+    /// The maximum size in bytes
+    pub max_size: Option<u64>,
+    /// Globs of which the file name has to match at least one, if any are given, eg "*.cr2".
+    /// Case is ignored, so that "*.cr2" also matches IMG_0001.CR2.
+    pub include: Vec<String>,
+    /// Globs of which the file name must not match any, eg "*.tmp". Case is ignored.
+    pub exclude: Vec<String>,
+    /// The file has to be modified after this time
+    pub newer_than: Option<SystemTime>,
+    /// The file has to be modified before this time
+    pub older_than: Option<SystemTime>,
+}
+
+impl Selection {
+    /// Returns whether every file is accepted
+    pub fn is_empty(&self) -> bool {
+        self.min_size.is_none()
+            && self.max_size.is_none()
+            && self.include.is_empty()
+            && self.exclude.is_empty()
+            && self.newer_than.is_none()
+            && self.older_than.is_none()
+    }
+
+    /// Checks a file against every criterion
+    ///
+    /// # Arguments
+    /// * `path` The path to the file, only its name is matched against the globs
+    /// * `metadata` The properties of the file
+    ///
+    /// # Returns
+    /// None if the file is accepted, otherwise a description of the criterion it failed, eg "is smaller than 1.00 MiB".
+    pub fn reject(&self, path: &Path, metadata: &Metadata) -> Option<String> {
+        if let Some(min_size) = self.min_size {
+            if metadata.len < min_size {
+                return Some(format!(
+                    "is smaller than {}",
+                    super::util::format_bytes(min_size)
+                ));
+            }
+        }
+        if let Some(max_size) = self.max_size {
+            if metadata.len > max_size {
+                return Some(format!(
+                    "is larger than {}",
+                    super::util::format_bytes(max_size)
+                ));
+            }
+        }
+
+        // cameras and older filesystems often write upper case extensions
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let matches = |glob: &&String| super::ignore::glob_matches(&glob.to_lowercase(), &name);
+        if !self.include.is_empty() && !self.include.iter().any(|glob| matches(&glob)) {
+            return Some(format!("does not match {}", self.include.join(", ")));
+        }
+        if let Some(glob) = self.exclude.iter().find(matches) {
+            return Some(format!("matches {}", glob));
+        }
+
+        // files without a modification time are not rejected by their age
+        if let Some(modified) = metadata.modified {
+            if let Some(newer_than) = self.newer_than {
+                if modified <= newer_than {
+                    return Some(format!("was modified before {}", format_time(newer_than)));
+                }
+            }
+            if let Some(older_than) = self.older_than {
+                if modified >= older_than {
+                    return Some(format!("was modified after {}", format_time(older_than)));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+/// Parses a size like "500", "10K", "1.5M" or "2G", the units are powers of 1024
+///
+/// # Arguments
+/// * `size` The size to parse
+///
+/// # Returns
+/// The size in bytes, None if it could not be parsed.
+pub fn parse_size(size: &str) -> Option<u64> {
+    let size = size.trim();
+    let (number, multiplier) = match size.char_indices().last()? {
+        (i, unit) if unit.is_ascii_alphabetic() => {
+            let exponent = match unit.to_ascii_uppercase() {
+                'B' => 0,
+                'K' => 1,
+                'M' => 2,
+                'G' => 3,
+                'T' => 4,
+                _ => return None,
+            };
+            (&size[..i], 1024u64.pow(exponent))
+        }
+        _ => (size, 1),
+    };
+
+    let number: f64 = number.trim().parse().ok()?;
+    if number < 0.0 || !number.is_finite() {
+        return None;
+    }
+
+    Some((number * multiplier as f64) as u64)
+}
+
+/// Parses a point in time, either as an age like "30m", "12h", "7d" or "2w" before now,
+/// or as a date like "2024-01-31", which means the beginning of that day in the local timezone
+///
+/// # Arguments
+/// * `time` The point in time to parse
+/// * `now` The time ages are relative to
+///
+/// # Returns
+/// None if the time could not be parsed.
+pub fn parse_time(time: &str, now: SystemTime) -> Option<SystemTime> {
+    let time = time.trim();
+
+    if let Ok(date) = NaiveDate::parse_from_str(time, "%Y-%m-%d") {
+        let midnight = Local
+            .from_local_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .earliest()?;
+        return Some(SystemTime::from(midnight));
+    }
+
+    let (i, unit) = time.char_indices().last()?;
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let number: u64 = time[..i].trim().parse().ok()?;
+
+    now.checked_sub(Duration::from_secs(number.checked_mul(seconds)?))
+}
+
+/// Formats a point in time in the local timezone, eg "2024-01-31 00:00:00"
+///
+/// # Arguments
+/// * `time` The point in time to format
+fn format_time(time: SystemTime) -> String {
+    DateTime::<Local>::from(time)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
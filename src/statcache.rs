@@ -0,0 +1,238 @@
+//! This module implements the stat cache, a hidden file next to every _algorithm_sum.txt
+//! that stores the size and modification time of the listed files.
+//! It allows update to notice changed, truncated or deleted files within seconds,
+//! without reading their contents like verify does.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use super::config::SymlinkPolicy;
+use super::filesystem::{FileSystem, Metadata};
+
+/// The size and modification time a file had when its hashsum was recorded
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileStat {
+    /// The hashsum the file was listed with, a stat for an outdated hashsum is discarded
+    pub hash: String,
+    /// The size in bytes
+    pub len: u64,
+    /// The time of the last modification, if the filesystem supports it
+    pub modified: Option<SystemTime>,
+}
+
+impl FileStat {
+    /// Creates a FileStat from the current properties of a file
+    ///
+    /// # Arguments
+    /// * `hash` The hashsum the file is listed with
+    /// * `metadata` The properties of the file
+    pub fn new(hash: &str, metadata: &Metadata) -> FileStat {
+        FileStat {
+            hash: hash.to_string(),
+            len: metadata.len,
+            modified: metadata.modified,
+        }
+    }
+}
+
+/// How a file differs from the stat recorded for it
+#[derive(Debug, Clone, PartialEq)]
+pub enum StatChange {
+    /// The file does not exist anymore
+    Missing,
+    /// The size of the file changed, eg because it was truncated
+    Resized {
+        /// The size that was recorded
+        recorded: u64,
+        /// The current size
+        current: u64,
+    },
+    /// The file was modified, but still has the same size
+    Modified,
+}
+
+impl fmt::Display for StatChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StatChange::Missing => write!(f, "has been deleted"),
+            StatChange::Resized { recorded, current } => {
+                write!(
+                    f,
+                    "has changed its size from {} to {} bytes",
+                    recorded, current
+                )
+            }
+            StatChange::Modified => write!(f, "has been modified without changing its size"),
+        }
+    }
+}
+
+/// The recorded stats of the files listed in an _algorithm_sum.txt, by their path as listed
+#[derive(Debug, Clone, Default)]
+pub struct StatCache {
+    /// The stats by path
+    pub stats: BTreeMap<String, FileStat>,
+}
+
+impl StatCache {
+    /// Reads a stat cache. A missing file is treated as an empty one,
+    /// lines that can not be understood are dropped, so that their files get a new stat.
+    ///
+    /// # Arguments
+    /// * `filesystem` The filesystem containing the stat cache
+    /// * `path` Path to the stat cache
+    pub fn read(filesystem: &dyn FileSystem, path: &Path) -> io::Result<StatCache> {
+        let contents = match filesystem.read(path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(StatCache::default()),
+            Err(e) => return Err(e),
+        };
+
+        let mut cache = StatCache::default();
+        for line in String::from_utf8_lossy(&contents).lines() {
+            // the path comes last, so that it may contain tabs
+            let fields: Vec<&str> = line.splitn(4, '\t').collect();
+            if fields.len() != 4 {
+                continue;
+            }
+            let len = match fields[1].parse() {
+                Ok(len) => len,
+                Err(_) => continue,
+            };
+            let modified = match fields[2] {
+                "-" => None,
+                modified => match parse_time(modified) {
+                    Some(modified) => Some(modified),
+                    None => continue,
+                },
+            };
+
+            cache.stats.insert(
+                fields[3].to_string(),
+                FileStat {
+                    hash: fields[0].to_string(),
+                    len,
+                    modified,
+                },
+            );
+        }
+
+        Ok(cache)
+    }
+
+    /// Atomically replaces a stat cache with the stats of this StatCache
+    ///
+    /// # Arguments
+    /// * `filesystem` The filesystem containing the stat cache
+    /// * `path` Path to the stat cache
+    pub fn write(&self, filesystem: &dyn FileSystem, path: &Path) -> io::Result<()> {
+        let mut contents = String::new();
+        for (file, stat) in self.stats.iter() {
+            contents.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                stat.hash,
+                stat.len,
+                stat.modified
+                    .and_then(format_time)
+                    .unwrap_or_else(|| String::from("-")),
+                file
+            ));
+        }
+
+        filesystem.write_atomic(path, contents.as_bytes())
+    }
+
+    /// Records the current stat of a file that was hashed again, or drops its stat if it can not be read
+    ///
+    /// # Arguments
+    /// * `filesystem` The filesystem containing the file
+    /// * `directory` The directory containing the _algorithm_sum.txt
+    /// * `path` The path of the file as listed in _algorithm_sum.txt
+    /// * `hash` The hashsum the file is listed with now
+    /// * `symlinks` The policy the file was hashed with
+    pub fn refresh(
+        &mut self,
+        filesystem: &dyn FileSystem,
+        directory: &Path,
+        path: &str,
+        hash: &str,
+        symlinks: SymlinkPolicy,
+    ) {
+        let file = directory.join(path);
+        let metadata = if symlinks == SymlinkPolicy::Record {
+            filesystem.symlink_metadata(&file)
+        } else {
+            filesystem.metadata(&file)
+        };
+
+        match metadata {
+            Ok(metadata) => {
+                self.stats
+                    .insert(path.to_string(), FileStat::new(hash, &metadata));
+            }
+            Err(_) => {
+                self.stats.remove(path);
+            }
+        }
+    }
+
+    /// Compares a file with its recorded stat
+    ///
+    /// # Arguments
+    /// * `path` The path of the file as listed in _algorithm_sum.txt
+    /// * `hash` The hashsum the file is listed with
+    /// * `metadata` The current properties of the file, None if it does not exist
+    ///
+    /// # Returns
+    /// None if the file did not change or nothing was recorded for its hashsum yet.
+    pub fn check(&self, path: &str, hash: &str, metadata: Option<&Metadata>) -> Option<StatChange> {
+        let recorded = match self.stats.get(path) {
+            Some(recorded) if recorded.hash == hash => recorded,
+            _ => return None,
+        };
+
+        match metadata {
+            None => Some(StatChange::Missing),
+            Some(metadata) if metadata.len != recorded.len => Some(StatChange::Resized {
+                recorded: recorded.len,
+                current: metadata.len,
+            }),
+            Some(metadata) if metadata.modified != recorded.modified => Some(StatChange::Modified),
+            Some(_) => None,
+        }
+    }
+}
+
+/// Returns whether the given filename belongs to a stat cache or its temporary file
+///
+/// # Arguments
+/// * `filename` The name of the file without any directories
+pub fn is_stat_cache(filename: &str) -> bool {
+    filename.starts_with('.')
+        && (filename.ends_with("sum.txt.stat") || filename.ends_with("sum.txt.stat.tmp"))
+}
+
+/// Formats a modification time as seconds and nanoseconds since the epoch, eg "1706655600.123456789"
+///
+/// # Arguments
+/// * `time` The modification time
+fn format_time(time: SystemTime) -> Option<String> {
+    time.duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|since| format!("{}.{:09}", since.as_secs(), since.subsec_nanos()))
+}
+
+/// Parses a modification time formatted by `format_time`
+///
+/// # Arguments
+/// * `time` The formatted modification time
+fn parse_time(time: &str) -> Option<SystemTime> {
+    let mut parts = time.splitn(2, '.');
+    let seconds = parts.next()?.parse().ok()?;
+    let nanoseconds = parts.next()?.parse().ok()?;
+
+    UNIX_EPOCH.checked_add(Duration::new(seconds, nanoseconds))
+}
@@ -0,0 +1,527 @@
+//! This module implements parsing and crash-safe writing of the _algorithm_sum.txt files
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::config::{Algorithm, Config, SymlinkPolicy};
+use super::filesystem::FileSystem;
+use super::util::normalize_path;
+
+/// Number of lines after which the written hashsums are synced to disk
+const CHECKPOINT_LINES: usize = 64;
+/// Time after which the written hashsums are synced to disk
+const CHECKPOINT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// A single hashsum read from an _algorithm_sum.txt
+#[derive(Debug, Clone)]
+pub struct SumfileEntry {
+    /// The number of the line the entry was read from, starting at 1
+    pub line_number: usize,
+    /// The hashsum of the file
+    pub hash: String,
+    /// The path to the file, relative to the directory of the _algorithm_sum.txt
+    pub path: String,
+}
+
+/// The kind of problem found in a line of an _algorithm_sum.txt
+#[derive(Debug, PartialEq, Clone)]
+pub enum DiagnosticKind {
+    /// The line is empty
+    Blank,
+    /// The line is not a valid hashsum line for the algorithm
+    Unparseable,
+    /// The path was already listed in the given line
+    Duplicate(usize),
+    /// The line does not end with a newline
+    Truncated,
+}
+
+/// A problem found in a line of an _algorithm_sum.txt
+#[derive(Debug, Clone)]
+pub struct SumfileDiagnostic {
+    /// The number of the line, starting at 1
+    pub line_number: usize,
+    /// The kind of problem
+    pub kind: DiagnosticKind,
+    /// The contents of the line
+    pub line: String,
+}
+
+impl fmt::Display for SumfileDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            DiagnosticKind::Blank => write!(f, "line {}: blank line", self.line_number),
+            DiagnosticKind::Unparseable => write!(
+                f,
+                "line {}: not a valid hashsum line: {}",
+                self.line_number, self.line
+            ),
+            DiagnosticKind::Duplicate(first) => write!(
+                f,
+                "line {}: duplicate entry, first listed in line {}: {}",
+                self.line_number, first, self.line
+            ),
+            DiagnosticKind::Truncated => write!(
+                f,
+                "line {}: truncated last line: {}",
+                self.line_number, self.line
+            ),
+        }
+    }
+}
+
+/// The contents of an _algorithm_sum.txt: the hashsums listed in it and the problems found in it
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    /// The hashsums in the order they are listed
+    pub entries: Vec<SumfileEntry>,
+    /// The problems found while reading the file
+    pub diagnostics: Vec<SumfileDiagnostic>,
+}
+
+impl Manifest {
+    /// Reads all hashsums from an _algorithm_sum.txt.
+    /// A missing file is treated as an empty one.
+    ///
+    /// # Arguments
+    /// * `filesystem` The filesystem containing the _algorithm_sum.txt
+    /// * `path` Path to the _algorithm_sum.txt
+    /// * `algorithm` The algorithm the hashsums were calculated with
+    pub fn read(
+        filesystem: &dyn FileSystem,
+        path: &Path,
+        algorithm: Algorithm,
+    ) -> io::Result<Manifest> {
+        match filesystem.read(path) {
+            Ok(contents) => Ok(Manifest::parse(&contents, algorithm)),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Reads all hashsums from a Reader in the format of an _algorithm_sum.txt
+    ///
+    /// # Arguments
+    /// * `reader` The Reader to read from
+    /// * `algorithm` The algorithm the hashsums were calculated with
+    pub fn from_reader<R: Read>(mut reader: R, algorithm: Algorithm) -> io::Result<Manifest> {
+        let mut contents = Vec::new();
+        reader.read_to_end(&mut contents)?;
+
+        Ok(Manifest::parse(&contents, algorithm))
+    }
+
+    /// Parses the contents of an _algorithm_sum.txt.
+    ///
+    /// Lines that can be understood are returned as entries, even if they are reported as a problem.
+    /// This keeps the lenient behavior of earlier versions, the diagnostics can be used to be stricter.
+    ///
+    /// # Arguments
+    /// * `contents` The contents of the _algorithm_sum.txt
+    /// * `algorithm` The algorithm the hashsums were calculated with
+    pub fn parse(contents: &[u8], algorithm: Algorithm) -> Manifest {
+        let file_path_re = algorithm.line_regex();
+
+        let mut manifest = Manifest::default();
+        let mut first_occurences: HashMap<PathBuf, usize> = HashMap::new();
+
+        let mut lines: Vec<&[u8]> = contents.split(|&b| b == b'\n').collect();
+        // the last element is empty if the file ends with a newline
+        let truncated = truncated_line_start(contents).is_some();
+        if !truncated {
+            lines.pop();
+        }
+        let line_count = lines.len();
+
+        for (i, line) in lines.into_iter().enumerate() {
+            let line_number = i + 1;
+            let line = String::from_utf8_lossy(line).to_string();
+
+            if truncated && line_number == line_count {
+                manifest.diagnostics.push(SumfileDiagnostic {
+                    line_number,
+                    kind: DiagnosticKind::Truncated,
+                    line: line.clone(),
+                });
+            }
+
+            if line.trim().is_empty() {
+                manifest.diagnostics.push(SumfileDiagnostic {
+                    line_number,
+                    kind: DiagnosticKind::Blank,
+                    line,
+                });
+                continue;
+            }
+
+            let captures = match file_path_re.captures(&line) {
+                Some(captures) => captures,
+                None => {
+                    manifest.diagnostics.push(SumfileDiagnostic {
+                        line_number,
+                        kind: DiagnosticKind::Unparseable,
+                        line,
+                    });
+                    continue;
+                }
+            };
+
+            // the regex does not have to match at the start of the line, but a valid line does
+            if captures.get(0).unwrap().start() != 0 || captures[2].is_empty() {
+                manifest.diagnostics.push(SumfileDiagnostic {
+                    line_number,
+                    kind: DiagnosticKind::Unparseable,
+                    line: line.clone(),
+                });
+            }
+
+            let path = captures[2].to_string();
+            // "a.jpg" and "./sub/../a.jpg" list the same file
+            let key = normalize_path(Path::new(&path)).unwrap_or_else(|| PathBuf::from(&path));
+            if let Some(&first) = first_occurences.get(&key) {
+                manifest.diagnostics.push(SumfileDiagnostic {
+                    line_number,
+                    kind: DiagnosticKind::Duplicate(first),
+                    line: line.clone(),
+                });
+            } else {
+                first_occurences.insert(key, line_number);
+            }
+
+            manifest.entries.push(SumfileEntry {
+                line_number,
+                hash: captures[1].to_string(),
+                path,
+            });
+        }
+
+        manifest
+    }
+
+    /// Returns the entries in the format of an _algorithm_sum.txt
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut contents = Vec::new();
+
+        for entry in self.entries.iter() {
+            contents.extend_from_slice(format!("{}  {}\n", entry.hash, entry.path).as_bytes());
+        }
+
+        contents
+    }
+
+    /// Atomically replaces an _algorithm_sum.txt with the entries of this Manifest
+    ///
+    /// # Arguments
+    /// * `filesystem` The filesystem containing the _algorithm_sum.txt
+    /// * `path` Path to the _algorithm_sum.txt
+    pub fn write(&self, filesystem: &dyn FileSystem, path: &Path) -> io::Result<()> {
+        filesystem.write_atomic(path, &self.to_bytes())
+    }
+}
+
+/// Reads all hashsums from an _algorithm_sum.txt, honoring the strict mode of the config.
+/// In strict mode an error listing every problem in the file is returned if there are any.
+///
+/// # Arguments
+/// * `path` Path to the _algorithm_sum.txt
+/// * `config` Config object that contains the desired algorithm and whether to be strict
+pub fn read_entries(path: &Path, config: &Config) -> io::Result<Vec<SumfileEntry>> {
+    let manifest = Manifest::read(&*config.filesystem, path, config.algorithm)?;
+
+    if config.strict && !manifest.diagnostics.is_empty() {
+        let mut message = String::new();
+        for diagnostic in manifest.diagnostics.iter() {
+            message.push_str(&format!("{}: {}\n", path.to_str().unwrap(), diagnostic));
+        }
+        message.push_str(&format!(
+            "{} contains {} malformed lines or duplicates",
+            path.to_str().unwrap(),
+            manifest.diagnostics.len()
+        ));
+
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+    }
+
+    Ok(manifest.entries)
+}
+
+/// Atomically replaces single lines of an _algorithm_sum.txt, keeping every other line as it is
+///
+/// # Arguments
+/// * `filesystem` The filesystem containing the _algorithm_sum.txt
+/// * `path` Path to the _algorithm_sum.txt
+/// * `replacements` The new contents of lines without newline, by their line number starting at 1
+pub fn replace_lines(
+    filesystem: &dyn FileSystem,
+    path: &Path,
+    replacements: &HashMap<usize, String>,
+) -> io::Result<()> {
+    let contents = filesystem.read(path)?;
+    let mut replaced = Vec::with_capacity(contents.len());
+
+    for (i, line) in contents.split_inclusive(|&b| b == b'\n').enumerate() {
+        match replacements.get(&(i + 1)) {
+            Some(replacement) => {
+                replaced.extend_from_slice(replacement.as_bytes());
+                if line.ends_with(b"\n") {
+                    replaced.push(b'\n');
+                }
+            }
+            None => replaced.extend_from_slice(line),
+        }
+    }
+
+    filesystem.write_atomic(path, &replaced)
+}
+
+/// Appends lines to an _algorithm_sum.txt and syncs them to disk at regular checkpoints.
+/// If the program is interrupted, at most the lines written since the last checkpoint are lost.
+pub struct SumfileWriter {
+    /// The filesystem containing the sumfile
+    filesystem: Arc<dyn FileSystem>,
+    /// Path to the sumfile
+    path: PathBuf,
+    /// The length of the sumfile before anything was appended
+    start: usize,
+    /// The lines written since the last checkpoint
+    pending: Vec<u8>,
+    /// Number of lines written since the last checkpoint
+    pending_lines: usize,
+    /// Time of the last checkpoint
+    last_checkpoint: Instant,
+}
+
+impl SumfileWriter {
+    /// Opens an _algorithm_sum.txt for appending, creating it if it does not exist
+    ///
+    /// # Arguments
+    /// * `filesystem` The filesystem containing the _algorithm_sum.txt
+    /// * `path` Path to the _algorithm_sum.txt
+    pub fn open(filesystem: Arc<dyn FileSystem>, path: &Path) -> io::Result<SumfileWriter> {
+        filesystem.append(path, &[])?;
+        let start = filesystem.metadata(path)?.len as usize;
+
+        Ok(SumfileWriter {
+            filesystem,
+            path: path.to_path_buf(),
+            start,
+            pending: Vec::new(),
+            pending_lines: 0,
+            last_checkpoint: Instant::now(),
+        })
+    }
+
+    /// Appends a line to the sumfile. The line has to end with a newline.
+    ///
+    /// # Arguments
+    /// * `line` The line to append
+    pub fn append(&mut self, line: &str) -> io::Result<()> {
+        self.pending.extend_from_slice(line.as_bytes());
+        self.pending_lines += 1;
+
+        if self.pending_lines >= CHECKPOINT_LINES
+            || self.last_checkpoint.elapsed() >= CHECKPOINT_INTERVAL
+        {
+            self.checkpoint()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes all buffered lines to the sumfile and syncs it to disk
+    pub fn checkpoint(&mut self) -> io::Result<()> {
+        self.filesystem.append(&self.path, &self.pending)?;
+        self.pending.clear();
+        self.pending_lines = 0;
+        self.last_checkpoint = Instant::now();
+
+        Ok(())
+    }
+
+    /// Writes all buffered lines and atomically moves every appended line to its place by path,
+    /// so that hashing the same files always results in the same _algorithm_sum.txt,
+    /// no matter in which order the worker threads finished.
+    ///
+    /// The appended lines are sorted and merged into the lines that were there before,
+    /// which keep their order: a sorted _algorithm_sum.txt stays sorted,
+    /// an unsorted one of an earlier version gets every new line in front of the first line with a greater path.
+    ///
+    /// # Arguments
+    /// * `algorithm` The algorithm the hashsums were calculated with
+    ///
+    /// # Returns
+    /// The truncated last line of the lines that were there before, if the appended lines were left unsorted
+    /// because of it.
+    pub fn sort_appended(&mut self, algorithm: Algorithm) -> io::Result<Option<String>> {
+        self.checkpoint()?;
+
+        let contents = self.filesystem.read(&self.path)?;
+        if contents.len() <= self.start {
+            return Ok(None);
+        }
+        let (existing, appended) = contents.split_at(self.start);
+        // lines can only be merged if the existing lines are complete
+        if let Some(start) = truncated_line_start(existing) {
+            return Ok(Some(
+                String::from_utf8_lossy(&existing[start..]).to_string(),
+            ));
+        }
+
+        let file_path_re = algorithm.line_regex();
+        let line_path = |line: &[u8]| -> Option<String> {
+            let line = String::from_utf8_lossy(line);
+            file_path_re
+                .captures(line.trim_end_matches('\n'))
+                .map(|captures| captures[2].to_string())
+        };
+
+        let mut new_lines: Vec<(Option<String>, &[u8])> = appended
+            .split_inclusive(|&b| b == b'\n')
+            .map(|line| (line_path(line), line))
+            .collect();
+        new_lines.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut new_lines = new_lines.into_iter().peekable();
+
+        let mut merged = Vec::with_capacity(contents.len());
+        for line in existing.split_inclusive(|&b| b == b'\n') {
+            // lines without a path, like blank ones, stay where they are
+            if let Some(path) = line_path(line) {
+                while let Some((_, new_line)) =
+                    new_lines.next_if(|(new_path, _)| new_path.as_ref() < Some(&path))
+                {
+                    merged.extend_from_slice(new_line);
+                }
+            }
+            merged.extend_from_slice(line);
+        }
+        for (_, new_line) in new_lines {
+            merged.extend_from_slice(new_line);
+        }
+
+        self.filesystem.write_atomic(&self.path, &merged)?;
+        self.start = merged.len();
+
+        Ok(None)
+    }
+}
+
+impl Drop for SumfileWriter {
+    fn drop(&mut self) {
+        if self.pending_lines > 0 {
+            if let Err(e) = self.checkpoint() {
+                eprintln!("Error writing to file: {}", e);
+            }
+        }
+    }
+}
+
+/// Returns whether the given filename belongs to a temporary file created by `FileSystem::write_atomic`
+///
+/// # Arguments
+/// * `filename` The name of the file without any directories
+pub fn is_temporary_file(filename: &str) -> bool {
+    filename.starts_with('.') && filename.ends_with("sum.txt.tmp")
+}
+
+/// Returns the position of the trailing line in the contents of a file if it does not end with a newline.
+/// Such a line is the result of an interrupted write.
+///
+/// # Arguments
+/// * `contents` The contents of the _algorithm_sum.txt
+fn truncated_line_start(contents: &[u8]) -> Option<usize> {
+    match contents.last() {
+        None | Some(b'\n') => None,
+        Some(_) => Some(
+            contents
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map_or(0, |position| position + 1),
+        ),
+    }
+}
+
+/// Returns the trailing line of an _algorithm_sum.txt if it does not end with a newline.
+///
+/// # Arguments
+/// * `filesystem` The filesystem containing the _algorithm_sum.txt
+/// * `path` Path to the _algorithm_sum.txt
+pub fn truncated_line(filesystem: &dyn FileSystem, path: &Path) -> io::Result<Option<String>> {
+    let contents = match filesystem.read(path) {
+        Ok(contents) => contents,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    Ok(truncated_line_start(&contents)
+        .map(|start| String::from_utf8_lossy(&contents[start..]).to_string()))
+}
+
+/// Repairs an _algorithm_sum.txt whose last line was not written completely.
+/// The missing newline is only appended if the last line is a valid hashsum line for a file that is not listed yet
+/// and hashing the file again results in the same hashsum. A line cut off in the middle of its path,
+/// eg "./ab" cut to "./a", would otherwise list the hashsum of one file for another one.
+/// In every other case the partial line is removed, so that the file gets hashed again.
+///
+/// # Arguments
+/// * `filesystem` The filesystem containing the _algorithm_sum.txt and the files
+/// * `path` Path to the _algorithm_sum.txt
+/// * `workdir` Path to the directory the hashsums belong to
+/// * `algorithm` The algorithm the hashsums were calculated with
+///
+/// # Returns
+/// The partial line and whether it was kept, or None if the file did not need to be repaired.
+pub fn repair_truncated_line(
+    filesystem: &dyn FileSystem,
+    path: &Path,
+    workdir: &Path,
+    algorithm: Algorithm,
+) -> io::Result<Option<(String, bool)>> {
+    let mut contents = match filesystem.read(path) {
+        Ok(contents) => contents,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    let start = match truncated_line_start(&contents) {
+        Some(start) => start,
+        None => return Ok(None),
+    };
+    let partial_line = String::from_utf8_lossy(&contents[start..]).to_string();
+
+    let complete = match algorithm.line_regex().captures(&partial_line) {
+        Some(ref captures) if captures.get(0).unwrap().start() == 0 => {
+            let listed = Manifest::parse(&contents[..start], algorithm)
+                .entries
+                .iter()
+                .any(|entry| {
+                    normalize_path(Path::new(&entry.path))
+                        == normalize_path(Path::new(&captures[2]))
+                });
+
+            !listed
+                && super::util::calculate_hash(
+                    captures[2].to_string(),
+                    workdir,
+                    algorithm,
+                    filesystem,
+                    SymlinkPolicy::Follow,
+                )
+                .is_ok_and(|(hash, _)| hash == captures[1].to_lowercase())
+        }
+        _ => false,
+    };
+
+    if complete {
+        contents.push(b'\n');
+    } else {
+        contents.truncate(start);
+    }
+
+    filesystem.write_atomic(path, &contents)?;
+
+    Ok(Some((partial_line, complete)))
+}
@@ -1,250 +1,1418 @@
 //! This module implements the update functionality
 
 extern crate chrono;
-extern crate crossbeam_deque;
-extern crate num_cpus;
 
-use std::fs::{self, OpenOptions};
-use std::io::{BufReader, Write};
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
 use std::sync::Arc;
 use std::thread;
+use std::time::{Duration, Instant, SystemTime};
 
 use self::chrono::DateTime;
 
-use self::crossbeam_deque::Injector;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::channel;
+use super::config::{Config, SymlinkPolicy};
+use super::filesystem::Metadata;
+use super::ignore::{IgnoreRules, IgnoreTree};
+use super::links::RecordedLinks;
+use super::progress::ProgressReporter;
+use super::report::{Event, NullReporter, Reporter};
+use super::statcache::{FileStat, StatCache, StatChange};
+use super::sumfile::SumfileEntry;
+use super::util::{DirectoryError, HashError, HashOutput, HashPool, HashTask, SkipReason};
 
-/// Updates the _algorithm_sum.txt files of some directories
-///
-/// # Arguments
-///
-/// * `opts` An Options object containing information about the program behavior
-pub fn update_directories(opts: super::util::Options) {
-    if !opts.subdir_mode {
-        let mut worker_handles = Vec::new();
-        let q = Arc::new(Injector::new());
-        let producer_finished = Arc::new(AtomicBool::new(false));
-        let num_threads = match opts.num_threads {
-            0 => num_cpus::get(),
-            _ => opts.num_threads,
-        };
+/// The result of updating the _algorithm_sum.txt of a directory
+#[derive(Debug)]
+pub struct UpdateReport {
+    /// The directory that was updated
+    pub directory: PathBuf,
+    /// The files that were hashed and appended to _algorithm_sum.txt
+    pub hashed: Vec<HashOutput>,
+    /// The files that could not be hashed
+    pub failed: Vec<HashError>,
+    /// The truncated last line of _algorithm_sum.txt and whether it was completed,
+    /// if it had to be repaired
+    pub repaired_line: Option<(String, bool)>,
+    /// The error that occured while trying to repair a truncated last line
+    pub repair_error: Option<io::Error>,
+    /// The number of new files that were skipped because the update was cancelled
+    pub skipped: usize,
+    /// The listed files whose size or modification time differs from the one recorded when they were hashed
+    pub changed: Vec<(String, StatChange)>,
+}
+
+impl UpdateReport {
+    /// Creates a report for a directory that has not been updated yet
+    ///
+    /// # Arguments
+    /// * `directory` The directory that is going to be updated
+    fn new(directory: &Path) -> UpdateReport {
+        UpdateReport {
+            directory: directory.to_path_buf(),
+            hashed: Vec::new(),
+            failed: Vec::new(),
+            repaired_line: None,
+            repair_error: None,
+            skipped: 0,
+            changed: Vec::new(),
+        }
+    }
+}
+
+/// The result of hashing selected files of a directory again
+#[derive(Debug)]
+pub struct RehashReport {
+    /// The directory whose _algorithm_sum.txt lists the files
+    pub directory: PathBuf,
+    /// The files that were hashed again, `cmp` holds the hashsum they were listed with before
+    pub rehashed: Vec<HashOutput>,
+    /// The files that could not be hashed, their lines were kept
+    pub failed: Vec<HashError>,
+    /// The given paths that are not listed in _algorithm_sum.txt
+    pub not_listed: Vec<String>,
+    /// The number of files that were skipped because the rehash was cancelled
+    pub skipped: usize,
+}
+
+impl RehashReport {
+    /// Creates a report for a directory whose files have not been hashed again yet
+    ///
+    /// # Arguments
+    /// * `directory` The directory whose _algorithm_sum.txt lists the files
+    fn new(directory: &Path) -> RehashReport {
+        RehashReport {
+            directory: directory.to_path_buf(),
+            rehashed: Vec::new(),
+            failed: Vec::new(),
+            not_listed: Vec::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Returns whether every given file was hashed again
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty() && self.not_listed.is_empty() && self.skipped == 0
+    }
+}
 
-        let opts = Arc::new(opts);
-        let workdir = PathBuf::from(&opts.folder);
-        let myq = Arc::clone(&q);
+/// The files an update of a directory would hash, as determined by a dry run
+#[derive(Debug, Clone)]
+pub struct UpdatePlan {
+    /// The directory that would be updated
+    pub directory: PathBuf,
+    /// The paths of the files that are not listed in _algorithm_sum.txt, together with their sizes
+    pub files: Vec<(String, u64)>,
+}
+
+impl UpdatePlan {
+    /// Returns the number of bytes that would be hashed
+    pub fn total_bytes(&self) -> u64 {
+        self.files.iter().map(|&(_, size)| size).sum()
+    }
+}
+
+/// Hashes files that are not listed in the _algorithm_sum.txt of a directory and appends them to it.
+/// An Updater owns a pool of worker threads, it can be shared between threads to update
+/// multiple directories at once.
+pub struct Updater {
+    /// The settings of the engine
+    config: Config,
+    /// The worker threads hashing the files
+    pool: HashPool,
+}
+
+impl Updater {
+    /// Creates a new Updater and starts its worker threads
+    ///
+    /// # Arguments
+    /// * `config` The settings of the engine
+    pub fn new(config: Config) -> Updater {
+        let pool = HashPool::new(config.threads());
+
+        Updater { config, pool }
+    }
+
+    /// Returns the settings of the engine
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Updates the _algorithm_sum.txt of a directory
+    ///
+    /// # Arguments
+    /// * `directory` The directory that is going to be updated
+    pub fn update(&self, directory: &Path) -> Result<UpdateReport, DirectoryError> {
+        self.update_with(directory, &NullReporter)
+    }
+
+    /// Updates the _algorithm_sum.txt of a directory and reports every hashed file to a Reporter
+    /// as soon as it is hashed
+    ///
+    /// # Arguments
+    /// * `directory` The directory that is going to be updated
+    /// * `reporter` Receives the events that occur while updating the directory
+    pub fn update_with(
+        &self,
+        directory: &Path,
+        reporter: &dyn Reporter,
+    ) -> Result<UpdateReport, DirectoryError> {
+        let result = self.update_reporting(directory, reporter);
+
+        if let Err(ref error) = result {
+            reporter.report(&Event::DirectoryFailed { directory, error });
+        }
+
+        result
+    }
+
+    /// Updates the _algorithm_sum.txt of a directory, see `update_with`
+    ///
+    /// # Arguments
+    /// * `directory` The directory that is going to be updated
+    /// * `reporter` Receives the events that occur while updating the directory
+    fn update_reporting(
+        &self,
+        directory: &Path,
+        reporter: &dyn Reporter,
+    ) -> Result<UpdateReport, DirectoryError> {
+        let mut report = UpdateReport::new(directory);
+
+        if dir_is_empty(directory, &self.config) {
+            reporter.report(&Event::DirectoryStarted {
+                directory,
+                total_bytes: 0,
+            });
+            reporter.report(&Event::DirectoryFinished {
+                directory,
+                failed_paths: &[],
+            });
+            return Ok(report);
+        }
 
-        let handle = thread::spawn(move || {
-            update_hashsums(&workdir, opts, myq);
+        let _lock = super::lock::lock_directory(directory, &self.config, &mut |notice| {
+            reporter.report(&Event::Lock { notice: &notice })
+        })
+        .map_err(DirectoryError::Locked)?;
+
+        let sumfile_path = self
+            .config
+            .sumfile_path(directory)
+            .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+        match super::sumfile::repair_truncated_line(
+            &*self.config.filesystem,
+            &sumfile_path,
+            directory,
+            self.config.algorithm,
+        ) {
+            Ok(Some((partial_line, kept))) => {
+                let message = format!(
+                    "{} truncated last line of {}: {}",
+                    if kept { "completed" } else { "removed" },
+                    sumfile_path.to_str().unwrap(),
+                    partial_line
+                );
+                reporter.report(&Event::Warning {
+                    directory,
+                    message: &message,
+                });
+                report.repaired_line = Some((partial_line, kept));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                let message = format!("could not repair {}: {}", sumfile_path.to_str().unwrap(), e);
+                reporter.report(&Event::Warning {
+                    directory,
+                    message: &message,
+                });
+                report.repair_error = Some(e);
+            }
+        }
+
+        let links_path = self
+            .config
+            .links_path(directory)
+            .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+        let mut links = RecordedLinks::read(&*self.config.filesystem, &links_path)
+            .map_err(|e| DirectoryError::Sumfile(format!("{}: {}", links_path.display(), e)))?;
+        let recorded_links = links.targets.len();
+
+        let stat_cache_path = self
+            .config
+            .stat_cache_path(directory)
+            .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+        let mut stat_cache = self.check_stats(
+            directory,
+            &sumfile_path,
+            &stat_cache_path,
+            &links,
+            reporter,
+            &mut report,
+        )?;
+
+        let new_files = self.new_files(directory, reporter)?;
+
+        let sumfile_error =
+            |e: io::Error| DirectoryError::Sumfile(format!("{}: {}", sumfile_path.display(), e));
+        let mut writer =
+            super::sumfile::SumfileWriter::open(Arc::clone(&self.config.filesystem), &sumfile_path)
+                .map_err(&sumfile_error)?;
+
+        let total_bytes = super::util::count_bytes(&*self.config.filesystem, directory, &new_files);
+        let mut processed_bytes = 0;
+        reporter.report(&Event::DirectoryStarted {
+            directory,
+            total_bytes,
         });
 
-        super::util::execute_workers(
-            num_threads,
-            Arc::clone(&q),
-            Arc::clone(&producer_finished),
-            &mut worker_handles,
+        let (sender, receiver) = channel();
+        let queued = new_files.len();
+
+        for path in new_files {
+            self.pool.push(HashTask {
+                path,
+                workdir: directory.to_path_buf(),
+                algorithm: self.config.algorithm,
+                filesystem: Arc::clone(&self.config.filesystem),
+                cmp: String::new(),
+                result_chan: sender.clone(),
+                cancelled: Arc::clone(&self.config.cancelled),
+                symlinks: self.config.symlinks,
+            });
+        }
+
+        drop(sender);
+
+        for task_result in receiver {
+            match task_result {
+                Ok(output) => {
+                    writer.append(&output.hashline()).map_err(&sumfile_error)?;
+                    processed_bytes += output.size;
+                    let path = directory.join(&output.path);
+                    if let Ok(metadata) = self.stat(&path, self.config.symlinks) {
+                        if metadata.is_symlink {
+                            if let Ok(target) = self.config.filesystem.read_link(&path) {
+                                let target = target.to_string_lossy().to_string();
+                                links.targets.insert(output.path.clone(), target);
+                            }
+                        }
+                        stat_cache
+                            .stats
+                            .insert(output.path.clone(), FileStat::new(&output.hash, &metadata));
+                    }
+
+                    reporter.report(&Event::FileHashed {
+                        directory,
+                        output: &output,
+                    });
+                    reporter.report(&Event::Progress {
+                        directory,
+                        processed_bytes,
+                        total_bytes,
+                    });
+                    report.hashed.push(output);
+                }
+                Err(error) => {
+                    reporter.report(&Event::Error {
+                        directory,
+                        error: &error,
+                    });
+                    report.failed.push(error);
+                }
+            }
+        }
+
+        if let Some(partial_line) = writer
+            .sort_appended(self.config.algorithm)
+            .map_err(&sumfile_error)?
+        {
+            let message = format!(
+                "did not sort the new lines of {}, the line before them is truncated: {}",
+                sumfile_path.to_str().unwrap(),
+                partial_line
+            );
+            reporter.report(&Event::Warning {
+                directory,
+                message: &message,
+            });
+        }
+        if links.targets.len() != recorded_links {
+            links
+                .write(&*self.config.filesystem, &links_path)
+                .map_err(|e| DirectoryError::Sumfile(format!("{}: {}", links_path.display(), e)))?;
+        }
+        if let Err(e) = stat_cache.write(&*self.config.filesystem, &stat_cache_path) {
+            let message = format!(
+                "could not write {}: {}",
+                stat_cache_path.to_str().unwrap(),
+                e
+            );
+            reporter.report(&Event::Warning {
+                directory,
+                message: &message,
+            });
+        }
+
+        let failed_paths: Vec<String> = report.failed.iter().map(|e| e.to_string()).collect();
+        report.skipped = queued - report.hashed.len() - report.failed.len();
+        if report.skipped > 0 {
+            reporter.report(&Event::DirectoryInterrupted {
+                directory,
+                failed_paths: &failed_paths,
+                skipped: report.skipped,
+            });
+        } else {
+            reporter.report(&Event::DirectoryFinished {
+                directory,
+                failed_paths: &failed_paths,
+            });
+        }
+
+        Ok(report)
+    }
+
+    /// Hashes files listed in the _algorithm_sum.txt of a directory again
+    /// and atomically replaces their lines with the new hashsums
+    ///
+    /// # Arguments
+    /// * `directory` The directory whose _algorithm_sum.txt lists the files
+    /// * `paths` The files to hash again, relative to the directory or absolute
+    pub fn rehash(
+        &self,
+        directory: &Path,
+        paths: &[String],
+    ) -> Result<RehashReport, DirectoryError> {
+        self.rehash_with(directory, paths, &NullReporter)
+    }
+
+    /// Hashes files listed in the _algorithm_sum.txt of a directory again, atomically replaces their lines
+    /// and reports every file to a Reporter as soon as it is hashed.
+    /// The `cmp` of the reported HashOutputs holds the hashsum the file was listed with before.
+    ///
+    /// # Arguments
+    /// * `directory` The directory whose _algorithm_sum.txt lists the files
+    /// * `paths` The files to hash again, relative to the directory or absolute
+    /// * `reporter` Receives the events that occur while rehashing the files
+    pub fn rehash_with(
+        &self,
+        directory: &Path,
+        paths: &[String],
+        reporter: &dyn Reporter,
+    ) -> Result<RehashReport, DirectoryError> {
+        let result = self.rehash_reporting(directory, paths, reporter);
+
+        if let Err(ref error) = result {
+            reporter.report(&Event::DirectoryFailed { directory, error });
+        }
+
+        result
+    }
+
+    /// Hashes files listed in the _algorithm_sum.txt of a directory again, see `rehash_with`
+    ///
+    /// # Arguments
+    /// * `directory` The directory whose _algorithm_sum.txt lists the files
+    /// * `paths` The files to hash again, relative to the directory or absolute
+    /// * `reporter` Receives the events that occur while rehashing the files
+    fn rehash_reporting(
+        &self,
+        directory: &Path,
+        paths: &[String],
+        reporter: &dyn Reporter,
+    ) -> Result<RehashReport, DirectoryError> {
+        let mut report = RehashReport::new(directory);
+
+        let _lock = super::lock::lock_directory(directory, &self.config, &mut |notice| {
+            reporter.report(&Event::Lock { notice: &notice })
+        })
+        .map_err(DirectoryError::Locked)?;
+
+        let sumfile_path = self
+            .config
+            .sumfile_path(directory)
+            .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+        let sumfile_error =
+            |e: io::Error| DirectoryError::Sumfile(format!("{}: {}", sumfile_path.display(), e));
+        let entries =
+            super::sumfile::read_entries(&sumfile_path, &self.config).map_err(&sumfile_error)?;
+        let links_path = self
+            .config
+            .links_path(directory)
+            .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+        let stat_cache_path = self
+            .config
+            .stat_cache_path(directory)
+            .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+        let links = RecordedLinks::read(&*self.config.filesystem, &links_path)
+            .map_err(|e| DirectoryError::Sumfile(format!("{}: {}", links_path.display(), e)))?;
+
+        // every line listing a file, by the normalised path of the file
+        let mut listed: HashMap<PathBuf, Vec<SumfileEntry>> = HashMap::new();
+        for entry in entries {
+            if let Some(key) = super::util::normalize_path(Path::new(&entry.path)) {
+                listed.entry(key).or_default().push(entry);
+            }
+        }
+
+        let base_directories = super::filter::base_directories(directory);
+        let mut to_rehash = Vec::new();
+        for path in paths {
+            let key = relative_key(Path::new(path), &base_directories);
+            match key.and_then(|key| listed.remove(&key)) {
+                Some(entries) => to_rehash.push(entries),
+                None => {
+                    let message = format!(
+                        "{} is not listed in {}, run update to hash it",
+                        path,
+                        sumfile_path.to_str().unwrap()
+                    );
+                    reporter.report(&Event::Warning {
+                        directory,
+                        message: &message,
+                    });
+                    report.not_listed.push(path.clone());
+                }
+            }
+        }
+
+        let total_bytes = super::util::count_bytes(
+            &*self.config.filesystem,
+            directory,
+            to_rehash.iter().map(|entries| &entries[0].path),
         );
+        let mut processed_bytes = 0;
+        reporter.report(&Event::DirectoryStarted {
+            directory,
+            total_bytes,
+        });
 
-        handle.join().unwrap();
+        let (sender, receiver) = channel();
+        let queued = to_rehash.len();
+        let mut lines_by_path = HashMap::new();
+
+        for entries in to_rehash {
+            self.pool.push(HashTask {
+                path: entries[0].path.clone(),
+                workdir: directory.to_path_buf(),
+                algorithm: self.config.algorithm,
+                filesystem: Arc::clone(&self.config.filesystem),
+                cmp: entries[0].hash.clone(),
+                result_chan: sender.clone(),
+                cancelled: Arc::clone(&self.config.cancelled),
+                symlinks: links.policy(&entries[0].path),
+            });
+            lines_by_path.insert(entries[0].path.clone(), entries);
+        }
 
-        producer_finished.store(true, Ordering::Relaxed);
+        drop(sender);
 
-        for handle in worker_handles {
-            handle.join().unwrap();
+        let mut replacements = HashMap::new();
+        for task_result in receiver {
+            match task_result {
+                Ok(output) => {
+                    processed_bytes += output.size;
+                    // a file listed in multiple lines gets the new hashsum in every line
+                    for entry in lines_by_path[&output.path].iter() {
+                        replacements.insert(
+                            entry.line_number,
+                            format!("{}  {}", output.hash, entry.path),
+                        );
+                    }
+
+                    reporter.report(&Event::FileHashed {
+                        directory,
+                        output: &output,
+                    });
+                    reporter.report(&Event::Progress {
+                        directory,
+                        processed_bytes,
+                        total_bytes,
+                    });
+                    report.rehashed.push(output);
+                }
+                Err(error) => {
+                    reporter.report(&Event::Error {
+                        directory,
+                        error: &error,
+                    });
+                    report.failed.push(error);
+                }
+            }
         }
-    } else {
-        let dirs_to_process = gather_directories_to_process(&opts);
 
-        execute_threads_subdir(opts, dirs_to_process)
+        if !replacements.is_empty() {
+            super::sumfile::replace_lines(&*self.config.filesystem, &sumfile_path, &replacements)
+                .map_err(&sumfile_error)?;
+            self.refresh_stats(
+                directory,
+                &stat_cache_path,
+                &report.rehashed,
+                &lines_by_path,
+                &links,
+                reporter,
+            );
+        }
+
+        let failed_paths: Vec<String> = report
+            .failed
+            .iter()
+            .map(|e| e.to_string())
+            .chain(report.not_listed.iter().cloned())
+            .collect();
+        report.skipped = queued - report.rehashed.len() - report.failed.len();
+        if report.skipped > 0 {
+            reporter.report(&Event::DirectoryInterrupted {
+                directory,
+                failed_paths: &failed_paths,
+                skipped: report.skipped,
+            });
+        } else {
+            reporter.report(&Event::DirectoryFinished {
+                directory,
+                failed_paths: &failed_paths,
+            });
+        }
+
+        Ok(report)
     }
-}
 
-/// Reads all directories in the working directory.
-/// Ignores all directories listed in .arkignore
-///
-/// # Arguments
-/// * `opts` Options object containing the working directory
-fn gather_directories_to_process(opts: &super::util::Options) -> Vec<PathBuf> {
-    let dir_entries = fs::read_dir(&opts.folder).unwrap();
-    let to_ignore = read_to_ignore(&opts);
+    /// Compares the size and modification time of the files listed in the _algorithm_sum.txt of a directory
+    /// with the ones recorded in its stat cache, without reading the files. Changed and missing files are reported,
+    /// files without a recorded stat for their hashsum get their current one, eg those listed by an earlier version.
+    /// Files skipped because of .arkignore or the selection are not checked.
+    ///
+    /// # Arguments
+    /// * `directory` The directory that is going to be updated
+    /// * `sumfile_path` Path to the _algorithm_sum.txt of the directory
+    /// * `stat_cache_path` Path to the stat cache of the directory
+    /// * `links` The symbolic links listed in the _algorithm_sum.txt that were hashed by their target path
+    /// * `reporter` The Reporter receiving the changed files
+    /// * `report` The report the changed files are added to
+    ///
+    /// # Returns
+    /// The stat cache to write after the update, still holding the recorded stats of the changed files,
+    /// so that they are reported again until they are hashed again.
+    fn check_stats(
+        &self,
+        directory: &Path,
+        sumfile_path: &Path,
+        stat_cache_path: &Path,
+        links: &RecordedLinks,
+        reporter: &dyn Reporter,
+        report: &mut UpdateReport,
+    ) -> Result<StatCache, DirectoryError> {
+        let recorded =
+            StatCache::read(&*self.config.filesystem, stat_cache_path).unwrap_or_else(|e| {
+                let message = format!(
+                    "could not read {}: {}",
+                    stat_cache_path.to_str().unwrap(),
+                    e
+                );
+                reporter.report(&Event::Warning {
+                    directory,
+                    message: &message,
+                });
+                StatCache::default()
+            });
+        let entries = super::sumfile::read_entries(sumfile_path, &self.config)
+            .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
 
-    if opts.loglevel_debug() {
-        println!("Dirs to ignore: {:?}", to_ignore);
+        let mut stat_cache = StatCache::default();
+        let mut ignore = IgnoreTree::new(Arc::clone(&self.config.filesystem), &self.config.root);
+        for entry in entries {
+            if stat_cache.stats.contains_key(&entry.path) {
+                continue;
+            }
+            let path = directory.join(&entry.path);
+            let metadata = self.stat(&path, links.policy(&entry.path)).ok();
+            let skipped = ignore.is_ignored(&path, false)
+                || metadata.as_ref().is_some_and(|metadata| {
+                    self.config.selection.reject(&path, metadata).is_some()
+                });
+
+            match recorded.check(&entry.path, &entry.hash, metadata.as_ref()) {
+                Some(change) if !skipped => {
+                    reporter.report(&Event::Changed {
+                        directory,
+                        path: Path::new(&entry.path),
+                        change: &change,
+                    });
+                    report.changed.push((entry.path.clone(), change));
+                    let stat = recorded.stats[&entry.path].clone();
+                    stat_cache.stats.insert(entry.path, stat);
+                }
+                Some(_) => {
+                    let stat = recorded.stats[&entry.path].clone();
+                    stat_cache.stats.insert(entry.path, stat);
+                }
+                None => {
+                    if let Some(metadata) = metadata {
+                        let stat = FileStat::new(&entry.hash, &metadata);
+                        stat_cache.stats.insert(entry.path, stat);
+                    }
+                }
+            }
+        }
+
+        Ok(stat_cache)
     }
 
-    if opts.loglevel_info() && !to_ignore.is_empty() {
-        let now: DateTime<chrono::Local> = chrono::Local::now();
-        for ignored_dir in to_ignore.iter().by_ref() {
-            println!(
-                "[{}] Ignoring Directory {}",
-                now,
-                ignored_dir.to_str().unwrap()
+    /// Records the current size and modification time of rehashed files in the stat cache,
+    /// so that update does not report them as changed anymore
+    ///
+    /// # Arguments
+    /// * `directory` The directory containing the _algorithm_sum.txt
+    /// * `stat_cache_path` Path to the stat cache of the directory
+    /// * `rehashed` The files that were hashed again
+    /// * `lines_by_path` Every line listing a rehashed file, by the path the file was hashed with
+    /// * `links` The symbolic links listed in the _algorithm_sum.txt that were hashed by their target path
+    /// * `reporter` The Reporter receiving a warning if the stat cache can not be updated
+    fn refresh_stats(
+        &self,
+        directory: &Path,
+        stat_cache_path: &Path,
+        rehashed: &[HashOutput],
+        lines_by_path: &HashMap<String, Vec<SumfileEntry>>,
+        links: &RecordedLinks,
+        reporter: &dyn Reporter,
+    ) {
+        let filesystem = &*self.config.filesystem;
+        let refreshed = StatCache::read(filesystem, stat_cache_path).and_then(|mut stat_cache| {
+            for output in rehashed {
+                for entry in lines_by_path[&output.path].iter() {
+                    stat_cache.refresh(
+                        filesystem,
+                        directory,
+                        &entry.path,
+                        &output.hash,
+                        links.policy(&entry.path),
+                    );
+                }
+            }
+            stat_cache.write(filesystem, stat_cache_path)
+        });
+
+        if let Err(e) = refreshed {
+            let message = format!(
+                "could not update {}: {}",
+                stat_cache_path.to_str().unwrap(),
+                e
             );
+            reporter.report(&Event::Warning {
+                directory,
+                message: &message,
+            });
         }
     }
 
-    let mut dirs_to_process = Vec::new();
-    for entry in dir_entries {
-        let entry = entry.unwrap();
-        let metadata = entry.metadata().unwrap();
+    /// Returns the properties of a file the way it is hashed:
+    /// with SymlinkPolicy::Record those of a symbolic link itself, otherwise those of its target
+    ///
+    /// # Arguments
+    /// * `path` Path to the file
+    /// * `symlinks` The policy the file is hashed with
+    fn stat(&self, path: &Path, symlinks: SymlinkPolicy) -> io::Result<Metadata> {
+        if symlinks == SymlinkPolicy::Record {
+            self.config.filesystem.symlink_metadata(path)
+        } else {
+            self.config.filesystem.metadata(path)
+        }
+    }
+
+    /// Returns the paths of the files in a directory that are not listed in its _algorithm_sum.txt.
+    /// Paths skipped because of .arkignore, the selection or the settle time are reported.
+    ///
+    /// # Arguments
+    /// * `directory` The directory that is going to be updated
+    /// * `reporter` The Reporter receiving the skipped paths
+    fn new_files(
+        &self,
+        directory: &Path,
+        reporter: &dyn Reporter,
+    ) -> Result<Vec<String>, DirectoryError> {
+        let mut dirwalker = super::util::DirWalker::for_config(directory, &self.config);
+        let filter = super::filter::Filter::new_unlocked(
+            BufReader::new(&mut dirwalker),
+            directory,
+            &self.config,
+        )
+        .map_err(DirectoryError::Sumfile)?;
+        let mut new_files: Vec<String> = filter.collect();
 
-        if metadata.is_dir() && !to_ignore.contains(&entry.path()) {
-            dirs_to_process.push(entry.path());
+        for (path, rule) in dirwalker.ignored() {
+            reporter.report(&Event::Ignored {
+                directory,
+                path,
+                rule,
+            });
         }
+        for (path, reason) in dirwalker.skipped() {
+            reporter.report(&Event::Skipped {
+                directory,
+                path,
+                reason,
+            });
+        }
+
+        if let Some(settle) = self.config.settle {
+            let now = SystemTime::now();
+            new_files.retain(|path| {
+                let modified = self
+                    .config
+                    .filesystem
+                    .metadata(&directory.join(path))
+                    .ok()
+                    .and_then(|metadata| metadata.modified);
+                // files modified in the future, eg because of a wrong clock, count as recently modified
+                let settled = match modified {
+                    Some(modified) => now.duration_since(modified).is_ok_and(|age| age >= settle),
+                    None => true,
+                };
+                if !settled {
+                    reporter.report(&Event::Skipped {
+                        directory,
+                        path: Path::new(path),
+                        reason: &SkipReason::Unsettled(settle),
+                    });
+                }
+                settled
+            });
+        }
+
+        Ok(new_files)
     }
 
-    dirs_to_process
+    /// Determines which files an update of a directory would hash, without hashing them.
+    /// Nothing is written, neither to _algorithm_sum.txt nor a lockfile.
+    ///
+    /// # Arguments
+    /// * `directory` The directory that would be updated
+    pub fn plan(&self, directory: &Path) -> Result<UpdatePlan, DirectoryError> {
+        let mut plan = UpdatePlan {
+            directory: directory.to_path_buf(),
+            files: Vec::new(),
+        };
+
+        for path in self.new_files(directory, &NullReporter)? {
+            let size = self
+                .config
+                .filesystem
+                .metadata(&directory.join(&path))
+                .map(|metadata| metadata.len)
+                .unwrap_or(0);
+            plan.files.push((path, size));
+        }
+        plan.files.sort();
+
+        Ok(plan)
+    }
+
+    /// Measures how fast the files of some plans can be read and hashed, by hashing
+    /// the beginning of their files for a short time
+    ///
+    /// # Arguments
+    /// * `plans` The plans containing the files to sample
+    ///
+    /// # Returns
+    /// The measured throughput in bytes per second, or None if nothing could be read.
+    pub fn measure_throughput(&self, plans: &[UpdatePlan]) -> Option<f64> {
+        const SAMPLE_BYTES: u64 = 64 * 1024 * 1024;
+        const SAMPLE_TIME: Duration = Duration::from_secs(2);
+        const BUFFER_SIZE: usize = 64 * 1024;
+
+        let start = Instant::now();
+        let mut buffer = vec![0; BUFFER_SIZE];
+        let mut sampled_bytes = 0;
+        let mut hasher = self.config.algorithm.hasher();
+
+        'files: for plan in plans {
+            for (path, _) in plan.files.iter() {
+                let mut file = match self.config.filesystem.open(&plan.directory.join(path)) {
+                    Ok(file) => file,
+                    Err(_) => continue,
+                };
+
+                loop {
+                    if sampled_bytes >= SAMPLE_BYTES || start.elapsed() >= SAMPLE_TIME {
+                        break 'files;
+                    }
+
+                    match file.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(n) => {
+                            hasher.input(&buffer[..n]);
+                            sampled_bytes += n as u64;
+                        }
+                    }
+                }
+            }
+        }
+
+        let elapsed = start.elapsed().as_secs_f64();
+        if sampled_bytes == 0 || elapsed <= 0.0 {
+            return None;
+        }
+
+        Some(sampled_bytes as f64 / elapsed)
+    }
 }
 
-/// Reads the .arkignore file and returns a Vector of directories that should be ignored when updating hashes.
+/// Updates the _algorithm_sum.txt files of some directories
 ///
 /// # Arguments
-/// * `opts` Options object containing the working directory
-fn read_to_ignore(opts: &super::util::Options) -> Vec<PathBuf> {
-    let to_ignore =
-        super::util::read_paths_from_file(&format!("{}{}", &opts.folder, "/.arkignore"));
-    let mut to_ignore_prepended = Vec::new();
-
-    for path in to_ignore {
-        if !path.to_str().unwrap().starts_with("./") {
-            let new_path = PathBuf::from(format!("./{}", path.to_str().unwrap()));
-            to_ignore_prepended.push(new_path);
-        } else {
-            to_ignore_prepended.push(path);
+///
+/// * `opts` An Options object containing information about the program behavior
+///
+/// # Returns
+/// The exit code the program should return.
+pub fn update_directories(opts: super::util::Options) -> i32 {
+    if opts.dry_run {
+        return plan_directories(&opts);
+    }
+
+    super::util::cancel_on_interrupt(&opts.config.cancelled);
+    let console = ConsoleReporter { opts: opts.clone() };
+
+    let _run_lock = match super::lock::lock_run(&opts.config, &mut |notice| {
+        console.report(&Event::Lock { notice: &notice })
+    }) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 3;
+        }
+    };
+
+    let dirs_to_process = if opts.subdir_mode {
+        gather_directories_to_process(&opts)
+    } else {
+        vec![opts.config.root.clone()]
+    };
+
+    let reporter: Arc<dyn Reporter> = if opts.loglevel_progress() {
+        let longest_folder = dirs_to_process
+            .iter()
+            .map(|dir| dir.to_str().unwrap().len())
+            .max()
+            .unwrap_or(0);
+        let progress = ProgressReporter::new(&dirs_to_process, longest_folder, "updated");
+        progress.start();
+        Arc::new(progress)
+    } else {
+        Arc::new(console)
+    };
+
+    let updater = Arc::new(Updater::new(opts.config.clone()));
+    let mut handles = Vec::new();
+
+    for entry in dirs_to_process {
+        let updater = Arc::clone(&updater);
+        let reporter = Arc::clone(&reporter);
+
+        handles.push(thread::spawn(move || {
+            match updater.update_with(&entry, &*reporter) {
+                // files that changed since they were hashed are reported like a failed verify
+                Ok(report) => (
+                    if report.changed.is_empty() { 0 } else { 1 },
+                    report.hashed.len(),
+                    report.skipped,
+                ),
+                Err(e) => (e.exit_code(), 0, 0),
+            }
+        }));
+    }
+
+    let mut exit_code = 0;
+    let mut hashed = 0;
+    let mut skipped = 0;
+    for handle in handles {
+        let (code, dir_hashed, dir_skipped) = handle.join().unwrap();
+        if code != 0 {
+            exit_code = code;
         }
+        hashed += dir_hashed;
+        skipped += dir_skipped;
     }
 
-    to_ignore_prepended
+    // restore the terminal before printing the summary
+    drop(reporter);
+
+    if opts.config.is_cancelled() {
+        let now: DateTime<chrono::Local> = chrono::Local::now();
+        eprintln!(
+            "[{}] Interrupted: {} files hashed, {} files not hashed yet, run update again to hash them",
+            now, hashed, skipped
+        );
+        return 130;
+    }
+
+    exit_code
 }
 
-/// Starts a thread for every directory in dirs_to_process as a HashTask producer.
-/// Launches as many worker threads as opts.num_threads or number of logical cpus.
+/// Hashes the files given on the commandline or on STDIN again
+/// and replaces their lines in the _algorithm_sum.txt files
 ///
 /// # Arguments
-/// * `opts` Options object
-/// * `dirs_to_process` Vector of directory paths that have to be updated
-fn execute_threads_subdir(opts: super::util::Options, dirs_to_process: Vec<PathBuf>) {
-    let mut producer_handles = Vec::new();
-    let mut worker_handles = Vec::new();
-    let opts = Arc::new(opts);
-    let q = Arc::new(Injector::new());
-    let producer_finished = Arc::new(AtomicBool::new(false));
-    let num_threads = match opts.num_threads {
-        0 => num_cpus::get(),
-        _ => opts.num_threads,
+///
+/// * `opts` An Options object containing information about the program behavior
+///
+/// # Returns
+/// The exit code the program should return.
+pub fn rehash_directories(opts: super::util::Options) -> i32 {
+    super::util::cancel_on_interrupt(&opts.config.cancelled);
+    let reporter = RehashReporter { opts: opts.clone() };
+
+    let _run_lock = match super::lock::lock_run(&opts.config, &mut |notice| {
+        reporter.report(&Event::Lock { notice: &notice })
+    }) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 3;
+        }
     };
 
-    for entry in dirs_to_process {
-        if opts.loglevel_info() {
-            let now: DateTime<chrono::Local> = chrono::Local::now();
-            println!("[{}] Updating Directory {}", now, entry.to_str().unwrap());
+    let paths = if opts.rehash_paths.is_empty() {
+        let stdin = io::stdin();
+        read_paths(stdin.lock(), if opts.null_data { b'\0' } else { b'\n' })
+    } else {
+        opts.rehash_paths.clone()
+    };
+
+    let mut exit_code = 0;
+    let paths_by_directory = if opts.subdir_mode {
+        let mut paths_by_directory = BTreeMap::new();
+        let base_directories = super::filter::base_directories(&opts.config.root);
+
+        for path in paths {
+            match split_subdirectory(Path::new(&path), &base_directories) {
+                Some((subdirectory, relative)) => paths_by_directory
+                    .entry(opts.config.root.join(subdirectory))
+                    .or_insert_with(Vec::new)
+                    .push(relative),
+                None => {
+                    let now: DateTime<chrono::Local> = chrono::Local::now();
+                    eprintln!(
+                        "[{}] {} is not inside a subdirectory of {}",
+                        now,
+                        path,
+                        opts.config.root.to_str().unwrap()
+                    );
+                    exit_code = 1;
+                }
+            }
         }
 
-        let opts = Arc::clone(&opts);
-        let myq = Arc::clone(&q);
+        paths_by_directory
+    } else {
+        let mut paths_by_directory = BTreeMap::new();
+        paths_by_directory.insert(opts.config.root.clone(), paths);
+        paths_by_directory
+    };
 
-        let handle = thread::spawn(move || {
-            update_hashsums(&entry, opts, myq);
-        });
+    let updater = Updater::new(opts.config.clone());
+    let mut rehashed = 0;
+    let mut skipped = 0;
 
-        producer_handles.push(handle);
+    for (directory, paths) in paths_by_directory {
+        match updater.rehash_with(&directory, &paths, &reporter) {
+            Ok(report) => {
+                if !report.is_ok() {
+                    exit_code = 1;
+                }
+                rehashed += report.rehashed.len();
+                skipped += report.skipped;
+            }
+            Err(e) => exit_code = e.exit_code(),
+        }
     }
 
-    super::util::execute_workers(
-        num_threads,
-        Arc::clone(&q),
-        Arc::clone(&producer_finished),
-        &mut worker_handles,
-    );
-
-    for handle in producer_handles {
-        handle.join().unwrap();
+    if opts.config.is_cancelled() {
+        let now: DateTime<chrono::Local> = chrono::Local::now();
+        eprintln!(
+            "[{}] Interrupted: {} files rehashed, {} files not rehashed",
+            now, rehashed, skipped
+        );
+        return 130;
     }
 
-    producer_finished.store(true, Ordering::Relaxed);
+    exit_code
+}
+
+/// Reads paths separated by a delimiter, skipping empty ones
+///
+/// # Arguments
+/// * `input` The BufRead containing the paths
+/// * `delimiter` The byte that terminates every path
+fn read_paths<R: BufRead>(input: R, delimiter: u8) -> Vec<String> {
+    input
+        .split(delimiter)
+        .map_while(Result::ok)
+        .map(|path| {
+            String::from_utf8_lossy(&path)
+                .trim_end_matches('\r')
+                .to_string()
+        })
+        .filter(|path| !path.is_empty())
+        .collect()
+}
+
+/// Splits a path below the folder of subdir mode into the subdirectory containing it
+/// and the path relative to that subdirectory
+///
+/// # Arguments
+/// * `path` The path to split, relative to the folder or absolute
+/// * `base_directories` The absolute forms of the folder, see `filter::base_directories`
+///
+/// # Returns
+/// None if the path does not point into a subdirectory of the folder.
+fn split_subdirectory(path: &Path, base_directories: &[PathBuf]) -> Option<(PathBuf, String)> {
+    let relative = relative_key(path, base_directories)?;
+    let mut components = relative.components();
+    let subdirectory = PathBuf::from(components.next()?.as_os_str());
+    let relative = components.as_path().to_str()?.to_string();
 
-    for handle in worker_handles {
-        handle.join().unwrap();
+    if relative.is_empty() {
+        None
+    } else {
+        Some((subdirectory, relative))
     }
 }
 
-/// Updates the _algorithm_sum.txt in a directory
+/// Lists the files update mode would hash in some directories, together with their sizes,
+/// the totals per directory and an estimate of the time hashing them would take.
+/// Nothing is written.
 ///
 /// # Arguments
 ///
-/// * `path` The path to the directory that is going to be updated
 /// * `opts` An Options object containing information about the program behavior
-/// * `myq` An Injector queue that is used to push the generated hashtasks to the workers and receive the results
-fn update_hashsums(
-    path: &PathBuf,
-    opts: Arc<super::util::Options>,
-    myq: Arc<Injector<super::util::HashTask>>,
-) {
-    if dir_is_empty(path) {
-        return;
-    }
-
-    let dirwalker = super::util::DirWalker::new(&path, opts.subdir_mode);
-    let reader = BufReader::new(dirwalker);
-
-    let filter = super::filter::Filter::new(reader, path.to_str().unwrap(), &opts);
-
-    let (sender, receiver) = channel();
-
-    if let Ok(filter) = filter {
-        let mut filepath = path.clone();
-        filepath.push(format!("{}sum.txt", opts.algorithm));
-        let file = OpenOptions::new().create(true).append(true).open(filepath);
-
-        if let Ok(mut file) = file {
-            for line in filter {
-                let task = super::util::HashTask {
-                    path: line,
-                    workdir: PathBuf::from(path),
-                    opts: Arc::clone(&opts),
-                    cmp: String::new(),
-                    result_chan: sender.clone(),
-                };
+///
+/// # Returns
+/// The exit code the program should return.
+fn plan_directories(opts: &super::util::Options) -> i32 {
+    let dirs_to_process = if opts.subdir_mode {
+        gather_directories_to_process(opts)
+    } else {
+        vec![opts.config.root.clone()]
+    };
 
-                myq.push(task);
+    let updater = Updater::new(opts.config.clone());
+    let mut plans = Vec::new();
+    let mut exit_code = 0;
+
+    for entry in dirs_to_process {
+        let plan = match updater.plan(&entry) {
+            Ok(plan) => plan,
+            Err(e) => {
+                let now: DateTime<chrono::Local> = chrono::Local::now();
+                eprintln!("[{}] {}: {}", now, entry.to_str().unwrap(), e);
+                exit_code = e.exit_code();
+                continue;
             }
+        };
 
-            drop(sender);
+        if opts.loglevel_info() {
+            let now: DateTime<chrono::Local> = chrono::Local::now();
+            for &(ref path, size) in plan.files.iter() {
+                println!(
+                    "[{}] {}: would hash {} ({})",
+                    now,
+                    entry.to_str().unwrap(),
+                    path,
+                    super::util::format_bytes(size)
+                );
+            }
+            println!(
+                "[{}] Directory {} would hash {} files, {}",
+                now,
+                entry.to_str().unwrap(),
+                plan.files.len(),
+                super::util::format_bytes(plan.total_bytes())
+            );
+        }
 
-            for task_result in receiver {
-                if let Ok((hashline, _)) = task_result {
-                    if let Err(e) = write!(file, "{}", hashline) {
-                        eprintln!("Error writing to file: {}", e);
-                    }
+        plans.push(plan);
+    }
 
-                    if opts.loglevel_info() {
-                        let now: DateTime<chrono::Local> = chrono::Local::now();
-                        print!("[{}] {}: {}", now, path.to_str().unwrap(), hashline);
-                    }
-                } else {
-                    let now: DateTime<chrono::Local> = chrono::Local::now();
-                    eprintln!(
+    if opts.loglevel_info() {
+        let files: usize = plans.iter().map(|plan| plan.files.len()).sum();
+        let total_bytes: u64 = plans.iter().map(|plan| plan.total_bytes()).sum();
+        let estimate = match updater.measure_throughput(&plans) {
+            Some(throughput) => format!(
+                "estimated time {} at {}/s",
+                super::util::format_duration((total_bytes as f64 / throughput).ceil() as u64),
+                super::util::format_bytes(throughput as u64)
+            ),
+            None => String::from("estimated time unknown"),
+        };
+
+        let now: DateTime<chrono::Local> = chrono::Local::now();
+        println!(
+            "[{}] Dry run: would hash {} files, {} in total, {}",
+            now,
+            files,
+            super::util::format_bytes(total_bytes),
+            estimate
+        );
+    }
+
+    exit_code
+}
+
+/// Reads all directories in the working directory.
+/// Ignores all directories matched by .arkignore
+///
+/// # Arguments
+/// * `opts` Options object containing the working directory
+fn gather_directories_to_process(opts: &super::util::Options) -> Vec<PathBuf> {
+    let subdirectories = super::util::subdirectories(&opts.config).unwrap();
+    let ignore = IgnoreRules::read(&*opts.config.filesystem, &opts.config.root);
+
+    if opts.loglevel_debug() {
+        println!("Ignore rules: {:?}", ignore);
+    }
+
+    let mut dirs_to_process = Vec::new();
+    for path in subdirectories {
+        if ignore.is_ignored(&path, true) {
+            if opts.loglevel_info() {
+                let now: DateTime<chrono::Local> = chrono::Local::now();
+                println!(
+                    "[{}] Ignoring Directory {}",
+                    now,
+                    Path::new(".")
+                        .join(path.file_name().unwrap())
+                        .to_str()
+                        .unwrap()
+                );
+            }
+        } else {
+            dirs_to_process.push(path);
+        }
+    }
+
+    dirs_to_process
+}
+
+/// A Reporter printing the progress of update mode to the console
+struct ConsoleReporter {
+    /// The Options object determining subdir_mode and loglevel
+    opts: super::util::Options,
+}
+
+impl Reporter for ConsoleReporter {
+    fn report(&self, event: &Event) {
+        let now: DateTime<chrono::Local> = chrono::Local::now();
+
+        match *event {
+            Event::DirectoryStarted { directory, .. } => {
+                if self.opts.subdir_mode && self.opts.loglevel_info() {
+                    println!(
+                        "[{}] Updating Directory {}",
+                        now,
+                        directory.to_str().unwrap()
+                    );
+                }
+            }
+            Event::FileHashed { directory, output } => {
+                if self.opts.loglevel_info() {
+                    print!(
                         "[{}] {}: {}",
                         now,
+                        directory.to_str().unwrap(),
+                        output.hashline()
+                    );
+                }
+            }
+            Event::Error { directory, error } => {
+                eprintln!(
+                    "[{}] {}: could not hash file! {}",
+                    now,
+                    directory.to_str().unwrap(),
+                    error
+                );
+            }
+            Event::DirectoryFinished { directory, .. } => {
+                if self.opts.loglevel_info() {
+                    println!(
+                        "[{}] Directory {} Updated",
+                        now,
+                        directory.to_str().unwrap()
+                    );
+                }
+            }
+            Event::DirectoryInterrupted {
+                directory, skipped, ..
+            } => {
+                eprintln!(
+                    "[{}] Directory {} interrupted, {} files were not hashed",
+                    now,
+                    directory.to_str().unwrap(),
+                    skipped
+                );
+            }
+            Event::DirectoryFailed { directory, error } => {
+                eprintln!("[{}] {}: {}", now, directory.to_str().unwrap(), error);
+            }
+            Event::Warning { directory, message } => {
+                eprintln!("[{}] {}: {}", now, directory.to_str().unwrap(), message);
+            }
+            Event::Ignored {
+                directory,
+                path,
+                rule,
+            } => {
+                if self.opts.loglevel_debug() {
+                    println!(
+                        "[{}] {}: ignoring {} ({})",
+                        now,
+                        directory.to_str().unwrap(),
                         path.to_str().unwrap(),
-                        "could not hash file!"
+                        rule
                     );
                 }
             }
+            Event::Changed {
+                directory,
+                path,
+                change,
+            } => {
+                eprintln!(
+                    "[{}] {}: {} {} since it was hashed",
+                    now,
+                    directory.to_str().unwrap(),
+                    path.to_str().unwrap(),
+                    change
+                );
+            }
+            Event::Skipped {
+                directory,
+                path,
+                reason,
+            } => {
+                if reason.is_warning() {
+                    eprintln!(
+                        "[{}] {}: skipping {}: {}",
+                        now,
+                        directory.to_str().unwrap(),
+                        path.to_str().unwrap(),
+                        reason
+                    );
+                } else if self.opts.loglevel_debug() {
+                    println!(
+                        "[{}] {}: skipping {}: {}",
+                        now,
+                        directory.to_str().unwrap(),
+                        path.to_str().unwrap(),
+                        reason
+                    );
+                }
+            }
+            Event::Lock { notice } => {
+                if self.opts.loglevel_info() {
+                    println!("[{}] {}", now, notice);
+                }
+            }
+            Event::Mismatch { .. } | Event::Progress { .. } => {}
         }
     }
+}
 
-    if opts.loglevel_info() {
+/// A Reporter printing the old and new hashsums of rehash mode to the console, depending on the loglevel
+struct RehashReporter {
+    /// The Options object determining the loglevel
+    opts: super::util::Options,
+}
+
+impl Reporter for RehashReporter {
+    fn report(&self, event: &Event) {
         let now: DateTime<chrono::Local> = chrono::Local::now();
-        println!("[{}] Directory {} Updated", now, path.to_str().unwrap());
+
+        match *event {
+            Event::FileHashed { directory, output } => {
+                if self.opts.loglevel_info() {
+                    if output.hash == output.cmp {
+                        println!(
+                            "[{}] {}: {} unchanged: {}",
+                            now,
+                            directory.to_str().unwrap(),
+                            output.path,
+                            output.hash
+                        );
+                    } else {
+                        println!(
+                            "[{}] {}: {} rehashed: {} -> {}",
+                            now,
+                            directory.to_str().unwrap(),
+                            output.path,
+                            output.cmp,
+                            output.hash
+                        );
+                    }
+                }
+            }
+            Event::Error { directory, error } => {
+                eprintln!("[{}] {}: {}", now, directory.to_str().unwrap(), error);
+            }
+            Event::DirectoryInterrupted {
+                directory, skipped, ..
+            } => {
+                eprintln!(
+                    "[{}] Directory {} interrupted, {} files were not rehashed",
+                    now,
+                    directory.to_str().unwrap(),
+                    skipped
+                );
+            }
+            Event::DirectoryFailed { directory, error } => {
+                eprintln!("[{}] {}: {}", now, directory.to_str().unwrap(), error);
+            }
+            Event::Warning { directory, message } => {
+                eprintln!("[{}] {}: {}", now, directory.to_str().unwrap(), message);
+            }
+            Event::Lock { notice } => {
+                if self.opts.loglevel_info() {
+                    println!("[{}] {}", now, notice);
+                }
+            }
+            Event::DirectoryStarted { .. }
+            | Event::Ignored { .. }
+            | Event::Skipped { .. }
+            | Event::Changed { .. }
+            | Event::Mismatch { .. }
+            | Event::Progress { .. }
+            | Event::DirectoryFinished { .. } => {}
+        }
     }
 }
 
-fn dir_is_empty(path: &PathBuf) -> bool {
-    let mut dirwalker = super::util::DirWalker::new(&path, false);
-    match dirwalker.next() {
-        Some(_) => false,
-        None => true,
+/// Returns the normalised path of a file relative to a directory.
+/// Relative paths are interpreted relative to the directory, absolute paths have to lie inside of it.
+///
+/// # Arguments
+/// * `path` The path to the file
+/// * `base_directories` The absolute forms of the directory, see `filter::base_directories`
+///
+/// # Returns
+/// None if the path points outside of the directory.
+fn relative_key(path: &Path, base_directories: &[PathBuf]) -> Option<PathBuf> {
+    if !path.is_absolute() {
+        return super::util::normalize_path(path);
     }
+
+    let path = super::util::normalize_path(path)?;
+    base_directories
+        .iter()
+        .find_map(|base| path.strip_prefix(base).ok())
+        .map(Path::to_path_buf)
+}
+
+/// Returns whether a directory contains no files except ignored ones, including its subdirectories
+///
+/// # Arguments
+/// * `path` The path to the directory
+/// * `config` Config object containing the filesystem and the folder of .arkignore
+fn dir_is_empty(path: &Path, config: &Config) -> bool {
+    let mut dirwalker = super::util::DirWalker::for_config(path, config);
+    dirwalker.next().is_none()
 }
@@ -1,34 +1,32 @@
 //! This module describes a set of utilities that will be used throughout the other modules
 
 extern crate crossbeam_deque;
-extern crate digest;
 extern crate hex;
-extern crate md5;
-extern crate regex;
-extern crate sha1;
-extern crate sha2;
 
+#[cfg(unix)]
+extern crate libc;
 #[cfg(unix)]
 extern crate termios;
 
 #[cfg(windows)]
 extern crate winapi;
 
-use self::regex::Regex;
-use std::fs::{self, OpenOptions};
-use std::io::{self, BufRead, BufReader, Error, Read};
-use std::path::PathBuf;
-
-use self::digest::{Digest, DynDigest};
-use self::md5::Md5;
-use self::sha1::Sha1;
-use self::sha2::{Sha224, Sha256, Sha384, Sha512};
+use std::collections::HashSet;
+use std::io::{self, Error, Read};
+use std::path::{Component, Path, PathBuf};
 
 use self::crossbeam_deque::{Injector, Steal};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::Sender;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::thread::JoinHandle;
+use std::time::{Duration, SystemTime};
+
+use super::config::{Algorithm, Config, SymlinkPolicy};
+use super::filesystem::{FileSystem, Metadata, SpecialFile};
+use super::ignore::{IgnoreRule, IgnoreRules};
+use super::lock::LockError;
+use super::selection::Selection;
 
 use std::fmt;
 
@@ -38,6 +36,29 @@ pub enum Mode {
     Filter,
     Update,
     Verify,
+    Export,
+    Lint,
+    Rehash,
+    Accept,
+}
+
+/// The format the hashsums will be written in when exporting
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExportFormat {
+    Hashdeep,
+    Json,
+    Csv,
+}
+
+/// The paths the filter mode will print
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FilterOutput {
+    /// Only paths that have not been hashed yet
+    New,
+    /// Only paths that have been hashed before
+    Hashed,
+    /// Every path, prefixed with its status
+    Annotate,
 }
 
 /// The level of detail the program will be logging
@@ -55,24 +76,137 @@ pub struct HashTask {
     pub path: String,
     /// The desired working directory of the worker thread
     pub workdir: PathBuf,
-    /// A reference to an Options struct containing various parameters
-    pub opts: Arc<Options>,
+    /// The hashing algorithm to use
+    pub algorithm: Algorithm,
+    /// The filesystem containing the file
+    pub filesystem: Arc<dyn FileSystem>,
     /// A string containing the hash that the file should match
     pub cmp: String,
     /// A channel to return the calculated hash and cmp to the task generator
-    pub result_chan: Sender<Result<(String, String), HashError>>,
+    pub result_chan: Sender<HashResult>,
+    /// Indicates that the file should be skipped instead of hashed, no result is sent then
+    pub cancelled: Arc<AtomicBool>,
+    /// Whether a symbolic link is hashed by its target path instead of the contents of its target
+    pub symlinks: SymlinkPolicy,
 }
 
+/// The successful result of a HashTask
+#[derive(Debug, Clone)]
+pub struct HashOutput {
+    /// Path to the file that was hashed, relative to the working directory
+    pub path: String,
+    /// The calculated hashsum
+    pub hash: String,
+    /// The hash the file should match, as given in the HashTask
+    pub cmp: String,
+    /// The number of bytes that were hashed
+    pub size: u64,
+}
+
+impl HashOutput {
+    /// Returns the line describing the file in an _algorithm_sum.txt, including the newline
+    pub fn hashline(&self) -> String {
+        format!("{}  {}\n", self.hash, self.path)
+    }
+}
+
+/// The result of a HashTask
+pub type HashResult = Result<HashOutput, HashError>;
+
 /// An error that occurs when a file cannot be hashed
 #[derive(Debug)]
 pub struct HashError {
-    source: io::Error,
-    path: String,
+    /// The error that occured while reading the file
+    pub source: io::Error,
+    /// Path to the file, relative to the working directory
+    pub path: String,
+}
+
+/// A pool of worker threads that hash the files of HashTasks.
+/// The workers are stopped when the pool is dropped.
+pub struct HashPool {
+    /// The queue carrying the HashTask objects to the workers
+    queue: Arc<Injector<HashTask>>,
+    /// Indicates that no new HashTasks will be pushed into the queue
+    producer_finished: Arc<AtomicBool>,
+    /// The handles to the worker threads
+    worker_handles: Vec<JoinHandle<()>>,
+}
+
+impl HashPool {
+    /// Starts a number of worker threads ready for hashing files
+    ///
+    /// # Arguments
+    /// * `num_threads` Number of worker threads to start
+    pub fn new(num_threads: usize) -> HashPool {
+        let queue = Arc::new(Injector::new());
+        let producer_finished = Arc::new(AtomicBool::new(false));
+        let mut worker_handles = Vec::new();
+
+        execute_workers(
+            num_threads,
+            Arc::clone(&queue),
+            Arc::clone(&producer_finished),
+            &mut worker_handles,
+        );
+
+        HashPool {
+            queue,
+            producer_finished,
+            worker_handles,
+        }
+    }
+
+    /// Queues a file to be hashed by the next free worker
+    ///
+    /// # Arguments
+    /// * `task` The HashTask describing the file
+    pub fn push(&self, task: HashTask) {
+        self.queue.push(task);
+    }
+}
+
+impl Drop for HashPool {
+    fn drop(&mut self) {
+        self.producer_finished.store(true, Ordering::Relaxed);
+
+        for handle in self.worker_handles.drain(..) {
+            let _ = handle.join();
+        }
+    }
 }
 
 impl fmt::Display for HashError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}: {}", self.source.to_string(), self.path)
+        write!(f, "{}: {}", self.source, self.path)
+    }
+}
+
+/// An error that prevents a directory from being updated or verified
+#[derive(Debug)]
+pub enum DirectoryError {
+    /// The _algorithm_sum.txt is locked by another process
+    Locked(LockError),
+    /// The _algorithm_sum.txt could not be read or written, or contains malformed lines in strict mode
+    Sumfile(String),
+}
+
+impl DirectoryError {
+    /// Returns the exit code the program should return because of this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            DirectoryError::Locked(_) => 3,
+            DirectoryError::Sumfile(_) => 1,
+        }
+    }
+}
+
+impl fmt::Display for DirectoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DirectoryError::Locked(e) => write!(f, "{}", e),
+            DirectoryError::Sumfile(message) => write!(f, "{}", message),
+        }
     }
 }
 
@@ -85,18 +219,28 @@ pub struct Options {
     pub version_info: bool,
     /// My name
     pub program_name: String,
-    /// The hashing algorithm to use
-    pub algorithm: String,
+    /// The settings of the hashing engine: algorithm, folder to operate on, threads, locking and strictness
+    pub config: Config,
     /// Whether or not it will be operated on a single folder or every subfolder
     pub subdir_mode: bool,
     /// The mode the program will operate in
     pub mode: Mode,
     /// The level of detail the program will be logging
     pub log_level: LogLevel,
-    /// Maximum number of threads to spawn
-    pub num_threads: usize,
-    /// The folder to operate on
-    pub folder: String,
+    /// The format to use in export mode
+    pub export_format: ExportFormat,
+    /// The file to write output to instead of STDOUT
+    pub output: Option<String>,
+    /// Whether the paths in filter mode are separated by NUL instead of newline characters
+    pub null_data: bool,
+    /// The paths the filter mode will print
+    pub filter_output: FilterOutput,
+    /// Whether update mode only lists the files it would hash instead of hashing them
+    pub dry_run: bool,
+    /// The files to hash again in rehash mode, read from STDIN if empty
+    pub rehash_paths: Vec<String>,
+    /// Whether accept mode accepts every change without asking
+    pub assume_yes: bool,
 }
 
 impl Options {
@@ -110,17 +254,25 @@ impl Options {
             help: false,
             version_info: false,
             program_name: args[0].to_string(),
-            algorithm: "sha1".to_string(),
+            config: Config::default(),
             subdir_mode: false,
             mode: Mode::Filter,
             log_level: LogLevel::Info,
-            num_threads: 0,
-            folder: ".".to_string(),
+            export_format: ExportFormat::Csv,
+            output: None,
+            null_data: false,
+            filter_output: FilterOutput::New,
+            dry_run: false,
+            rehash_paths: Vec::new(),
+            assume_yes: false,
         };
 
         // prepare Strings for parsing
         let args = prepare_args(args[1..].to_vec());
 
+        // every argument that is not an option after --rehash is a file to hash again
+        let mut rehash_index = None;
+
         // loop through every argument, except the name
         for i in 0..args.len() {
             let arg = &args[i];
@@ -129,7 +281,15 @@ impl Options {
             if arg.starts_with('-') {
                 match arg.as_ref() {
                     "-a" | "--algo" | "--algorithm" => {
-                        opts.algorithm = args[i + 1].clone().to_lowercase()
+                        opts.config.algorithm = args
+                            .get(i + 1)
+                            .and_then(|name| Algorithm::from_name(name))
+                            .unwrap_or_else(|| {
+                                panic!(
+                                    "Usage: {} {} md5/sha1/sha224/sha256/sha384/sha512",
+                                    opts.program_name, args[i]
+                                )
+                            })
                     }
                     "-s" | "--subdir" | "--subdirs" | "--subdirectories" => opts.subdir_mode = true,
                     "-u" | "--update" => opts.mode = Mode::Update,
@@ -156,7 +316,7 @@ impl Options {
                     }
                     "--quiet" => opts.log_level = LogLevel::Quiet,
                     "-T" | "--threads" => {
-                        opts.num_threads = args
+                        opts.config.num_threads = args
                             .get(i + 1)
                             .unwrap_or_else(|| {
                                 panic!("Usage: {} -T NUMBER_OF_MAX_THREADS", opts.program_name)
@@ -167,16 +327,134 @@ impl Options {
                                 panic!("Usage: {} -T NUMBER_OF_MAX_THREADS", opts.program_name)
                             })
                     }
+                    "-e" | "--export" => {
+                        opts.mode = Mode::Export;
+                        opts.export_format = {
+                            match args
+                                .get(i + 1)
+                                .unwrap_or_else(|| {
+                                    panic!(
+                                        "Usage: {} {} hashdeep/json/csv",
+                                        opts.program_name, args[i]
+                                    )
+                                })
+                                .to_lowercase()
+                                .as_ref()
+                            {
+                                "hashdeep" => ExportFormat::Hashdeep,
+                                "json" => ExportFormat::Json,
+                                "csv" => ExportFormat::Csv,
+                                _ => panic!(
+                                    "Usage: {} {} hashdeep/json/csv",
+                                    opts.program_name, args[i]
+                                ),
+                            }
+                        }
+                    }
+                    "-o" | "--output" => {
+                        opts.output = Some(
+                            args.get(i + 1)
+                                .unwrap_or_else(|| {
+                                    panic!("Usage: {} {} FILE", opts.program_name, args[i])
+                                })
+                                .clone(),
+                        )
+                    }
+                    "--db-dir" | "--db_dir" => {
+                        opts.config.db_dir =
+                            Some(PathBuf::from(args.get(i + 1).unwrap_or_else(|| {
+                                panic!("Usage: {} {} DIRECTORY", opts.program_name, args[i])
+                            })))
+                    }
+                    "-x" | "--one-file-system" => opts.config.one_file_system = true,
+                    "--symlinks" => {
+                        opts.config.symlinks =
+                            SymlinkPolicy::from_name(args.get(i + 1).unwrap_or_else(|| {
+                                panic!(
+                                    "Usage: {} {} skip/follow/record",
+                                    opts.program_name, args[i]
+                                )
+                            }))
+                            .unwrap_or_else(|| {
+                                panic!(
+                                    "Usage: {} {} skip/follow/record",
+                                    opts.program_name, args[i]
+                                )
+                            })
+                    }
+                    "--min-size" | "--max-size" => {
+                        let size = args
+                            .get(i + 1)
+                            .and_then(|size| super::selection::parse_size(size))
+                            .unwrap_or_else(|| {
+                                panic!("Usage: {} {} SIZE (eg 10M)", opts.program_name, args[i])
+                            });
+                        if args[i] == "--min-size" {
+                            opts.config.selection.min_size = Some(size);
+                        } else {
+                            opts.config.selection.max_size = Some(size);
+                        }
+                    }
+                    "--include" | "--exclude" => {
+                        let glob = args
+                            .get(i + 1)
+                            .unwrap_or_else(|| {
+                                panic!("Usage: {} {} GLOB", opts.program_name, args[i])
+                            })
+                            .clone();
+                        if args[i] == "--include" {
+                            opts.config.selection.include.push(glob);
+                        } else {
+                            opts.config.selection.exclude.push(glob);
+                        }
+                    }
+                    "--newer-than" | "--older-than" => {
+                        let time = args
+                            .get(i + 1)
+                            .and_then(|time| super::selection::parse_time(time, SystemTime::now()))
+                            .unwrap_or_else(|| {
+                                panic!(
+                                    "Usage: {} {} AGE/DATE (eg 7d or 2024-01-31)",
+                                    opts.program_name, args[i]
+                                )
+                            });
+                        if args[i] == "--newer-than" {
+                            opts.config.selection.newer_than = Some(time);
+                        } else {
+                            opts.config.selection.older_than = Some(time);
+                        }
+                    }
+                    "--settle" => {
+                        let seconds = args
+                            .get(i + 1)
+                            .and_then(|seconds| seconds.trim().parse().ok())
+                            .unwrap_or_else(|| {
+                                panic!("Usage: {} {} SECONDS", opts.program_name, args[i])
+                            });
+                        opts.config.settle = Some(Duration::from_secs(seconds));
+                    }
+                    "-0" | "--null" => opts.null_data = true,
+                    "--hashed" => opts.filter_output = FilterOutput::Hashed,
+                    "--annotate" => opts.filter_output = FilterOutput::Annotate,
+                    "--wait" => opts.config.wait_for_lock = true,
+                    "--strict" => opts.config.strict = true,
+                    "--dry-run" | "--dry_run" => opts.dry_run = true,
+                    "--lint" => opts.mode = Mode::Lint,
+                    "--accept" => opts.mode = Mode::Accept,
+                    "-y" | "--yes" => opts.assume_yes = true,
+                    "--rehash" => {
+                        opts.mode = Mode::Rehash;
+                        rehash_index = Some(i);
+                    }
                     "-h" | "--help" => opts.help = true,
                     "-V" | "--version" => opts.version_info = true,
                     _ => opts.help = true,
                 }
-            } else {
-                // if a String does not start with - and the String before it is none of the below, it is the folder to operate on
-                match args[i - 1].as_ref() {
-                    "--loglevel" | "--log_level" | "--log-level" | "-a" | "--algo"
-                    | "--algorithm" | "-T" | "--threads" => {}
-                    _ => opts.folder = arg.clone(),
+            } else if i == 0 || !takes_value(&args[i - 1]) {
+                // if a String does not start with - and the String before it does not expect a value, it is the folder to operate on
+                match rehash_index {
+                    Some(index) if i > index => opts.rehash_paths.push(arg.clone()),
+                    _ => opts.config.root = PathBuf::from(arg),
                 }
             }
         }
@@ -200,12 +478,47 @@ impl Options {
     }
 }
 
+/// Returns whether the given option expects a value as the following argument
+///
+/// # Arguments
+///
+/// * `arg` The option to be checked
+fn takes_value(arg: &str) -> bool {
+    matches!(
+        arg,
+        "--loglevel"
+            | "--log_level"
+            | "--log-level"
+            | "-a"
+            | "--algo"
+            | "--algorithm"
+            | "-T"
+            | "--threads"
+            | "-e"
+            | "--export"
+            | "-o"
+            | "--output"
+            | "--db-dir"
+            | "--db_dir"
+            | "--symlinks"
+            | "--min-size"
+            | "--max-size"
+            | "--include"
+            | "--exclude"
+            | "--newer-than"
+            | "--older-than"
+            | "--settle"
+    )
+}
+
 /// Prepares a vec of Strings for parsing options
 ///
 /// A new vec gets returned that contains more Strings than the original, because two rules get applied:
 /// * If a String starts with a single -, but it has more than 2 characters, the parameters get split
 ///   into single Strings with a leading -
-/// * If a String contains a =, the = will get cut and the prefix and suffix will be split into two Strings
+/// * If an option contains a =, the = will get cut and the prefix and suffix will be split into two Strings
+///
+/// Values and folders are kept as they are, even if they contain a - or a =, eg dates like 2024-01-31.
 /// This is necessary for the match statement in Options::new to work correctly
 ///
 /// # Arguments
@@ -215,8 +528,8 @@ fn prepare_args(args: Vec<String>) -> Vec<String> {
     let mut prepared_args = Vec::with_capacity(args.len());
 
     for arg in args {
-        if !arg.contains('=') {
-            if arg.contains('-') && !arg.contains("--") && arg.len() > 2 {
+        if !arg.starts_with('-') || !arg.contains('=') {
+            if arg.starts_with('-') && !arg.starts_with("--") && arg.len() > 2 {
                 let characters = &arg[1..];
                 for char in characters.chars() {
                     let single_arg = format!("-{}", char);
@@ -237,20 +550,139 @@ fn prepare_args(args: Vec<String>) -> Vec<String> {
     prepared_args
 }
 
-/// Creates a regex that identifies hashsum and path from a hashsum line.
+/// Normalises a path lexically, without accessing the filesystem.
+/// Removes `.` components and duplicate separators and resolves `..` components.
+///
+/// # Arguments
+/// * `path` The path to normalise
+///
+/// # Returns
+/// None if a `..` component would leave the beginning of the path.
+pub fn normalize_path(path: &Path) -> Option<PathBuf> {
+    let mut normalized = PathBuf::new();
+    let mut depth = 0;
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if depth == 0 {
+                    return None;
+                }
+                normalized.pop();
+                depth -= 1;
+            }
+            Component::Normal(name) => {
+                normalized.push(name);
+                depth += 1;
+            }
+            Component::RootDir | Component::Prefix(_) => normalized.push(component.as_os_str()),
+        }
+    }
+
+    Some(normalized)
+}
+
+/// Returns the subdirectories of the working directory that are processed in subdir mode, sorted by name.
+/// Symbolic links to directories are treated like DirWalker treats them: they are skipped,
+/// unless they are followed and lead to a directory that is not processed already.
+/// With `Config::one_file_system` directories on other filesystems are skipped.
 ///
 /// # Arguments
-/// * `opts` Options object that contains the desired algorithm
-pub fn regex_from_opts(opts: &Options) -> Result<Regex, &'static str> {
-    match opts.algorithm.as_ref() {
-        "sha1" => Ok(Regex::new(r"([[:xdigit:]]{40})\s\s(.*)$").unwrap()),
-        "md5" => Ok(Regex::new(r"([[:xdigit:]]{32})\s\s(.*)$").unwrap()),
-        "sha224" => Ok(Regex::new(r"([[:xdigit:]]{56})\s\s(.*)$").unwrap()),
-        "sha256" => Ok(Regex::new(r"([[:xdigit:]]{64})\s\s(.*)$").unwrap()),
-        "sha384" => Ok(Regex::new(r"([[:xdigit:]]{96})\s\s(.*)$").unwrap()),
-        "sha512" => Ok(Regex::new(r"([[:xdigit:]]{128})\s\s(.*)$").unwrap()),
-        _ => Err("Could not recognize hashing algorithm"),
+/// * `config` Config object containing the working directory and the filesystem
+pub fn subdirectories(config: &Config) -> io::Result<Vec<PathBuf>> {
+    let filesystem = &*config.filesystem;
+    let root = filesystem.metadata(&config.root).ok();
+
+    let mut visited = HashSet::new();
+    if let Some(ref root) = root {
+        visited.insert((root.device, root.inode));
+    }
+
+    let mut directories = Vec::new();
+    let mut links = Vec::new();
+    let mut unreadable = Vec::new();
+    for entry in filesystem.read_dir(&config.root)? {
+        match entry.metadata {
+            Ok(ref metadata) if metadata.is_symlink => {
+                // links are handled last, so that a link never takes the place of the directory it points to
+                if config.symlinks == SymlinkPolicy::Follow {
+                    links.push(entry.path);
+                }
+            }
+            Ok(metadata) => {
+                if metadata.is_dir {
+                    directories.push((entry.path, metadata));
+                }
+            }
+            // processing the entry reports the error, instead of skipping it without a word
+            Err(_) => unreadable.push(entry.path),
+        }
+    }
+    links.sort();
+    for link in links {
+        if let Ok(metadata) = filesystem.metadata(&link) {
+            if metadata.is_dir {
+                directories.push((link, metadata));
+            }
+        }
     }
+
+    let mut subdirectories = Vec::new();
+    for (path, metadata) in directories {
+        let other_filesystem = config.one_file_system
+            && root
+                .as_ref()
+                .is_some_and(|root| root.device != metadata.device);
+        if !other_filesystem && visited.insert((metadata.device, metadata.inode)) {
+            subdirectories.push(path);
+        }
+    }
+    subdirectories.append(&mut unreadable);
+
+    subdirectories.sort();
+    Ok(subdirectories)
+}
+
+/// Reads all directories in the working directory that contain an _algorithm_sum.txt file.
+/// Returns the paths to the directories together with their path relative to the working directory
+/// (eg "./2017"), sorted by name.
+///
+/// # Arguments
+/// * `config` Config object containing the working directory
+pub fn gather_sumfile_directories(config: &Config) -> Vec<(PathBuf, String)> {
+    let mut dirs = Vec::new();
+
+    if let Ok(subdirectories) = subdirectories(config) {
+        for path in subdirectories {
+            let has_sumfile = config
+                .sumfile_path(&path)
+                .and_then(|sumfile_path| config.filesystem.metadata(&sumfile_path))
+                .map(|metadata| metadata.is_file)
+                .unwrap_or(false);
+
+            if has_sumfile {
+                let relative = format!("./{}", path.file_name().unwrap().to_string_lossy());
+                dirs.push((path, relative));
+            }
+        }
+    }
+
+    dirs.sort_by(|a, b| a.1.cmp(&b.1));
+    dirs
+}
+
+/// Returns whether the given filename belongs to a lockfile, stat cache, list of recorded links
+/// or temporary file created by this program.
+/// Such files are never hashed.
+///
+/// # Arguments
+/// * `filename` The name of the file without any directories
+pub fn is_internal_file(filename: &str) -> bool {
+    super::lock::is_lockfile(filename)
+        || super::sumfile::is_temporary_file(filename)
+        || super::statcache::is_stat_cache(filename)
+        || super::links::is_links_file(filename)
 }
 
 /// Imitate _algorithm_sum with the path of a file to get the hashsum.
@@ -259,48 +691,85 @@ pub fn regex_from_opts(opts: &Options) -> Result<Regex, &'static str> {
 ///
 /// * `path` Path to the file to be hashed, relative to the workdir
 /// * `workdir` Path to the wanted working directory
-/// * `opts` A reference to an Options object containing information about the program behavior
+/// * `algorithm` The hashing algorithm to use
+/// * `filesystem` The filesystem containing the file
+/// * `symlinks` With SymlinkPolicy::Record a symbolic link is hashed by the path it points to
 ///
 /// # Returns
 ///
-/// A String containing the output of the _algorithm_sum command.
+/// The hashsum of the file and the number of bytes hashed.
 pub fn calculate_hash(
     path: String,
-    workdir: &PathBuf,
-    opts: &super::util::Options,
-) -> Result<String, HashError> {
-    let file = fs::File::open(&format!("{}/{}", workdir.to_str().unwrap(), path));
+    workdir: &Path,
+    algorithm: Algorithm,
+    filesystem: &dyn FileSystem,
+    symlinks: SymlinkPolicy,
+) -> Result<(String, u64), HashError> {
+    let full_path = workdir.join(&path);
+    if symlinks == SymlinkPolicy::Record {
+        match filesystem.symlink_metadata(&full_path) {
+            Ok(ref metadata) if metadata.is_symlink => {
+                return match filesystem.read_link(&full_path) {
+                    Ok(target) => {
+                        let target = target.to_string_lossy();
+                        let mut hasher = algorithm.hasher();
+                        hasher.input(target.as_bytes());
+                        Ok((hex::encode(hasher.result()), target.len() as u64))
+                    }
+                    Err(e) => Err(HashError { source: e, path }),
+                };
+            }
+            Ok(_) => {}
+            Err(e) => return Err(HashError { source: e, path }),
+        }
+    }
+
+    // opening a FIFO or device node blocks or never reaches the end
+    let before = filesystem.metadata(&full_path).ok();
+    if let Some(Metadata {
+        special: Some(kind),
+        ..
+    }) = before
+    {
+        return Err(HashError {
+            source: io::Error::other(format!("not hashing {}", kind)),
+            path,
+        });
+    }
+
+    let file = filesystem.open(&full_path);
     const BUFFER_SIZE: usize = 1024;
     let mut buffer = [0; BUFFER_SIZE];
+    let mut size = 0;
 
-    let mut hasher = match opts.algorithm.as_ref() {
-        "sha1" => Box::new(Sha1::new()) as Box<dyn DynDigest>,
-        "md5" => Box::new(Md5::new()) as Box<dyn DynDigest>,
-        "sha224" => Box::new(Sha224::new()) as Box<dyn DynDigest>,
-        "sha256" => Box::new(Sha256::new()) as Box<dyn DynDigest>,
-        "sha384" => Box::new(Sha384::new()) as Box<dyn DynDigest>,
-        "sha512" => Box::new(Sha512::new()) as Box<dyn DynDigest>,
-        _ => panic!("Algorithm not recognized"),
-    };
+    let mut hasher = algorithm.hasher();
 
     match file {
-        Err(e) => {
-            return Err(HashError {
-                source: e,
-                path: path,
-            })
-        }
+        Err(e) => return Err(HashError { source: e, path }),
         Ok(mut file) => loop {
-            let n = file.read(&mut buffer).unwrap();
+            // a read may return fewer bytes than requested before the end of the file is reached
+            let n = match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(HashError { source: e, path }),
+            };
             hasher.input(&buffer[0..n]);
-
-            if n == 0 || n < BUFFER_SIZE {
-                break;
-            }
+            size += n as u64;
         },
     }
 
-    Ok(format!("{}  {}\n", hex::encode(hasher.result()), path))
+    // a file that is still being written would get the hashsum of a part of it
+    if let (Some(before), Ok(after)) = (before, filesystem.metadata(&full_path)) {
+        if size != before.len || after.len != before.len || after.modified != before.modified {
+            return Err(HashError {
+                source: io::Error::other("changed while being hashed, discarding its hashsum"),
+                path,
+            });
+        }
+    }
+
+    Ok((hex::encode(hasher.result()), size))
 }
 
 /// Starts a number of worker threads ready for hashing files.
@@ -326,11 +795,29 @@ pub fn execute_workers(
 
             match task {
                 Steal::Success(task) => {
-                    let hashline = calculate_hash(task.path, &task.workdir, &task.opts);
-                    match hashline {
-                        Ok(hashline) => task.result_chan.send(Ok((hashline, task.cmp))).unwrap(),
-                        Err(e) => task.result_chan.send(Err(e)).unwrap(),
-                    };
+                    let HashTask {
+                        path,
+                        workdir,
+                        algorithm,
+                        filesystem,
+                        cmp,
+                        result_chan,
+                        cancelled,
+                        symlinks,
+                    } = task;
+                    if cancelled.load(Ordering::SeqCst) {
+                        continue;
+                    }
+                    let result =
+                        calculate_hash(path.clone(), &workdir, algorithm, &*filesystem, symlinks)
+                            .map(|(hash, size)| HashOutput {
+                                path,
+                                hash,
+                                cmp,
+                                size,
+                            });
+                    // the receiver may have given up on the results, eg after an error
+                    let _ = result_chan.send(result);
                 }
                 Steal::Retry => {
                     std::thread::sleep(std::time::Duration::from_millis(10));
@@ -348,17 +835,37 @@ pub fn execute_workers(
     }
 }
 
-/// Disables echo on terminal
+/// The terminal settings before echo was disabled, restored by terminal_restore
 #[cfg(unix)]
-pub fn terminal_noecho() {
-    let mut termios_noecho = termios::Termios::from_fd(0).unwrap();
+static ORIGINAL_TERMINAL: Mutex<Option<termios::Termios>> = Mutex::new(None);
+
+/// The console mode before echo was disabled, restored by terminal_restore
+#[cfg(windows)]
+static ORIGINAL_TERMINAL: Mutex<Option<u32>> = Mutex::new(None);
+
+/// Disables echo on terminal, fails if stdin is not a terminal
+#[cfg(unix)]
+pub fn terminal_noecho() -> io::Result<()> {
+    let mut termios_noecho = termios::Termios::from_fd(0)?;
+    ORIGINAL_TERMINAL
+        .lock()
+        .unwrap()
+        .get_or_insert(termios_noecho);
     termios_noecho.c_lflag &= !termios::ECHO;
-    termios::tcsetattr(0, termios::TCSANOW, &termios_noecho).unwrap();
+    termios::tcsetattr(0, termios::TCSANOW, &termios_noecho)
 }
 
-/// Disables echo on terminal
+/// Restores the terminal settings from before terminal_noecho was called
+#[cfg(unix)]
+pub fn terminal_restore() {
+    if let Some(original) = ORIGINAL_TERMINAL.lock().unwrap().take() {
+        let _ = termios::tcsetattr(0, termios::TCSANOW, &original);
+    }
+}
+
+/// Disables echo on terminal, fails if stdin is not a console
 #[cfg(windows)]
-pub fn terminal_noecho() {
+pub fn terminal_noecho() -> io::Result<()> {
     use self::winapi::shared::minwindef::LPDWORD;
     use self::winapi::um::consoleapi::{GetConsoleMode, SetConsoleMode};
     use self::winapi::um::processenv::GetStdHandle;
@@ -368,64 +875,326 @@ pub fn terminal_noecho() {
     let handle = unsafe { GetStdHandle(STD_INPUT_HANDLE) };
 
     let mut mode = 0;
-    // unsafe { GetConsoleMode(handle, &mut mode as LPDWORD) };
-    unsafe { GetConsoleMode(handle, &mut mode as LPDWORD) };
-    unsafe { SetConsoleMode(handle, mode & (!ENABLE_ECHO_INPUT)) };
+    if unsafe { GetConsoleMode(handle, &mut mode as LPDWORD) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    ORIGINAL_TERMINAL.lock().unwrap().get_or_insert(mode);
+    if unsafe { SetConsoleMode(handle, mode & (!ENABLE_ECHO_INPUT)) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Restores the console mode from before terminal_noecho was called
+#[cfg(windows)]
+pub fn terminal_restore() {
+    use self::winapi::um::consoleapi::SetConsoleMode;
+    use self::winapi::um::processenv::GetStdHandle;
+    use self::winapi::um::winbase::STD_INPUT_HANDLE;
+
+    if let Some(mode) = ORIGINAL_TERMINAL.lock().unwrap().take() {
+        let handle = unsafe { GetStdHandle(STD_INPUT_HANDLE) };
+        unsafe { SetConsoleMode(handle, mode) };
+    }
+}
+
+/// The flag set by the handler of SIGINT and SIGTERM, see cancel_on_interrupt
+static INTERRUPT_FLAG: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Sets the given flag when SIGINT or SIGTERM is received, instead of terminating the program.
+/// A second signal terminates the program immediately.
+///
+/// # Arguments
+/// * `cancelled` The flag to set, usually `Config::cancelled`
+#[cfg(unix)]
+pub fn cancel_on_interrupt(cancelled: &Arc<AtomicBool>) {
+    if INTERRUPT_FLAG.set(Arc::clone(cancelled)).is_err() {
+        return;
+    }
+
+    let handler = handle_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+/// Sets the given flag when SIGINT or SIGTERM is received.
+/// Not supported on windows, where Ctrl-C keeps terminating the program.
+///
+/// # Arguments
+/// * `cancelled` The flag to set, usually `Config::cancelled`
+#[cfg(windows)]
+pub fn cancel_on_interrupt(_cancelled: &Arc<AtomicBool>) {}
+
+/// Handles SIGINT and SIGTERM by setting the flag passed to cancel_on_interrupt
+/// and restoring the default handler, so a second signal terminates the program.
+/// Only async-signal-safe operations may be used here.
+///
+/// # Arguments
+/// * `signal` The number of the received signal
+#[cfg(unix)]
+extern "C" fn handle_interrupt(signal: libc::c_int) {
+    if let Some(flag) = INTERRUPT_FLAG.get() {
+        flag.store(true, Ordering::SeqCst);
+    }
+    unsafe {
+        libc::signal(signal, libc::SIG_DFL);
+    }
+}
+
+/// Accumulates the sizes of some files. Files that can not be read are skipped.
+///
+/// # Arguments
+/// * `filesystem` The filesystem containing the files
+/// * `workdir` Path to the directory the paths are relative to
+/// * `paths` The paths to the files
+pub fn count_bytes<I, P>(filesystem: &dyn FileSystem, workdir: &Path, paths: I) -> u64
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut all_bytes = 0;
+
+    for path in paths {
+        if let Ok(metadata) = filesystem.metadata(&workdir.join(path)) {
+            all_bytes += metadata.len;
+        }
+    }
+
+    all_bytes
+}
+
+/// Formats a number of bytes in a human readable way, eg "1.50 GiB"
+///
+/// # Arguments
+/// * `bytes` The number of bytes
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+
+    format!("{:.2} {}", value, UNITS[unit])
+}
+
+/// Formats a duration in seconds as hours, minutes and seconds, eg "2h 05m 09s"
+///
+/// # Arguments
+/// * `seconds` The duration in seconds
+pub fn format_duration(seconds: u64) -> String {
+    format!(
+        "{}h {:02}m {:02}s",
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
 }
 
 /// Read paths line by line from a file and return them in a Vector
 ///
 /// # Arguments
 ///
+/// * `filesystem` The filesystem containing the file
 /// * `filepath` Path to the file to be read
-pub fn read_paths_from_file(filepath: &str) -> Vec<PathBuf> {
+pub fn read_paths_from_file(filesystem: &dyn FileSystem, filepath: &Path) -> Vec<PathBuf> {
     let mut vec = Vec::new();
 
-    let file = OpenOptions::new().read(true).open(filepath);
-    if let Ok(file) = file {
-        let reader = BufReader::new(file);
-        for line in reader.lines() {
-            if let Ok(line) = line {
-                vec.push(PathBuf::from(line));
-            }
+    if let Ok(contents) = filesystem.read(filepath) {
+        for line in String::from_utf8_lossy(&contents).lines() {
+            vec.push(PathBuf::from(line));
         }
     }
 
     vec
 }
 
+/// The reason DirWalker skipped a file or directory, besides .arkignore
+#[derive(Debug, Clone, PartialEq)]
+pub enum SkipReason {
+    /// A symbolic link, skipped because of SymlinkPolicy::Skip
+    Symlink,
+    /// A symbolic link whose target does not exist
+    BrokenSymlink,
+    /// A directory that was already walked, reached again through a symbolic link
+    Loop,
+    /// A directory on another filesystem than the walked directory, skipped because of `Config::one_file_system`
+    OtherFilesystem,
+    /// A FIFO, socket or device node, which can not be hashed
+    Special(SpecialFile),
+    /// A file rejected by `Config::selection`, with the criterion it failed, eg "is smaller than 1.00 MiB"
+    Excluded(String),
+    /// A new file modified more recently than `Config::settle`, which may still be written
+    Unsettled(Duration),
+    /// A file or directory whose properties or entries could not be read, with the error
+    Unreadable(String),
+}
+
+impl SkipReason {
+    /// Returns whether the user should be warned about the skipped path,
+    /// instead of only mentioning it in the debug loglevel
+    pub fn is_warning(&self) -> bool {
+        match self {
+            SkipReason::Symlink | SkipReason::OtherFilesystem | SkipReason::Excluded(_) => false,
+            SkipReason::BrokenSymlink
+            | SkipReason::Loop
+            | SkipReason::Special(_)
+            | SkipReason::Unsettled(_)
+            | SkipReason::Unreadable(_) => true,
+        }
+    }
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SkipReason::Symlink => write!(f, "is a symbolic link"),
+            SkipReason::BrokenSymlink => write!(f, "is a symbolic link to a missing target"),
+            SkipReason::Loop => write!(f, "leads to a directory that is already walked"),
+            SkipReason::OtherFilesystem => write!(f, "is on another filesystem"),
+            SkipReason::Special(kind) => write!(f, "is {}", kind),
+            SkipReason::Excluded(reason) => write!(f, "{}", reason),
+            SkipReason::Unsettled(settle) => write!(
+                f,
+                "was modified less than {} seconds ago and may still be written",
+                settle.as_secs()
+            ),
+            SkipReason::Unreadable(e) => write!(f, "could not be read: {}", e),
+        }
+    }
+}
+
 /// An Object that returns Paths to all the files in all folders recursively (like find)
+/// The paths are relative to the start directory, eg "./sub/a.jpg"
+/// Files and directories matched by the .arkignore files of the directories it descends into are skipped.
 ///
 /// DirWalker implements Iterator and Read for this behavior
 pub struct DirWalker {
     /// A Buffer for the currently known files
     current_files: Vec<PathBuf>,
-    /// A Buffer for the directories that have to be scanned recursively
-    current_directories: Vec<PathBuf>,
+    /// A Buffer for the directories that have to be scanned recursively,
+    /// together with the patterns of .arkignore applying to their contents
+    current_directories: Vec<(PathBuf, Arc<IgnoreRules>)>,
+    /// A Buffer for the directories reached through symbolic links, together with their properties.
+    /// They are scanned after all other directories, so that a directory also reachable without a link
+    /// is walked by its real path.
+    linked_directories: Vec<(PathBuf, Arc<IgnoreRules>, Metadata)>,
     /// A Buffer for the filepath that was only partially read
     unfinished_read: String,
-    /// Whether or not the first directory should be stripped from the filepath
-    subdir_mode: bool,
+    /// The directory the returned paths are relative to
+    start_directory: PathBuf,
+    /// The filesystem that is scanned
+    filesystem: Arc<dyn FileSystem>,
+    /// How symbolic links are treated
+    symlinks: SymlinkPolicy,
+    /// Device and inode of every directory walked so far, to detect loops when following symbolic links
+    visited: HashSet<(u64, u64)>,
+    /// The device of the start directory, if directories on other devices are skipped
+    device: Option<u64>,
+    /// The size, name and age a file needs to be returned
+    selection: Selection,
+    /// The paths that were skipped because of .arkignore, together with the pattern that matched them
+    ignored: Vec<(PathBuf, IgnoreRule)>,
+    /// The paths that were skipped for other reasons
+    skipped: Vec<(PathBuf, SkipReason)>,
 }
 
 impl DirWalker {
-    /// Create a new DirWalker object
+    /// Create a new DirWalker object for a directory inside the folder of a Config,
+    /// applying the .arkignore files above the directory and the symlink and filesystem settings of the Config
     ///
     /// # Arguments
     ///
     /// * `start_directory` Path to the directory that should be scanned
-    /// * `subdir_mode` Whether or not the first directory should be stripped from the filepath
-    pub fn new(start_directory: &PathBuf, subdir_mode: bool) -> DirWalker {
-        let mut dirwalker = DirWalker {
+    /// * `config` The Config containing the filesystem, the folder to operate on and the walk settings
+    pub fn for_config(start_directory: &Path, config: &Config) -> DirWalker {
+        let ignore = IgnoreRules::read_nested(&*config.filesystem, &config.root, start_directory);
+        let mut dirwalker = DirWalker::with_settings(
+            start_directory,
+            Arc::clone(&config.filesystem),
+            config.symlinks,
+        );
+        dirwalker.selection = config.selection.clone();
+        dirwalker.start(ignore, config.one_file_system);
+
+        dirwalker
+    }
+
+    /// Create a new DirWalker object that has not scanned anything yet
+    ///
+    /// # Arguments
+    ///
+    /// * `start_directory` Path to the directory that should be scanned
+    /// * `filesystem` The filesystem containing the directory
+    /// * `symlinks` How symbolic links are treated
+    fn with_settings(
+        start_directory: &Path,
+        filesystem: Arc<dyn FileSystem>,
+        symlinks: SymlinkPolicy,
+    ) -> DirWalker {
+        DirWalker {
             current_files: Vec::new(),
             current_directories: Vec::new(),
+            linked_directories: Vec::new(),
             unfinished_read: String::new(),
-            subdir_mode,
-        };
+            start_directory: start_directory.to_path_buf(),
+            filesystem,
+            symlinks,
+            visited: HashSet::new(),
+            device: None,
+            selection: Selection::default(),
+            ignored: Vec::new(),
+            skipped: Vec::new(),
+        }
+    }
 
-        dirwalker.populate_with_dir(&start_directory);
+    /// Scans the start directory
+    ///
+    /// # Arguments
+    ///
+    /// * `ignore` The patterns applying to the start directory, including its own .arkignore
+    /// * `one_file_system` Whether directories on other devices than the start directory are skipped
+    fn start(&mut self, ignore: IgnoreRules, one_file_system: bool) {
+        let start_directory = self.start_directory.clone();
+
+        if let Ok(metadata) = self.filesystem.metadata(&start_directory) {
+            self.first_visit(&metadata);
+            if one_file_system {
+                self.device = Some(metadata.device);
+            }
+        }
+        self.populate_with_dir(&start_directory, Arc::new(ignore));
+    }
 
-        dirwalker
+    /// Returns the paths skipped so far because of .arkignore, together with the pattern that matched them
+    pub fn ignored(&self) -> &[(PathBuf, IgnoreRule)] {
+        &self.ignored
+    }
+
+    /// Returns the paths skipped so far for other reasons than .arkignore
+    pub fn skipped(&self) -> &[(PathBuf, SkipReason)] {
+        &self.skipped
+    }
+
+    /// Remembers a directory as walked
+    ///
+    /// # Arguments
+    ///
+    /// * `metadata` The properties of the directory
+    ///
+    /// # Returns
+    /// false if the directory was already walked before.
+    fn first_visit(&mut self, metadata: &Metadata) -> bool {
+        // without an inode, eg on Windows, loops can not be detected
+        metadata.inode == 0 || self.visited.insert((metadata.device, metadata.inode))
     }
 
     /// Update the DirWalker object by adding all subdirectories and files of directory to the queue
@@ -433,22 +1202,90 @@ impl DirWalker {
     /// # Arguments
     ///
     /// * `directory` Path to the directory that is going to be scanned
-    fn populate_with_dir(&mut self, directory: &PathBuf) {
-        let dir_entries = fs::read_dir(directory);
-
+    /// * `ignore` The patterns applying to the contents of the directory
+    fn populate_with_dir(&mut self, directory: &Path, ignore: Arc<IgnoreRules>) {
+        let dir_entries = self.filesystem.read_dir(directory);
+
+        if let Err(ref e) = dir_entries {
+            self.skipped.push((
+                directory.to_path_buf(),
+                SkipReason::Unreadable(e.to_string()),
+            ));
+        }
         if let Ok(dir_entries) = dir_entries {
             let mut files = Vec::new();
             let mut dirs = Vec::new();
 
             for entry in dir_entries {
-                let entry = entry.unwrap();
-                let metadata = entry.metadata().unwrap();
+                let mut metadata = match entry.metadata {
+                    Ok(metadata) => metadata,
+                    Err(e) => {
+                        self.skipped
+                            .push((entry.path, SkipReason::Unreadable(e.to_string())));
+                        continue;
+                    }
+                };
+                let linked = metadata.is_symlink;
+                if linked {
+                    match self.symlinks {
+                        SymlinkPolicy::Skip => {
+                            self.skipped.push((entry.path, SkipReason::Symlink));
+                            continue;
+                        }
+                        // the link itself is recorded, like a file
+                        SymlinkPolicy::Record => {}
+                        SymlinkPolicy::Follow => match self.filesystem.metadata(&entry.path) {
+                            Ok(target) => metadata = target,
+                            Err(_) => {
+                                self.skipped.push((entry.path, SkipReason::BrokenSymlink));
+                                continue;
+                            }
+                        },
+                    }
+                }
 
-                if metadata.is_dir() {
-                    dirs.push(entry.path());
+                if let Some(rule) = ignore.ignored_by(&entry.path, metadata.is_dir) {
+                    self.ignored.push((entry.path, rule.clone()));
+                    continue;
                 }
-                if metadata.is_file() {
-                    files.push(entry.path());
+                if let Some(kind) = metadata.special {
+                    self.skipped.push((entry.path, SkipReason::Special(kind)));
+                    continue;
+                }
+                if metadata.is_dir {
+                    if self.device.is_some_and(|device| device != metadata.device) {
+                        self.skipped.push((entry.path, SkipReason::OtherFilesystem));
+                        continue;
+                    }
+                    if !linked
+                        && self.symlinks == SymlinkPolicy::Follow
+                        && !self.first_visit(&metadata)
+                    {
+                        self.skipped.push((entry.path, SkipReason::Loop));
+                        continue;
+                    }
+                    // the patterns of an .arkignore only apply to its own subtree
+                    let nested = IgnoreRules::read(&*self.filesystem, &entry.path);
+                    let rules = if nested.is_empty() {
+                        Arc::clone(&ignore)
+                    } else {
+                        let mut rules = (*ignore).clone();
+                        rules.extend(nested);
+                        Arc::new(rules)
+                    };
+                    if linked {
+                        self.linked_directories.push((entry.path, rules, metadata));
+                    } else {
+                        dirs.push((entry.path, rules));
+                    }
+                } else if metadata.is_file || metadata.is_symlink {
+                    match self.selection.reject(&entry.path, &metadata) {
+                        Some(reason) => {
+                            self.skipped
+                                .push((entry.path, SkipReason::Excluded(reason)));
+                        }
+                        None => files.push(entry.path),
+                    }
                 }
             }
 
@@ -456,26 +1293,6 @@ impl DirWalker {
             self.current_files.append(&mut files);
         }
     }
-
-    /// Return the position of the first directory seperator in a str containing a path
-    ///
-    /// # Arguments
-    ///
-    /// * `path_string` String containing the path to be searched
-    #[cfg(unix)]
-    fn find_dir_seperator_position(path_string: &str) -> usize {
-        path_string.find('/').unwrap()
-    }
-
-    /// Return the position of the first directory seperator in a str containing a path
-    ///
-    /// # Arguments
-    ///
-    /// * `path_string` String containing the path to be searched
-    #[cfg(windows)]
-    fn find_dir_seperator_position(path_string: &str) -> usize {
-        path_string.find('\\').unwrap()
-    }
 }
 
 impl Iterator for DirWalker {
@@ -485,22 +1302,26 @@ impl Iterator for DirWalker {
         if !self.current_files.is_empty() {
             let filepath = self.current_files.pop().unwrap();
 
-            if self.subdir_mode {
-                let path_string = filepath.to_string_lossy().to_string();
-                let path_string = &path_string[2..];
-                let position = DirWalker::find_dir_seperator_position(path_string);
-                let path_string = format!(".{}", path_string[position..].to_string());
-                let filepath = PathBuf::from(path_string);
-                return Some(filepath);
-            }
-
-            return Some(filepath);
+            return match filepath.strip_prefix(&self.start_directory) {
+                Ok(relative) => Some(Path::new(".").join(relative)),
+                Err(_) => Some(filepath),
+            };
         }
 
         if !self.current_directories.is_empty() {
-            let dirpath = self.current_directories.pop().unwrap();
+            let (dirpath, ignore) = self.current_directories.pop().unwrap();
+
+            self.populate_with_dir(&dirpath, ignore);
+
+            return self.next();
+        }
 
-            self.populate_with_dir(&dirpath);
+        if let Some((dirpath, ignore, metadata)) = self.linked_directories.pop() {
+            if self.first_visit(&metadata) {
+                self.populate_with_dir(&dirpath, ignore);
+            } else {
+                self.skipped.push((dirpath, SkipReason::Loop));
+            }
 
             return self.next();
         }
@@ -1,24 +1,298 @@
 //! This module implements the verify mode
 
 extern crate chrono;
-extern crate crossbeam_deque;
-extern crate num_cpus;
-extern crate regex;
-
-use std::borrow::Borrow;
-use std::fs::{self, OpenOptions};
-use std::io::{self, BufRead, BufReader, Write};
-use std::path::PathBuf;
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::channel;
 use std::sync::Arc;
 use std::thread;
 
 use self::chrono::{DateTime, Datelike};
 
-use self::crossbeam_deque::Injector;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::mpsc::{channel, Receiver, Sender};
+use super::config::Config;
+use super::ignore::{IgnoreRules, IgnoreTree};
+use super::links::RecordedLinks;
+use super::progress::ProgressReporter;
+use super::report::{Event, MultiReporter, NullReporter, Reporter};
+use super::util::{DirectoryError, HashError, HashOutput, HashPool, HashTask, SkipReason};
+
+/// The result of verifying the files listed in the _algorithm_sum.txt of a directory
+#[derive(Debug)]
+pub struct VerifyReport {
+    /// The directory that was verified
+    pub directory: PathBuf,
+    /// The number of files that still match their hashsum
+    pub verified: usize,
+    /// The files that do not match their hashsum anymore, `cmp` holds the expected hashsum
+    pub mismatches: Vec<HashOutput>,
+    /// The files that could not be hashed
+    pub failed: Vec<HashError>,
+    /// The truncated last line of _algorithm_sum.txt, which was ignored
+    pub truncated_line: Option<String>,
+    /// The number of files that were skipped because the verify was cancelled
+    pub skipped: usize,
+    /// The listed files that were not verified because they do not match `Config::selection`
+    pub excluded: Vec<String>,
+}
+
+impl VerifyReport {
+    /// Creates a report for a directory that has not been verified yet
+    ///
+    /// # Arguments
+    /// * `directory` The directory that is going to be verified
+    fn new(directory: &Path) -> VerifyReport {
+        VerifyReport {
+            directory: directory.to_path_buf(),
+            verified: 0,
+            mismatches: Vec::new(),
+            failed: Vec::new(),
+            truncated_line: None,
+            skipped: 0,
+            excluded: Vec::new(),
+        }
+    }
+
+    /// Returns whether every file was verified and matched its hashsum
+    pub fn is_ok(&self) -> bool {
+        self.mismatches.is_empty() && self.failed.is_empty() && self.skipped == 0
+    }
+
+    /// Returns the paths of the changed files and descriptions of the files that could not be hashed,
+    /// as written to to_check_DIRECTORY.txt
+    pub fn failed_paths(&self) -> Vec<String> {
+        self.mismatches
+            .iter()
+            .map(|output| output.path.clone())
+            .chain(self.failed.iter().map(|e| e.to_string()))
+            .collect()
+    }
+}
+
+/// Hashes the files listed in the _algorithm_sum.txt of a directory and compares them to their hashsums.
+/// A Verifier owns a pool of worker threads, it can be shared between threads to verify
+/// multiple directories at once.
+pub struct Verifier {
+    /// The settings of the engine
+    config: Config,
+    /// The worker threads hashing the files
+    pool: HashPool,
+}
+
+impl Verifier {
+    /// Creates a new Verifier and starts its worker threads
+    ///
+    /// # Arguments
+    /// * `config` The settings of the engine
+    pub fn new(config: Config) -> Verifier {
+        let pool = HashPool::new(config.threads());
+
+        Verifier { config, pool }
+    }
+
+    /// Returns the settings of the engine
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// Verifies the files of a directory
+    ///
+    /// # Arguments
+    /// * `directory` The directory that is going to be verified
+    pub fn verify(&self, directory: &Path) -> Result<VerifyReport, DirectoryError> {
+        self.verify_with(directory, &NullReporter)
+    }
+
+    /// Verifies the files of a directory and reports every file to a Reporter
+    /// as soon as it is hashed
+    ///
+    /// # Arguments
+    /// * `directory` The directory that is going to be verified
+    /// * `reporter` Receives the events that occur while verifying the directory
+    pub fn verify_with(
+        &self,
+        directory: &Path,
+        reporter: &dyn Reporter,
+    ) -> Result<VerifyReport, DirectoryError> {
+        let result = self.verify_reporting(directory, reporter);
+
+        if let Err(ref error) = result {
+            reporter.report(&Event::DirectoryFailed { directory, error });
+        }
+
+        result
+    }
+
+    /// Verifies the files of a directory, see `verify_with`
+    ///
+    /// # Arguments
+    /// * `directory` The directory that is going to be verified
+    /// * `reporter` Receives the events that occur while verifying the directory
+    fn verify_reporting(
+        &self,
+        directory: &Path,
+        reporter: &dyn Reporter,
+    ) -> Result<VerifyReport, DirectoryError> {
+        let mut report = VerifyReport::new(directory);
+
+        let _lock = super::lock::lock_directory(directory, &self.config, &mut |notice| {
+            reporter.report(&Event::Lock { notice: &notice })
+        })
+        .map_err(DirectoryError::Locked)?;
+
+        let sumfile_path = self
+            .config
+            .sumfile_path(directory)
+            .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+        if let Ok(Some(partial_line)) =
+            super::sumfile::truncated_line(&*self.config.filesystem, &sumfile_path)
+        {
+            let message = format!(
+                "last line of {} is truncated, run update to repair it: {}",
+                sumfile_path.to_str().unwrap(),
+                partial_line
+            );
+            reporter.report(&Event::Warning {
+                directory,
+                message: &message,
+            });
+            report.truncated_line = Some(partial_line);
+        }
+
+        let mut entries = super::sumfile::read_entries(&sumfile_path, &self.config)
+            .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+        let mut ignore = IgnoreTree::new(Arc::clone(&self.config.filesystem), &self.config.root);
+        entries.retain(|entry| {
+            let path = directory.join(&entry.path);
+            match ignore.ignored_by(&path, false) {
+                Some(rule) => {
+                    reporter.report(&Event::Ignored {
+                        directory,
+                        path: Path::new(&entry.path),
+                        rule,
+                    });
+                    false
+                }
+                None => true,
+            }
+        });
+        if !self.config.selection.is_empty() {
+            let selection = &self.config.selection;
+            let filesystem = &self.config.filesystem;
+            let excluded = &mut report.excluded;
+            entries.retain(|entry| {
+                let path = directory.join(&entry.path);
+                // missing files are not excluded, so that verify still reports them
+                let reason = match filesystem.metadata(&path) {
+                    Ok(metadata) => selection.reject(&path, &metadata),
+                    Err(_) => None,
+                };
+                match reason {
+                    Some(reason) => {
+                        reporter.report(&Event::Skipped {
+                            directory,
+                            path: Path::new(&entry.path),
+                            reason: &SkipReason::Excluded(reason),
+                        });
+                        excluded.push(entry.path.clone());
+                        false
+                    }
+                    None => true,
+                }
+            });
+        }
+
+        let links_path = self
+            .config
+            .links_path(directory)
+            .map_err(|e| DirectoryError::Sumfile(e.to_string()))?;
+        let links = RecordedLinks::read(&*self.config.filesystem, &links_path)
+            .map_err(|e| DirectoryError::Sumfile(format!("{}: {}", links_path.display(), e)))?;
+
+        let total_bytes = super::util::count_bytes(
+            &*self.config.filesystem,
+            directory,
+            entries.iter().map(|e| &e.path),
+        );
+        let mut processed_bytes = 0;
+        reporter.report(&Event::DirectoryStarted {
+            directory,
+            total_bytes,
+        });
+
+        let (sender, receiver) = channel();
+        let queued = entries.len();
+
+        for entry in entries {
+            let symlinks = links.policy(&entry.path);
+            self.pool.push(HashTask {
+                path: entry.path,
+                workdir: directory.to_path_buf(),
+                algorithm: self.config.algorithm,
+                filesystem: Arc::clone(&self.config.filesystem),
+                cmp: entry.hash,
+                result_chan: sender.clone(),
+                cancelled: Arc::clone(&self.config.cancelled),
+                symlinks,
+            });
+        }
+
+        drop(sender);
+
+        for task_result in receiver {
+            match task_result {
+                Ok(output) => {
+                    processed_bytes += output.size;
+
+                    reporter.report(&Event::FileHashed {
+                        directory,
+                        output: &output,
+                    });
+                    if output.hash != output.cmp {
+                        reporter.report(&Event::Mismatch {
+                            directory,
+                            output: &output,
+                        });
+                    }
+                    reporter.report(&Event::Progress {
+                        directory,
+                        processed_bytes,
+                        total_bytes,
+                    });
+
+                    if output.hash == output.cmp {
+                        report.verified += 1;
+                    } else {
+                        report.mismatches.push(output);
+                    }
+                }
+                Err(error) => {
+                    reporter.report(&Event::Error {
+                        directory,
+                        error: &error,
+                    });
+                    report.failed.push(error);
+                }
+            }
+        }
+
+        report.skipped = queued - report.verified - report.mismatches.len() - report.failed.len();
+        if report.skipped > 0 {
+            reporter.report(&Event::DirectoryInterrupted {
+                directory,
+                failed_paths: &report.failed_paths(),
+                skipped: report.skipped,
+            });
+        } else {
+            reporter.report(&Event::DirectoryFinished {
+                directory,
+                failed_paths: &report.failed_paths(),
+            });
+        }
 
-use super::util::HashError;
+        Ok(report)
+    }
+}
 
 /// Verifies the integrity of some directories
 ///
@@ -29,76 +303,120 @@ use super::util::HashError;
 /// # Returns
 /// The exit code the program should return.
 pub fn verify_directories(opts: super::util::Options) -> i32 {
-    let now = chrono::Local::now();
-    let known_good_path = format!("known_good_{}_{}.txt", now.month(), now.year());
-    let to_check_path = format!("to_check_{}_{}.txt", now.month(), now.year());
+    super::util::cancel_on_interrupt(&opts.config.cancelled);
+    let console = ConsoleReporter { opts: opts.clone() };
+
+    let _run_lock = match super::lock::lock_run(&opts.config, &mut |notice| {
+        console.report(&Event::Lock { notice: &notice })
+    }) {
+        Ok(lock) => lock,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 3;
+        }
+    };
 
-    if !opts.subdir_mode {
+    let known_good_path = known_good_path(&opts.config);
+    let to_check_path = to_check_path(&opts.config);
+
+    let (exit_code, checked, skipped) = if !opts.subdir_mode {
         // execute in directory
 
-        if opts.loglevel_progress() {
-            super::util::terminal_noecho();
-            println!();
-        }
-        let mut worker_handles = Vec::new();
-        let q = Arc::new(Injector::new());
-        let producer_finished = Arc::new(AtomicBool::new(false));
-        let num_threads = match opts.num_threads {
-            0 => num_cpus::get(),
-            _ => opts.num_threads,
-        };
+        let workdir = opts.config.root.clone();
+        let verifier = Verifier::new(opts.config.clone());
+        let reporter = cli_reporter(
+            &opts,
+            known_good_path,
+            to_check_path,
+            std::slice::from_ref(&workdir),
+            0,
+        );
 
-        let opts = Arc::new(opts);
-        let cloned_opts = Arc::clone(&opts);
-        let workdir = PathBuf::from(&opts.folder);
-        let myq = Arc::clone(&q);
-        let (tx, rx) = channel();
-
-        let handle = thread::spawn(move || {
-            verify_directory(
-                &workdir,
-                Arc::new(known_good_path),
-                Arc::new(to_check_path),
-                cloned_opts,
-                1,
-                0,
-                myq,
-                tx,
-            );
-        });
+        verify_directory(&workdir, &verifier, &reporter)
+    } else {
+        // iterate over subdirs and spawn verify_directory threads
+        execute_threads_subdir(&opts, known_good_path, to_check_path)
+    };
 
-        super::util::execute_workers(
-            num_threads,
-            Arc::clone(&q),
-            Arc::clone(&producer_finished),
-            &mut worker_handles,
+    if opts.config.is_cancelled() {
+        let now: DateTime<chrono::Local> = chrono::Local::now();
+        eprintln!(
+            "[{}] Interrupted: {} files checked, {} files not checked yet",
+            now, checked, skipped
         );
+        return 130;
+    }
 
-        handle.join().unwrap();
+    exit_code
+}
 
-        producer_finished.store(true, Ordering::Relaxed);
+/// Returns the path to the text file listing the directories checked this month that were good
+///
+/// # Arguments
+/// * `config` The Config determining where the verify state is stored
+pub fn known_good_path(config: &Config) -> PathBuf {
+    let now = chrono::Local::now();
+    config.state_file_path(&format!("known_good_{}_{}.txt", now.month(), now.year()))
+}
 
-        for handle in worker_handles {
-            handle.join().unwrap();
-        }
+/// Returns the path to the text file listing the directories checked this month that were bad
+///
+/// # Arguments
+/// * `config` The Config determining where the verify state is stored
+pub fn to_check_path(config: &Config) -> PathBuf {
+    let now = chrono::Local::now();
+    config.state_file_path(&format!("to_check_{}_{}.txt", now.month(), now.year()))
+}
 
-        let mut exit_code = 0;
+/// Returns the paths to the text files listing the bad directories of every month, to_check_MONTH_YEAR.txt
+///
+/// # Arguments
+/// * `config` The Config determining where the verify state is stored
+pub fn to_check_paths(config: &Config) -> io::Result<Vec<PathBuf>> {
+    let state_dir = match config.db_dir {
+        None => PathBuf::from("."),
+        Some(ref db_dir) => db_dir.clone(),
+    };
 
-        for code in rx {
-            if code != 0 {
-                exit_code = code;
-            }
+    let mut paths = Vec::new();
+    for entry in config.filesystem.read_dir(&state_dir)? {
+        let name = match entry.path.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        // to_check_DIRECTORY.txt of a directory named like a month is listed as well
+        let month_year = name
+            .strip_prefix("to_check_")
+            .and_then(|name| name.strip_suffix(".txt"))
+            .and_then(|name| name.split_once('_'));
+        let is_month_year = |(month, year): (&str, &str)| {
+            !month.is_empty()
+                && !year.is_empty()
+                && month.chars().all(|c| c.is_ascii_digit())
+                && year.chars().all(|c| c.is_ascii_digit())
+        };
+        let is_file = entry.metadata.is_ok_and(|metadata| metadata.is_file);
+        if is_file && month_year.is_some_and(is_month_year) {
+            paths.push(config.state_file_path(&name));
         }
+    }
+    paths.sort();
 
-        exit_code
-    } else {
-        // iterate over subdirs and spawn verify_directory threads
-        execute_threads_subdir(
-            opts,
-            known_good_path,
-            to_check_path,
-        )
+    Ok(paths)
+}
+
+/// Returns the path to the text file listing the changed files of a directory, to_check_DIRECTORY.txt
+///
+/// # Arguments
+/// * `config` The Config determining where the verify state is stored
+/// * `workdir` Path to the checked directory
+pub fn to_check_report_path(config: &Config, workdir: &Path) -> PathBuf {
+    let mut to_check_dir = workdir.to_str().unwrap();
+    if to_check_dir.len() > 2 {
+        to_check_dir = &to_check_dir[2..];
     }
+
+    config.state_file_path(&format!("to_check_{}.txt", to_check_dir))
 }
 
 /// Reads all directories in the working directory and compares them with already checked directories.
@@ -113,12 +431,13 @@ pub fn verify_directories(opts: super::util::Options) -> i32 {
 /// * `to_check_path` Path to the text file containing all checked and bad directories
 fn gather_directories_to_process(
     opts: &super::util::Options,
-    known_good_path: &String,
-    to_check_path: &String,
+    known_good_path: &Path,
+    to_check_path: &Path,
 ) -> (Vec<PathBuf>, usize, bool) {
     // read every line from known_good_path and to_check_path to vec
-    let already_checked_good = super::util::read_paths_from_file(&known_good_path);
-    let already_checked_bad = super::util::read_paths_from_file(&to_check_path);
+    let filesystem = &*opts.config.filesystem;
+    let already_checked_good = super::util::read_paths_from_file(filesystem, known_good_path);
+    let already_checked_bad = super::util::read_paths_from_file(filesystem, to_check_path);
     if opts.loglevel_debug() {
         println!("Already checked subdirs: known good: {:?}, known bad: {:?}", already_checked_good, already_checked_bad);
     }
@@ -141,29 +460,26 @@ fn gather_directories_to_process(
         }
     }
 
-    let dir_entries = fs::read_dir(&opts.folder).unwrap();
+    let subdirectories = super::util::subdirectories(&opts.config).unwrap();
+    let ignore = IgnoreRules::read(&*opts.config.filesystem, &opts.config.root);
     let mut dirs_to_process = Vec::new();
     let mut longest_folder = 0;
 
-    for entry in dir_entries {
-        let entry = entry.unwrap();
-        let metadata = entry.metadata().unwrap();
-
-        if metadata.is_dir() {
-            if !(already_checked_good.contains(&entry.path()) || already_checked_bad.contains(&entry.path())) {
-                let sum_txt_path = fs::metadata(format!(
-                    "{}/{}sum.txt",
-                    entry.path().to_str().unwrap(),
-                    &opts.algorithm
-                ));
-                if let Ok(path) = sum_txt_path {
-                    if path.is_file() {
-                        dirs_to_process.push(entry.path());
-                    }
-                }
+    for path in subdirectories {
+        if !ignore.is_ignored(&path, true) {
+            let has_sumfile = opts
+                .config
+                .sumfile_path(&path)
+                .and_then(|sumfile_path| opts.config.filesystem.metadata(&sumfile_path))
+                .map(|metadata| metadata.is_file)
+                .unwrap_or(false);
+            if !(already_checked_good.contains(&path) || already_checked_bad.contains(&path))
+                && has_sumfile
+            {
+                dirs_to_process.push(path.clone());
             }
 
-            let len = entry.path().to_str().unwrap().len();
+            let len = path.to_str().unwrap().len();
             if len > longest_folder {
                 longest_folder = len;
             }
@@ -173,11 +489,23 @@ fn gather_directories_to_process(
     if opts.loglevel_progress() {
         for dir in already_checked_good {
             println!();
-            print_message_aligned(1, "already known good", dir.to_str().unwrap(), longest_folder).unwrap();
+            super::progress::print_message_aligned(
+                1,
+                "already known good",
+                dir.to_str().unwrap(),
+                longest_folder,
+            )
+            .unwrap();
         }
         for dir in already_checked_bad.iter().by_ref() {
             println!();
-            print_message_aligned(1, "already known BAD", dir.to_str().unwrap(), longest_folder).unwrap();
+            super::progress::print_message_aligned(
+                1,
+                "already known BAD",
+                dir.to_str().unwrap(),
+                longest_folder,
+            )
+            .unwrap();
         }
     }
 
@@ -193,87 +521,47 @@ fn gather_directories_to_process(
 /// * `to_check_path` Path to the text file containing all checked and bad directories
 ///
 /// # Returns
-/// The exit code the program should return.
+/// The exit code the program should return, the number of checked files and the number of skipped files.
 fn execute_threads_subdir(
-    opts: super::util::Options,
-    known_good_path: String,
-    to_check_path: String,
-) -> i32 {
+    opts: &super::util::Options,
+    known_good_path: PathBuf,
+    to_check_path: PathBuf,
+) -> (i32, usize, usize) {
     let (dirs_to_process, longest_folder, known_bad_empty) =
-        gather_directories_to_process(&opts, &known_good_path, &to_check_path);
+        gather_directories_to_process(opts, &known_good_path, &to_check_path);
 
-    if opts.loglevel_progress() {
-        super::util::terminal_noecho();
-        for _ in 0..dirs_to_process.len() {
-            println!();
-        }
-    }
-
-    let mut producer_handles = Vec::new();
-    let mut worker_handles = Vec::new();
-    let mut print_line = 1;
-    let opts = Arc::new(opts);
-    let known_good_path = Arc::new(known_good_path);
-    let to_check_path = Arc::new(to_check_path);
-    let q = Arc::new(Injector::new());
-    let producer_finished = Arc::new(AtomicBool::new(false));
-    let num_threads = match opts.num_threads {
-        0 => num_cpus::get(),
-        _ => opts.num_threads,
-    };
-    let (tx, rx) = channel();
+    let reporter = Arc::new(cli_reporter(
+        opts,
+        known_good_path,
+        to_check_path,
+        &dirs_to_process,
+        longest_folder,
+    ));
+    let verifier = Arc::new(Verifier::new(opts.config.clone()));
+    let mut handles = Vec::new();
     let mut exit_code = if known_bad_empty { 0 } else { 2 };
 
     for entry in dirs_to_process {
-        let opts = Arc::clone(&opts);
-        let myq = Arc::clone(&q);
-        let known_good_path = Arc::clone(&known_good_path);
-        let to_check_path = Arc::clone(&to_check_path);
-        let tx = tx.clone();
-
-        let handle = thread::spawn(move || {
-            verify_directory(
-                &entry,
-                known_good_path,
-                to_check_path,
-                opts,
-                print_line,
-                longest_folder,
-                myq,
-                tx,
-            );
-        });
-
-        producer_handles.push(handle);
-
-        print_line += 1;
-    }
-
-    super::util::execute_workers(
-        num_threads,
-        Arc::clone(&q),
-        Arc::clone(&producer_finished),
-        &mut worker_handles,
-    );
-
-    for handle in producer_handles {
-        handle.join().unwrap();
-    }
+        let verifier = Arc::clone(&verifier);
+        let reporter = Arc::clone(&reporter);
 
-    producer_finished.store(true, Ordering::Relaxed);
-
-    for handle in worker_handles {
-        handle.join().unwrap();
+        handles.push(thread::spawn(move || {
+            verify_directory(&entry, &verifier, &*reporter)
+        }));
     }
 
-    drop(tx);
-    for code in rx {
+    let mut checked = 0;
+    let mut skipped = 0;
+    for handle in handles {
+        let (code, dir_checked, dir_skipped) = handle.join().unwrap();
         if code != 0 {
             exit_code = code;
         }
+        checked += dir_checked;
+        skipped += dir_skipped;
     }
 
-    exit_code
+    (exit_code, checked, skipped)
 }
 
 /// Verifies the integrity of a directory
@@ -281,470 +569,247 @@ fn execute_threads_subdir(
 /// # Arguments
 ///
 /// * `workdir` Path to the directory that should be verified
-/// * `known_good_path` The file the workdir path gets appended to if the directory is verified to be good
-/// * `to_check_path` The file the workdir path gets appended to if the directory is not verified to be good
-/// * `opts` An Options object containing information about the program behavior
-/// * `print_line` The line to print progressbar and messages to. Only used in loglevel progress.
-/// * `longest_folder` Number of characters in the name of the longest folder, determines how many spaces are padded
-/// * `tx` Sender for sending the supposed exit code for the program.
-fn verify_directory(
-    workdir: &PathBuf,
-    known_good_path: Arc<String>,
-    to_check_path: Arc<String>,
-    opts: Arc<super::util::Options>,
-    print_line: u32,
-    longest_folder: usize,
-    myq: Arc<Injector<super::util::HashTask>>,
-    tx: Sender<i32>,
-) {
-    if opts.loglevel_info() {
-        let now: DateTime<chrono::Local> = chrono::Local::now();
-        println!(
-            "[{}] Verifying Directory {}",
-            now,
-            workdir.to_str().unwrap()
-        );
-    }
-
-    let mut failed_paths = Vec::new();
-
-    let success = if opts.loglevel_progress() {
-        verify_directory_with_progressbar(
-            &workdir,
-            &opts,
-            print_line,
-            &mut failed_paths,
-            longest_folder,
-            myq,
-        )
-    } else {
-        verify_directory_oneshot(&workdir, &opts, &mut failed_paths, myq)
-    };
-
-    if success.is_ok() {
-        // every file from _algorithm_sum.txt was correct
-        inform_directory_good(&workdir, known_good_path, opts);
-        tx.send(0).unwrap();
-    } else {
-        // some files from _algorithm_sum.txt were INCORRECT
-        inform_directory_bad(&workdir, to_check_path, opts, &failed_paths);
-        tx.send(1).unwrap();
-    }
-}
-
-/// Append workdir to the text file in to_check_path, print FAILED if in loglevel info or above
-/// and append all paths to unexpectedly changed files to to_check_workdir.txt
+/// * `verifier` The Verifier hashing the files
+/// * `reporter` The Reporter printing the results and writing the report files
 ///
-/// # Arguments
-/// * `workdir` Path to the directory that was just checked
-/// * `to_check_path` Path to the text file containing all checked and bad directories
-/// * `opts` The Options object determining subdir_mode and loglevel
-/// * `failed_paths` Vector of paths to files that have changed
-fn inform_directory_bad(
-    workdir: &PathBuf,
-    to_check_path: Arc<String>,
-    opts: Arc<super::util::Options>,
-    failed_paths: &[String],
-) {
-    if opts.subdir_mode {
-        let to_check_path: &String = to_check_path.borrow();
-
-        let mut to_check_file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(to_check_path)
-            .unwrap();
-        if let Err(e) = writeln!(to_check_file, "{}", workdir.to_str().unwrap()) {
-            eprintln!("Error writing to file: {}", e);
-        }
-    }
-    if opts.loglevel_info() {
-        let now = chrono::Local::now();
-        println!(
-            "[{}] Directory {} checked: FAILED",
-            now,
-            workdir.to_str().unwrap()
-        );
-    }
-    let mut to_check_dir = workdir.to_str().unwrap();
-    if to_check_dir.len() > 2 {
-        to_check_dir = &to_check_dir[2..];
-    }
-    let bad_hashlines_filepath = format!("to_check_{}.txt", to_check_dir);
-    if opts.loglevel_debug() {
-        println!("Filepath for Bad Files: {:?}", bad_hashlines_filepath);
-    }
-    let mut bad_hashlines_file = OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(bad_hashlines_filepath)
-        .unwrap();
-    for line in failed_paths {
-        if let Err(e) = writeln!(bad_hashlines_file, "{}", line) {
-            eprintln!("Error writing to file: {}", e);
+/// # Returns
+/// 0 if every file matched its hashsum, 1 if not or if _algorithm_sum.txt could not be read,
+/// 3 if the directory is locked by another process.
+/// Also the number of checked files and the number of files skipped due to an interruption.
+fn verify_directory(
+    workdir: &Path,
+    verifier: &Verifier,
+    reporter: &dyn Reporter,
+) -> (i32, usize, usize) {
+    match verifier.verify_with(workdir, reporter) {
+        Ok(report) => {
+            let checked = report.verified + report.mismatches.len() + report.failed.len();
+            // the code is 0 if every file from _algorithm_sum.txt was correct
+            let code = if report.is_ok() { 0 } else { 1 };
+            (code, checked, report.skipped)
         }
+        Err(e) => (e.exit_code(), 0, 0),
     }
 }
 
-/// Append workdir to the text file in known_good_path and print OK if in loglevel info or above.
+/// Creates the Reporter of verify mode, which prints the results depending on the loglevel
+/// and writes the known_good, to_check and to_check_DIRECTORY.txt files
 ///
 /// # Arguments
-/// * `workdir` Path to the directory that was just checked
-/// * `known_good_path` Path to the text file containing all checked and good directories
 /// * `opts` The Options object determining subdir_mode and loglevel
-fn inform_directory_good(
-    workdir: &PathBuf,
-    known_good_path: Arc<String>,
-    opts: Arc<super::util::Options>,
-) {
-    if opts.subdir_mode {
-        let known_good_path: &String = known_good_path.borrow();
-
-        let mut known_good_file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(known_good_path)
-            .unwrap();
-        if let Err(e) = writeln!(known_good_file, "{}", workdir.to_str().unwrap()) {
-            eprintln!("Error writing to file: {}", e);
-        }
-    }
+/// * `known_good_path` Path to the text file containing all checked and good directories
+/// * `to_check_path` Path to the text file containing all checked and bad directories
+/// * `directories` The directories that get a progressbar. Only used in loglevel progress.
+/// * `longest_folder` Number of characters in the name of the longest folder, determines how many spaces are padded
+fn cli_reporter(
+    opts: &super::util::Options,
+    known_good_path: PathBuf,
+    to_check_path: PathBuf,
+    directories: &[PathBuf],
+    longest_folder: usize,
+) -> MultiReporter {
+    let mut reporter = MultiReporter::new();
 
-    if opts.loglevel_info() {
-        let now = chrono::Local::now();
-        println!("[{}] {}: checked: OK", now, workdir.to_str().unwrap());
-    }
-}
+    reporter.push(Box::new(StateFileReporter {
+        opts: opts.clone(),
+        known_good_path,
+        to_check_path,
+    }));
 
-/// Verifies the integrity of a directory
-///
-/// # Arguments
-///
-/// * `workdir` Path to the directory that should be verified
-/// * `opts` An Options object containing information about the program behavior
-/// * `failed_paths` Reference to a Vector of Paths to files that have changed unexpectedly
-fn verify_directory_oneshot(
-    workdir: &PathBuf,
-    opts: &Arc<super::util::Options>,
-    failed_paths: &mut Vec<String>,
-    myq: Arc<Injector<super::util::HashTask>>,
-) -> Result<(), io::Error> {
-    let file_path_re = match super::util::regex_from_opts(&opts) {
-        Ok(re) => re,
-        Err(e) => panic!(e),
-    };
-    let mut success = true;
-
-    let file = match OpenOptions::new()
-        .read(true)
-        .append(true)
-        .create(true)
-        .open(format!(
-            "{}/{}sum.txt",
-            workdir.to_str().unwrap(),
-            opts.algorithm
-        )) {
-        Ok(f) => f,
-        Err(e) => panic!(e),
-    };
+    if opts.loglevel_progress() {
+        let progress = ProgressReporter::new(directories, longest_folder, "checked");
+        progress.start();
+        reporter.push(Box::new(progress));
+    } else {
+        reporter.push(Box::new(ConsoleReporter { opts: opts.clone() }));
+    }
 
-    let (sender, receiver) = channel();
+    reporter
+}
 
-    for line in BufReader::new(file).lines() {
-        if let Ok(line) = line {
-            if let Some(captures) = file_path_re.captures(&line) {
-                let hash = &captures[1];
-                let path = &captures[2];
+/// A Reporter printing the results of verify mode to the console, depending on the loglevel
+struct ConsoleReporter {
+    /// The Options object determining the loglevel
+    opts: super::util::Options,
+}
 
-                let task = super::util::HashTask {
-                    path: String::from(path),
-                    workdir: PathBuf::from(workdir),
-                    opts: Arc::clone(&opts),
-                    cmp: String::from(hash),
-                    result_chan: sender.clone(),
-                };
+impl Reporter for ConsoleReporter {
+    fn report(&self, event: &Event) {
+        let now: DateTime<chrono::Local> = chrono::Local::now();
 
-                myq.push(task);
+        match *event {
+            Event::DirectoryStarted { directory, .. } => {
+                if self.opts.loglevel_info() {
+                    println!(
+                        "[{}] Verifying Directory {}",
+                        now,
+                        directory.to_str().unwrap()
+                    );
+                }
             }
-        }
-    }
-
-    drop(sender);
-
-    for task_result in receiver {
-        match task_result {
-            Ok((mut hashline, cmp)) => {
-                hashline.pop();
-                if let Some(new_captures) = file_path_re.captures(&hashline) {
-                    let new_hash = &new_captures[1];
-                    if new_hash != cmp {
-                        if opts.loglevel_info() {
-                            let now: DateTime<chrono::Local> = chrono::Local::now();
-                            println!("[{}] {}: {}", now, workdir.to_str().unwrap(), hashline);
-                        }
-                        failed_paths.push(String::from(&new_captures[2]));
-                        success = false;
-                    }
+            Event::Mismatch { directory, output } => {
+                if self.opts.loglevel_info() {
+                    print!(
+                        "[{}] {}: {}",
+                        now,
+                        directory.to_str().unwrap(),
+                        output.hashline()
+                    );
                 }
             }
-            Err(e) => {
-                let now: DateTime<chrono::Local> = chrono::Local::now();
-                eprintln!("[{}] {}: {}", now, workdir.to_str().unwrap(), e);
-
-                failed_paths.push(e.to_string());
-                success = false;
+            Event::Error { directory, error } => {
+                eprintln!("[{}] {}: {}", now, directory.to_str().unwrap(), error);
             }
-        }
-    }
-
-    if success {
-        Ok(())
-    } else {
-        Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Some files changed unexpectedly",
-        ))
-    }
-}
-
-/// Verifies the integrity of a directory and printing a progressbar along the way
-///
-/// # Arguments
-///
-/// * `workdir` Path to the directory that should be verified
-/// * `opts` An Options object containing information about the program behavior
-/// * `print_line` Number of lines to scroll up before printing the progressbar
-/// * `failed_paths` Reference to a Vector of Paths to files that have changed unexpectedly
-/// * `longest_folder` Number of characters in the name of the longest folder
-fn verify_directory_with_progressbar(
-    workdir: &PathBuf,
-    opts: &Arc<super::util::Options>,
-    print_line: u32,
-    failed_paths: &mut Vec<String>,
-    longest_folder: usize,
-    myq: Arc<Injector<super::util::HashTask>>,
-) -> Result<(), io::Error> {
-    let mut processed_bytes: u64 = 0;
-    let file_path_re = match super::util::regex_from_opts(&opts) {
-        Ok(re) => Arc::new(re),
-        Err(e) => panic!(e),
-    };
-    let all_bytes = count_bytes_from_txt(workdir, opts, &file_path_re);
-    let workdir_str = workdir.to_str().unwrap();
-    let workdir_updater = String::from(workdir_str);
-    let file_path_re_updater = Arc::clone(&file_path_re);
-    let (tx_result, rx_result): (
-        Sender<Result<(String, String), HashError>>,
-        Receiver<Result<(String, String), HashError>>,
-    ) = channel();
-    let (tx_paths, rx_paths) = channel();
-
-    print_progress(
-        all_bytes,
-        processed_bytes,
-        print_line,
-        workdir_str,
-        longest_folder,
-    )?;
-
-    let file = match OpenOptions::new()
-        .read(true)
-        .append(true)
-        .create(true)
-        .open(format!(
-            "{}/{}sum.txt",
-            workdir.to_str().unwrap(),
-            opts.algorithm
-        )) {
-        Ok(f) => f,
-        Err(e) => panic!(e),
-    };
-
-    let updater_handle = std::thread::spawn(move || {
-        for task_result in rx_result {
-            match task_result {
-                Ok((mut hashline, cmp)) => {
-                    hashline.pop();
-                    if let Some(new_captures) = file_path_re_updater.captures(&hashline) {
-                        let new_hash = &new_captures[1];
-                        if new_hash != cmp {
-                            tx_paths.send(String::from(&new_captures[2])).unwrap();
-                        }
-
-                        let metadata =
-                            fs::metadata(format!("{}/{}", workdir_updater, &new_captures[2]));
-                        if let Ok(metadata) = metadata {
-                            processed_bytes += metadata.len();
-                        }
+            Event::DirectoryFinished {
+                directory,
+                failed_paths,
+            } => {
+                if self.opts.loglevel_info() {
+                    if failed_paths.is_empty() {
+                        println!("[{}] {}: checked: OK", now, directory.to_str().unwrap());
+                    } else {
+                        println!(
+                            "[{}] Directory {} checked: FAILED",
+                            now,
+                            directory.to_str().unwrap()
+                        );
                     }
-
-                    print_progress(
-                        all_bytes,
-                        processed_bytes,
-                        print_line,
-                        &workdir_updater,
-                        longest_folder,
-                    )
-                    .unwrap();
                 }
-                Err(e) => {
-                    tx_paths.send(e.to_string()).unwrap();
+            }
+            Event::DirectoryInterrupted {
+                directory, skipped, ..
+            } => {
+                eprintln!(
+                    "[{}] Directory {} interrupted, {} files were not checked",
+                    now,
+                    directory.to_str().unwrap(),
+                    skipped
+                );
+            }
+            Event::DirectoryFailed { directory, error } => {
+                eprintln!("[{}] {}: {}", now, directory.to_str().unwrap(), error);
+            }
+            Event::Warning { directory, message } => {
+                eprintln!("[{}] {}: {}", now, directory.to_str().unwrap(), message);
+            }
+            Event::Ignored {
+                directory,
+                path,
+                rule,
+            } => {
+                if self.opts.loglevel_debug() {
+                    println!(
+                        "[{}] {}: ignoring {} ({})",
+                        now,
+                        directory.to_str().unwrap(),
+                        path.to_str().unwrap(),
+                        rule
+                    );
                 }
             }
-        }
-    });
-
-    for line in BufReader::new(file).lines() {
-        if let Ok(line) = line {
-            if let Some(captures) = file_path_re.captures(&line) {
-                let hash = &captures[1];
-                let path = &captures[2];
-
-                let task = super::util::HashTask {
-                    path: String::from(path),
-                    workdir: PathBuf::from(workdir),
-                    opts: Arc::clone(&opts),
-                    cmp: String::from(hash),
-                    result_chan: tx_result.clone(),
-                };
-
-                myq.push(task);
+            Event::Skipped {
+                directory,
+                path,
+                reason,
+            } => {
+                if self.opts.loglevel_info() {
+                    println!(
+                        "[{}] {}: skipping {}: {}",
+                        now,
+                        directory.to_str().unwrap(),
+                        path.to_str().unwrap(),
+                        reason
+                    );
+                }
+            }
+            Event::Lock { notice } => {
+                if self.opts.loglevel_info() {
+                    println!("[{}] {}", now, notice);
+                }
             }
+            Event::FileHashed { .. } | Event::Changed { .. } | Event::Progress { .. } => {}
         }
     }
-
-    drop(tx_result);
-
-    for path in rx_paths {
-        failed_paths.push(path);
-    }
-
-    updater_handle.join().unwrap();
-
-    if failed_paths.is_empty() {
-        print_message_aligned(print_line, "checked: OK", workdir_str, longest_folder)?;
-        Ok(())
-    } else {
-        print_message_aligned(print_line, "checked: FAILED", workdir_str, longest_folder)?;
-        Err(io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Some files changed unexpectedly",
-        ))
-    }
 }
 
-/// Reads all files from an _algorithm_sum.txt and accumulates all bytes
-///
-/// # Arguments
-/// * `workdir` PathBuf to the current working directory with an _algorithm_sum.txt inside
-/// * `opts` The Options object containing the chosen algorithm
-/// * `file_path_re` Regex used to extrapolate the filepath from the line containing filepath and hash
-fn count_bytes_from_txt(
-    workdir: &PathBuf,
-    opts: &Arc<super::util::Options>,
-    file_path_re: &regex::Regex,
-) -> u64 {
-    let mut all_bytes = 0;
-
-    let file = match OpenOptions::new()
-        .read(true)
-        .append(true)
-        .create(true)
-        .open(format!(
-            "{}/{}sum.txt",
-            workdir.to_str().unwrap(),
-            opts.algorithm
-        )) {
-        Ok(f) => f,
-        Err(e) => panic!(e),
-    };
+/// A Reporter writing the checked directories to known_good_MONTH_YEAR.txt and to_check_MONTH_YEAR.txt,
+/// and the changed files to to_check_DIRECTORY.txt
+struct StateFileReporter {
+    /// The Options object determining subdir_mode and loglevel
+    opts: super::util::Options,
+    /// Path to the text file containing all checked and good directories
+    known_good_path: PathBuf,
+    /// Path to the text file containing all checked and bad directories
+    to_check_path: PathBuf,
+}
 
-    for line in BufReader::new(file).lines() {
-        if let Ok(line) = line {
-            if let Some(captures) = file_path_re.captures(&line) {
-                let path = &captures[2];
-                let metadata = fs::metadata(format!("{}/{}", workdir.to_str().unwrap(), path));
-                if let Ok(metadata) = metadata {
-                    all_bytes += metadata.len();
+impl Reporter for StateFileReporter {
+    fn report(&self, event: &Event) {
+        match *event {
+            Event::DirectoryFinished {
+                directory,
+                failed_paths,
+            } => {
+                if failed_paths.is_empty() {
+                    self.inform_directory_good(directory);
+                } else {
+                    self.inform_directory_bad(directory, failed_paths);
                 }
             }
+            // an interrupted directory is only known to be bad, it is checked again otherwise
+            Event::DirectoryInterrupted {
+                directory,
+                failed_paths,
+                ..
+            } if !failed_paths.is_empty() => {
+                self.inform_directory_bad(directory, failed_paths);
+            }
+            _ => {}
         }
     }
-
-    all_bytes
 }
 
-/// Produce a String containing workdir, progress percentage and progress bar, then printing it with print_message
-///
-/// # Arguments
-/// * `all_bytes` Number of bytes in this working directory that are listed in _algorithm_sum.txt
-/// * `processed_bytes` Number of already processed bytes
-/// * `line` Number of lines to scroll up before printing the message
-/// * `workdir` String containing the current working directory, which is printed before the message
-/// * `longest_folder` Number of characters in the name of the longest folder, determines how many spaces are padded
-fn print_progress(
-    all_bytes: u64,
-    processed_bytes: u64,
-    line: u32,
-    workdir: &str,
-    longest_folder: usize,
-) -> Result<(), io::Error> {
-    let progress = processed_bytes as f64 / all_bytes as f64;
-    let mut message = format!("{:05.2}% ", progress * 100.0);
-
-    let progress_bar = 60.0 * progress;
-    for i in 0..60 {
-        if (f64::from(i)) < progress_bar {
-            message = format!("{}#", message);
-        } else {
-            message = format!("{}_", message);
+impl StateFileReporter {
+    /// Append workdir to the text file in to_check_path
+    /// and append all paths to unexpectedly changed files to to_check_workdir.txt
+    ///
+    /// # Arguments
+    /// * `workdir` Path to the directory that was just checked
+    /// * `failed_paths` Vector of paths to files that have changed
+    fn inform_directory_bad(&self, workdir: &Path, failed_paths: &[String]) {
+        let filesystem = &self.opts.config.filesystem;
+        if self.opts.subdir_mode {
+            // a single write, so lines of directories finishing at the same time do not interleave
+            let line = format!("{}\n", workdir.to_str().unwrap());
+            if let Err(e) = filesystem.append(&self.to_check_path, line.as_bytes()) {
+                eprintln!("Error writing to file: {}", e);
+            }
+        }
+        let bad_hashlines_filepath = to_check_report_path(&self.opts.config, workdir);
+        if self.opts.loglevel_debug() {
+            println!("Filepath for Bad Files: {:?}", bad_hashlines_filepath);
+        }
+        let lines: String = failed_paths
+            .iter()
+            .map(|line| format!("{}\n", line))
+            .collect();
+        if let Err(e) = filesystem.append(&bad_hashlines_filepath, lines.as_bytes()) {
+            eprintln!("Error writing to file: {}", e);
         }
     }
 
-    print_message_aligned(line, &message, workdir, longest_folder)
-}
-
-/// Print a message N lines above the current cursor.
-/// Cursor position is saved and restored after this operation.
-/// The line is cleared before printing.
-///
-/// # Arguments
-/// * `line` Number of lines to scroll up before printing the message
-/// * `message` String to print
-/// * `workdir` String containing the current working directory, which is printed before the message
-fn print_message(line: u32, message: &str, workdir: &str) -> Result<(), io::Error> {
-    let stdout = io::stdout();
-    let mut handle = stdout.lock();
-    handle.write_all(b"\x1b[s")?;
-    write!(handle, "\x1b[{}A\x1b[2K", line)?;
-    write!(handle, "{}: {}", workdir, message)?;
-    handle.write_all(b"\x1b[u")?;
-    io::stdout().flush()
-}
-
-/// Print a message N lines above the current cursor.
-/// Cursor position is saved and restored after this operation.
-/// The line is cleared before printing.
-/// The message gets padded to the left with spaces in order to align it with
-/// other messages on other lines, using longest_folder as an indicator of needed padding.
-///
-/// # Arguments
-/// * `line` Number of lines to scroll up before printing the message
-/// * `message` String to print
-/// * `workdir` String containing the current working directory, which is printed before the message
-/// * `longest_folder` length of the name of the longest folder in the current workset
-fn print_message_aligned(
-    line: u32,
-    message: &str,
-    workdir: &str,
-    longest_folder: usize,
-) -> Result<(), io::Error> {
-    let mut padding = String::new();
-    let mut i = workdir.len();
-    while i < longest_folder {
-        padding = format!("{} ", padding);
-        i += 1;
+    /// Append workdir to the text file in known_good_path
+    ///
+    /// # Arguments
+    /// * `workdir` Path to the directory that was just checked
+    fn inform_directory_good(&self, workdir: &Path) {
+        if self.opts.subdir_mode {
+            // a single write, so lines of directories finishing at the same time do not interleave
+            let line = format!("{}\n", workdir.to_str().unwrap());
+            let filesystem = &self.opts.config.filesystem;
+            if let Err(e) = filesystem.append(&self.known_good_path, line.as_bytes()) {
+                eprintln!("Error writing to file: {}", e);
+            }
+        }
     }
-    let to_print = &format!("{} {}", padding, message);
-    print_message(line, to_print, workdir)
 }
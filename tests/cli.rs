@@ -1,3 +1,12 @@
+// the original tests predate these lints and are kept as they were written
+#![allow(
+    clippy::collapsible_if,
+    clippy::unused_io_amount,
+    clippy::bool_assert_comparison,
+    clippy::regex_creation_in_loops
+)]
+
+extern crate arkhash;
 extern crate assert_cli;
 extern crate regex;
 extern crate chrono;
@@ -20,6 +29,36 @@ lazy_static! {
     static ref MTX: Mutex<()> = Mutex::new(());
 }
 
+/// Creates an empty MemoryFs and a Config operating on a directory inside it
+///
+/// # Arguments
+/// * `root` The directory the Config operates on
+fn memory_config(root: &str) -> (std::sync::Arc<arkhash::MemoryFs>, arkhash::Config) {
+    let filesystem = std::sync::Arc::new(arkhash::MemoryFs::new());
+    let mut config = arkhash::Config::new(root);
+    config.filesystem = filesystem.clone();
+
+    (filesystem, config)
+}
+
+/// A Reporter collecting every skipped or ignored path together with the reason it was skipped
+struct SkippedReporter(Mutex<Vec<String>>);
+
+impl arkhash::Reporter for SkippedReporter {
+    fn report(&self, event: &arkhash::Event) {
+        let skipped = match *event {
+            arkhash::Event::Skipped { path, reason, .. } => {
+                format!("{}: {}", path.display(), reason)
+            }
+            arkhash::Event::Ignored { path, rule, .. } => {
+                format!("{}: ignored by {}", path.display(), rule)
+            }
+            _ => return,
+        };
+        self.0.lock().unwrap().push(skipped);
+    }
+}
+
 #[test]
 #[ignore]
 fn template_test() {
@@ -586,6 +625,2072 @@ fn to_check_dirs_skipped_test() {
     teardown();
 }
 
+/// Tests the export mode in all supported formats.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Export checksums as csv, json and hashdeep
+///
+/// # Expected
+/// * arkhash should return without failure
+/// * the csv export should contain a header and one line per hashed file
+/// * every export should contain the size of the files
+#[test]
+fn export_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["--export", "csv", "-o", "export.csv"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    let export = fs::File::open("testenvironment/export.csv").unwrap();
+    let lines: Vec<String> = BufReader::new(export)
+        .lines()
+        .map(|line| line.unwrap())
+        .collect();
+    assert_eq!(lines.len(), 28);
+    assert_eq!(lines[0], "path,size,sha1");
+    assert!(lines
+        .iter()
+        .any(|line| line.starts_with("./test/little_1,10,")));
+
+    Assert::main_binary()
+        .with_args(&["--export", "json"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("\"./secondsecond/little_1\": {\"size\": 10, \"sha1\": ")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["--export", "hashdeep"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("%%%% HASHDEEP-1.0\n%%%% size,sha1,filename")
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests the export mode on the databases of the subdirectories.
+///
+/// # Steps
+/// * Update subdirs for testenvironment
+/// * Export checksums of the subdirs as csv
+///
+/// # Expected
+/// * arkhash should return without failure
+/// * the paths in the export should be relative to testenvironment
+#[test]
+fn export_subdir_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-us"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-s", "--export", "csv"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("./test/middle_1,")
+        .stdout()
+        .contains("./secondsecond/big_1,")
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests the exit code of export when listed files are missing.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Remove a hashed file and export checksums as csv
+///
+/// # Expected
+/// * arkhash should report the missing file and fail with exit code 1
+/// * the export should contain every other file
+#[test]
+fn export_missing_file_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    fs::remove_file("testenvironment/test/little_1").unwrap();
+
+    Assert::main_binary()
+        .with_args(&["--export", "csv", "-o", "export.csv"])
+        .current_dir("testenvironment")
+        .stderr()
+        .contains("./test/little_1: skipped")
+        .fails_with(1)
+        .unwrap();
+
+    let export = fs::read_to_string("testenvironment/export.csv").unwrap();
+    assert_eq!(export.lines().count(), 27);
+    assert!(!export.contains("./test/little_1,"));
+
+    teardown();
+}
+
+/// Tests that a directory locked by another instance is not updated.
+///
+/// # Steps
+/// * Create a lockfile in testenvironment that belongs to a process on another host
+/// * Update checksums for testenvironment
+/// * Update checksums for testenvironment with a lock held on a subdirectory in subdir mode
+///
+/// # Expected
+/// * arkhash should fail with exit code 3 and name the holder of the lock
+/// * no database file should be created in the locked directory
+#[test]
+fn locked_directory_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    let mut lockfile = fs::File::create("testenvironment/.arkhash.lock").unwrap();
+    lockfile.write_all(b"1 otherhost\n").unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .stderr()
+        .contains("is locked by process 1 on host otherhost")
+        .fails_with(3)
+        .unwrap();
+
+    fs::remove_file("testenvironment/.arkhash.lock").unwrap();
+    let mut lockfile = fs::File::create("testenvironment/test/.sha1sum.txt.lock").unwrap();
+    lockfile.write_all(b"1 otherhost\n").unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-us"])
+        .current_dir("testenvironment")
+        .fails_with(3)
+        .unwrap();
+
+    assert!(!std::path::Path::new("testenvironment/test/sha1sum.txt").exists());
+    assert!(std::path::Path::new("testenvironment/secondsecond/sha1sum.txt").exists());
+
+    teardown();
+}
+
+/// Tests that the reading modes respect the lock of a directory.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Create the lockfile of sha1sum.txt that belongs to a process on another host
+/// * Run the filter, export and lint modes
+///
+/// # Expected
+/// * every mode should fail and name the holder of the lock, export with exit code 3
+/// * the lockfile should be left in place
+#[test]
+fn read_modes_locked_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    let mut lockfile = fs::File::create("testenvironment/.sha1sum.txt.lock").unwrap();
+    lockfile.write_all(b"1 otherhost\n").unwrap();
+
+    Assert::main_binary()
+        .current_dir("testenvironment")
+        .stdin("./new_file\n")
+        .stderr()
+        .contains("is locked by process 1 on host otherhost")
+        .fails_with(1)
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["--export", "csv"])
+        .current_dir("testenvironment")
+        .stderr()
+        .contains("is locked by process 1 on host otherhost")
+        .fails_with(3)
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["--lint"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("is locked by process 1 on host otherhost")
+        .fails_with(1)
+        .unwrap();
+
+    assert!(std::path::Path::new("testenvironment/.sha1sum.txt.lock").exists());
+
+    teardown();
+}
+
+/// Tests that a lockfile left behind by a terminated process is removed.
+///
+/// # Steps
+/// * Create a lockfile in testenvironment that belongs to a terminated process on this host
+/// * Update checksums for testenvironment
+///
+/// # Expected
+/// * arkhash should return without failure and report the stale lock
+/// * the lockfiles should be removed after the run
+#[cfg(target_os = "linux")]
+#[test]
+fn stale_lock_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    let mut child = std::process::Command::new("true").spawn().unwrap();
+    child.wait().unwrap();
+    let hostname = fs::read_to_string("/proc/sys/kernel/hostname").unwrap();
+
+    let mut lockfile = fs::File::create("testenvironment/.arkhash.lock").unwrap();
+    write!(lockfile, "{} {}", child.id(), hostname.trim()).unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("Removing stale lock")
+        .unwrap();
+
+    assert!(!std::path::Path::new("testenvironment/.arkhash.lock").exists());
+    assert!(!std::path::Path::new("testenvironment/.sha1sum.txt.lock").exists());
+
+    teardown();
+}
+
+/// Tests the repair of a database whose last line was not written completely.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Cut off the end of the last line in the database
+/// * Verify checksums for testenvironment
+/// * Update checksums for testenvironment
+/// * Verify checksums for testenvironment again
+///
+/// # Expected
+/// * verify should warn about the truncated line and fail because the truncated path does not exist
+/// * update should remove the truncated line and hash the file again
+/// * the second verify should succeed
+#[test]
+fn truncated_line_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    let data = fs::read("testenvironment/sha1sum.txt").unwrap();
+    fs::write("testenvironment/sha1sum.txt", &data[..data.len() - 5]).unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-v"])
+        .current_dir("testenvironment")
+        .stderr()
+        .contains("is truncated, run update to repair it")
+        .fails_with(1)
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .stderr()
+        .contains("removed truncated last line")
+        .unwrap();
+
+    let data = fs::read_to_string("testenvironment/sha1sum.txt").unwrap();
+    assert!(data.ends_with('\n'));
+
+    Assert::main_binary()
+        .with_args(&["-v"])
+        .current_dir("testenvironment")
+        .stdout()
+        .doesnt_contain("FAILED")
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests the lint mode on a clean and a malformed database.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Lint the database
+/// * Add a blank line, a line with a hash of the wrong length and a duplicate line to the database
+/// * Lint the database again
+///
+/// # Expected
+/// * the first lint should return without failure
+/// * the second lint should fail and report every problem with its line number
+#[test]
+fn lint_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["--lint"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("lint: OK")
+        .unwrap();
+
+    let data = fs::read_to_string("testenvironment/sha1sum.txt").unwrap();
+    let first_line = data.lines().next().unwrap().to_string();
+    let mut hashfile = fs::OpenOptions::new()
+        .append(true)
+        .open("testenvironment/sha1sum.txt")
+        .unwrap();
+    write!(
+        hashfile,
+        "\n0123456789abcdef  ./test/little_1\n{}\n",
+        first_line
+    )
+    .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["--lint"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("line 28: blank line")
+        .stdout()
+        .contains("line 29: not a valid hashsum line")
+        .stdout()
+        .contains("line 30: duplicate entry, first listed in line 1")
+        .fails_with(1)
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests that duplicate entries are found regardless of how their paths are written.
+///
+/// # Steps
+/// * Parse a database listing the same file as "./a.jpg", "a.jpg" and "./sub/../a.jpg"
+///
+/// # Expected
+/// * the second and third line are reported as duplicates of the first one
+#[test]
+fn duplicate_normalized_paths_test() {
+    let hash = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+    let contents = format!(
+        "{0}  ./a.jpg\n{0}  a.jpg\n{0}  ./sub/../a.jpg\n{0}  ./b.jpg\n",
+        hash
+    );
+
+    let manifest = arkhash::Manifest::parse(contents.as_bytes(), arkhash::Algorithm::Sha1);
+    assert_eq!(manifest.entries.len(), 4);
+    let diagnostics: Vec<String> = manifest.diagnostics.iter().map(|d| d.to_string()).collect();
+    assert_eq!(diagnostics.len(), 2);
+    assert!(diagnostics[0].starts_with("line 2: duplicate entry, first listed in line 1"));
+    assert!(diagnostics[1].starts_with("line 3: duplicate entry, first listed in line 1"));
+}
+
+/// Tests that the strict mode fails on a malformed database.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Add a line with a hash of the wrong length to the database
+/// * Verify checksums for testenvironment
+/// * Verify checksums for testenvironment in strict mode
+///
+/// # Expected
+/// * the verification without strict mode should ignore the malformed line and succeed
+/// * the verification in strict mode should report the line and fail
+#[test]
+fn strict_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    let mut hashfile = fs::OpenOptions::new()
+        .append(true)
+        .open("testenvironment/sha1sum.txt")
+        .unwrap();
+    hashfile
+        .write_all(b"0123456789abcdef  ./test/little_1\n")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-v"])
+        .current_dir("testenvironment")
+        .stdout()
+        .doesnt_contain("FAILED")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-v", "--strict"])
+        .current_dir("testenvironment")
+        .stderr()
+        .contains("line 28: not a valid hashsum line")
+        .fails_with(1)
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests storing the database outside of the directory to operate on.
+///
+/// # Steps
+/// * Update checksums for testenvironment/test with the database in testenvironment/db
+/// * Verify checksums for testenvironment/test with the database in testenvironment/db
+///
+/// # Expected
+/// * the _algorithm_sum.txt should be written to testenvironment/db instead of testenvironment/test
+/// * the verification should succeed
+/// * testenvironment/test should contain exactly the same files as before
+#[test]
+fn db_dir_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    let list_files = || {
+        let mut files: Vec<String> = fs::read_dir("testenvironment/test")
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        files
+    };
+    let files_before = list_files();
+
+    Assert::main_binary()
+        .with_args(&["-u", "--db-dir", "../db"])
+        .current_dir("testenvironment/test")
+        .unwrap();
+
+    let data = fs::read_to_string("testenvironment/db/sha1sum.txt").unwrap();
+    assert_eq!(data.lines().count(), 13);
+
+    Assert::main_binary()
+        .with_args(&["-v", "--db-dir=../db"])
+        .current_dir("testenvironment/test")
+        .stdout()
+        .contains("checked: OK")
+        .unwrap();
+
+    assert_eq!(files_before, list_files());
+
+    teardown();
+}
+
+/// Tests the filter mode with NUL-separated paths.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Pipe two known paths and one unknown path containing a newline, separated by NUL, into the filter mode
+///
+/// # Expected
+/// * only the unknown path should be printed, terminated by NUL
+#[test]
+fn filter_null_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-0"])
+        .current_dir("testenvironment")
+        .stdin(&b"./test/little_1\0./new\nfile\0./test/middle_1\0"[..])
+        .stdout()
+        .is(&b"./new\nfile\0"[..])
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests that the filter mode reports paths that are not valid UTF-8.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Pipe an unknown path, a path that is not valid UTF-8 and a known path into the filter mode
+///
+/// # Expected
+/// * only the unknown path should be printed
+/// * the invalid path should be reported and arkhash should fail with exit code 1
+#[test]
+fn filter_invalid_utf8_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    Assert::main_binary()
+        .current_dir("testenvironment")
+        .stdin(&b"./new_file\n./new\xfffile\n./test/little_1\n"[..])
+        .stdout()
+        .is("./new_file")
+        .stderr()
+        .contains("./new\u{fffd}file: skipped, the path is not valid UTF-8")
+        .fails_with(1)
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests that the Filter remembers the error its input failed with.
+///
+/// # Steps
+/// * Filter an input on a MemoryFs that fails after a valid and an invalid path
+///
+/// # Expected
+/// * the valid path is returned, the invalid one is remembered
+/// * the error of the input is remembered
+#[test]
+fn filter_read_error_test() {
+    struct FailingReader;
+
+    impl std::io::Read for FailingReader {
+        fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::other("simulated read error"))
+        }
+    }
+
+    let (filesystem, config) = memory_config("archive");
+    filesystem.write_file("archive/a.jpg", b"first picture");
+    let input = std::io::Read::chain(&b"./a.jpg\n./b\xff.jpg\n"[..], FailingReader);
+    let mut filter = arkhash::Filter::new(
+        BufReader::new(input),
+        std::path::Path::new("archive"),
+        &config,
+    )
+    .unwrap();
+
+    let paths: Vec<String> = filter.by_ref().collect();
+    assert_eq!(paths, vec!["./a.jpg"]);
+    assert_eq!(filter.invalid_records(), ["./b\u{fffd}.jpg"]);
+    assert_eq!(
+        filter.read_error().unwrap().to_string(),
+        "simulated read error"
+    );
+}
+
+/// Tests that the filter mode recognizes hashed files regardless of how their paths are written.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Pipe known paths without leading ./, with duplicate separators, with .. components and
+///   as absolute paths into the filter mode, as well as unknown relative and absolute paths
+///
+/// # Expected
+/// * only the unknown paths should be printed, in the form they were given
+#[test]
+fn filter_normalization_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    let absolute = std::env::current_dir()
+        .unwrap()
+        .join("testenvironment")
+        .to_str()
+        .unwrap()
+        .to_string();
+    let input = format!(
+        "test/little_1\n./test//little_2\n./secondsecond/../test/little_3\n{0}/test/little_4\n{0}/./test/middle_1\n./new_file\n{0}/test/new_file\n",
+        absolute
+    );
+
+    Assert::main_binary()
+        .current_dir("testenvironment")
+        .stdin(input)
+        .stdout()
+        .is(format!("./new_file\n{}/test/new_file", absolute).as_str())
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests the inverse and annotated output of the filter mode.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Modify a hashed file
+/// * Pipe a hashed, the modified and a new path into the filter mode with --hashed
+/// * Pipe the same paths into the filter mode with --annotate
+///
+/// # Expected
+/// * --hashed should print the hashed and the modified path
+/// * --annotate should print every path with its status
+#[test]
+fn filter_annotate_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    let mut modified_file = fs::OpenOptions::new()
+        .append(true)
+        .open("testenvironment/test/little_2")
+        .unwrap();
+    modified_file.write_all(b"changed").unwrap();
+    drop(modified_file);
+
+    let input = "./test/little_1\n./test/little_2\n./new_file\n";
+
+    Assert::main_binary()
+        .with_args(&["--hashed"])
+        .current_dir("testenvironment")
+        .stdin(input)
+        .stdout()
+        .is("./test/little_1\n./test/little_2")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["--annotate"])
+        .current_dir("testenvironment")
+        .stdin(input)
+        .stdout()
+        .is("hashed\t./test/little_1\nmodified\t./test/little_2\nnew\t./new_file")
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests the filter mode in subdir mode.
+///
+/// # Steps
+/// * Create a .arkignore in testenvironment, containing "secondsecond"
+/// * Update checksums for every subdir of testenvironment
+/// * Pipe known and new paths in both subdirectories, as well as the sumfile of a subdirectory,
+///   into the filter mode in subdir mode
+///
+/// # Expected
+/// * only the new path in testenvironment/test should be printed
+#[test]
+fn filter_subdir_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    let mut f = fs::File::create("testenvironment/.arkignore").unwrap();
+    f.write_all(b"secondsecond\n").unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-us"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-s"])
+        .current_dir("testenvironment")
+        .stdin("./test/little_1\n./test/sha1sum.txt\n./test/new_file\n./secondsecond/little_1\n./secondsecond/new_file\n")
+        .stdout()
+        .is("./test/new_file")
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests using arkhash as a library.
+///
+/// # Steps
+/// * Update testenvironment/test with an Updater
+/// * Alter a file
+/// * Verify testenvironment/test with a Verifier
+///
+/// # Expected
+/// * The Updater reports all 13 files as hashed
+/// * The Manifest read from sha1sum.txt contains 13 entries
+/// * The Verifier reports the altered file as the only mismatch
+#[test]
+fn library_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    let config = arkhash::Config::new("testenvironment");
+    let directory = std::path::Path::new("testenvironment/test");
+
+    let updater = arkhash::Updater::new(config.clone());
+    let report = updater.update(directory).unwrap();
+    assert_eq!(report.hashed.len(), 13);
+    assert!(report.failed.is_empty());
+
+    let manifest = arkhash::Manifest::read(
+        &*config.filesystem,
+        &config.sumfile_path(directory).unwrap(),
+        config.algorithm,
+    )
+    .unwrap();
+    assert_eq!(manifest.entries.len(), 13);
+    assert!(manifest.diagnostics.is_empty());
+
+    fs::write("testenvironment/test/little_1", b"Altered file").unwrap();
+
+    let verifier = arkhash::Verifier::new(config);
+    let report = verifier.verify(directory).unwrap();
+    assert!(!report.is_ok());
+    assert_eq!(report.verified, 12);
+    assert_eq!(report.failed_paths(), vec![String::from("./little_1")]);
+
+    teardown();
+}
+
+/// Tests receiving the events of update and verify through a Reporter.
+///
+/// # Steps
+/// * Update testenvironment/test with a Reporter recording the events
+/// * Alter a file
+/// * Verify testenvironment/test with the same Reporter
+///
+/// # Expected
+/// * Update reports 13 hashed files between starting and finishing the directory
+/// * Verify reports 13 hashed files and a single mismatch for the altered file
+/// * The last progress event of verify has processed every byte
+#[test]
+fn reporter_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl arkhash::Reporter for Recorder {
+        fn report(&self, event: &arkhash::Event) {
+            let line = match *event {
+                arkhash::Event::DirectoryStarted { .. } => String::from("started"),
+                arkhash::Event::FileHashed { .. } => String::from("hashed"),
+                arkhash::Event::Mismatch { output, .. } => format!("mismatch {}", output.path),
+                arkhash::Event::Progress {
+                    processed_bytes,
+                    total_bytes,
+                    ..
+                } => format!("progress {}/{}", processed_bytes, total_bytes),
+                arkhash::Event::DirectoryFinished { .. } => String::from("finished"),
+                _ => String::from("other"),
+            };
+            self.events.lock().unwrap().push(line);
+        }
+    }
+
+    let config = arkhash::Config::new("testenvironment");
+    let directory = std::path::Path::new("testenvironment/test");
+    let recorder = Recorder {
+        events: Mutex::new(Vec::new()),
+    };
+
+    arkhash::Updater::new(config.clone())
+        .update_with(directory, &recorder)
+        .unwrap();
+    {
+        let events = recorder.events.lock().unwrap();
+        assert_eq!(events.first().unwrap(), "started");
+        assert_eq!(events.last().unwrap(), "finished");
+        assert_eq!(events.iter().filter(|e| *e == "hashed").count(), 13);
+    }
+    recorder.events.lock().unwrap().clear();
+
+    fs::write("testenvironment/test/little_1", b"Altered file").unwrap();
+
+    arkhash::Verifier::new(config)
+        .verify_with(directory, &recorder)
+        .unwrap();
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(events.iter().filter(|e| *e == "hashed").count(), 13);
+    let mismatches: Vec<&String> = events
+        .iter()
+        .filter(|e| e.starts_with("mismatch"))
+        .collect();
+    assert_eq!(mismatches, vec!["mismatch ./little_1"]);
+    let last_progress = events
+        .iter()
+        .rev()
+        .find(|e| e.starts_with("progress"))
+        .unwrap();
+    let bytes: Vec<&str> = last_progress["progress ".len()..].split('/').collect();
+    assert_eq!(bytes[0], bytes[1]);
+
+    teardown();
+}
+
+/// Tests simulating corruption on an in-memory filesystem.
+///
+/// # Steps
+/// * Create three files in a MemoryFs and update them
+/// * Flip a bit of the first file, make reads of the second fail and remove the third
+/// * Verify the directory
+///
+/// # Expected
+/// * The _algorithm_sum.txt in memory lists all three files
+/// * The flipped file is reported as a mismatch
+/// * The unreadable and the removed file are reported as errors
+#[test]
+fn memory_fs_test() {
+    let (filesystem, config) = memory_config("archive");
+    filesystem.write_file("archive/a.jpg", b"first picture");
+    filesystem.write_file("archive/sub/b.jpg", b"second picture");
+    filesystem.write_file("archive/sub/c.jpg", b"third picture");
+
+    let directory = std::path::Path::new("archive");
+
+    let report = arkhash::Updater::new(config.clone())
+        .update(directory)
+        .unwrap();
+    assert_eq!(report.hashed.len(), 3);
+
+    let manifest = arkhash::Manifest::read(
+        &*filesystem,
+        &config.sumfile_path(directory).unwrap(),
+        config.algorithm,
+    )
+    .unwrap();
+    let mut paths: Vec<String> = manifest.entries.into_iter().map(|e| e.path).collect();
+    paths.sort();
+    assert_eq!(paths, vec!["./a.jpg", "./sub/b.jpg", "./sub/c.jpg"]);
+
+    filesystem.flip_bit("archive/a.jpg", 3, 0).unwrap();
+    filesystem
+        .fail_reads("archive/sub/b.jpg", std::io::ErrorKind::Other)
+        .unwrap();
+    arkhash::FileSystem::remove_file(&*filesystem, std::path::Path::new("archive/sub/c.jpg"))
+        .unwrap();
+
+    let report = arkhash::Verifier::new(config).verify(directory).unwrap();
+    assert_eq!(report.verified, 0);
+    assert_eq!(report.mismatches.len(), 1);
+    assert_eq!(report.mismatches[0].path, "./a.jpg");
+    let mut failed: Vec<&str> = report.failed.iter().map(|e| e.path.as_str()).collect();
+    failed.sort();
+    assert_eq!(failed, vec!["./sub/b.jpg", "./sub/c.jpg"]);
+}
+
+/// Tests the dry run of update mode.
+///
+/// # Steps
+/// * Update ./test, then dry run the update of every subdir
+///
+/// # Expected
+/// * arkhash should return without failure
+/// * the files of ./secondsecond are listed, the files of ./test are not
+/// * the totals per directory and the summary are printed
+/// * no sha1sum.txt or lockfile is written to ./secondsecond
+#[test]
+fn dry_run_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u", "test"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-us", "--dry-run"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("./secondsecond: would hash ./big_1 (")
+        .stdout()
+        .contains("Directory ./secondsecond would hash 14 files")
+        .stdout()
+        .contains("Directory ./test would hash 0 files, 0 B")
+        .stdout()
+        .contains("Dry run: would hash 14 files")
+        .stdout()
+        .doesnt_contain("./test: would hash")
+        .unwrap();
+
+    let written: Vec<String> = fs::read_dir("testenvironment/secondsecond")
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().to_string())
+        .filter(|name| name.contains("sum.txt") || name.contains(".lock"))
+        .collect();
+    if !written.is_empty() || fs::metadata("testenvironment/.arkhash.lock").is_ok() {
+        teardown();
+        panic!("dry run wrote files: {:?}", written);
+    }
+
+    teardown();
+}
+
+/// Tests cancelling updates and verifies, like an interrupt of the program does.
+///
+/// # Steps
+/// * Create two files in a MemoryFs and update them
+/// * Add a third file and cancel the Config
+/// * Update and verify the directory with the cancelled Config
+/// * Update the directory with a Config that is not cancelled
+///
+/// # Expected
+/// * The cancelled update skips the new file and leaves _algorithm_sum.txt unchanged
+/// * The cancelled verify skips both listed files and is not ok
+/// * The last update hashes the new file
+#[test]
+fn cancel_test() {
+    let (filesystem, mut config) = memory_config("archive");
+    filesystem.write_file("archive/a.jpg", b"first picture");
+    filesystem.write_file("archive/b.jpg", b"second picture");
+
+    let directory = std::path::Path::new("archive");
+    let sumfile_path = config.sumfile_path(directory).unwrap();
+
+    arkhash::Updater::new(config.clone())
+        .update(directory)
+        .unwrap();
+    let sumfile = arkhash::FileSystem::read(&*filesystem, &sumfile_path).unwrap();
+
+    filesystem.write_file("archive/c.jpg", b"third picture");
+    let cancelled = config.clone();
+    cancelled.cancel();
+    assert!(config.is_cancelled());
+
+    let report = arkhash::Updater::new(cancelled.clone())
+        .update(directory)
+        .unwrap();
+    assert_eq!(report.hashed.len(), 0);
+    assert_eq!(report.skipped, 1);
+    assert_eq!(
+        arkhash::FileSystem::read(&*filesystem, &sumfile_path).unwrap(),
+        sumfile
+    );
+
+    let report = arkhash::Verifier::new(cancelled).verify(directory).unwrap();
+    assert_eq!(report.verified, 0);
+    assert_eq!(report.skipped, 2);
+    assert!(!report.is_ok());
+
+    config.cancelled = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let report = arkhash::Updater::new(config).update(directory).unwrap();
+    assert_eq!(report.hashed.len(), 1);
+    assert_eq!(report.skipped, 0);
+}
+
+/// Tests hashing selected files again.
+///
+/// # Steps
+/// * Update every subdir, then alter ./test/little_1
+/// * Rehash ./test/little_1 and ./test/missing given on the commandline
+/// * Rehash ./secondsecond/big_1 given on STDIN in subdir mode
+/// * Verify ./test
+///
+/// # Expected
+/// * The first rehash prints the old and the new hashsum of little_1, warns about missing and fails
+/// * The second rehash reports big_1 as unchanged
+/// * The verify succeeds, the other lines of sha1sum.txt were kept
+#[test]
+fn rehash_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-us"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open("testenvironment/test/little_1")
+        .unwrap();
+    file.write_all(b" that changed").unwrap();
+
+    Assert::main_binary()
+        .with_args(&["test", "--rehash", "little_1", "missing"])
+        .current_dir("testenvironment")
+        .fails_with(1)
+        .stdout()
+        .contains("./little_1 rehashed: b85ff052083dea11bc843a9d910edb8219e166da -> ")
+        .stderr()
+        .contains("missing is not listed in test/sha1sum.txt")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-s", "--rehash"])
+        .current_dir("testenvironment")
+        .stdin("secondsecond/big_1\n")
+        .stdout()
+        .contains("./secondsecond: ./big_1 unchanged")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-v", "test"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("test: checked: OK")
+        .unwrap();
+
+    let lines = fs::read_to_string("testenvironment/test/sha1sum.txt")
+        .unwrap()
+        .lines()
+        .count();
+    teardown();
+    assert_eq!(lines, 13);
+}
+
+/// Tests accepting changed files reported by verify.
+///
+/// # Steps
+/// * Update every subdir, alter ./test/little_1 and verify every subdir
+/// * Accept in subdir mode, answering no
+/// * Accept in subdir mode with --yes
+/// * Verify ./test
+///
+/// # Expected
+/// * The first accept shows the old and the new hashsum of little_1, keeps it in to_check_test.txt and fails
+/// * The second accept removes to_check_test.txt and ./test from to_check_month_year.txt
+/// * The verify succeeds
+#[test]
+fn accept_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-us"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open("testenvironment/test/little_1")
+        .unwrap();
+    file.write_all(b" that changed").unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-vs"])
+        .current_dir("testenvironment")
+        .fails_with(1)
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-s", "--accept"])
+        .current_dir("testenvironment")
+        .stdin("n\n")
+        .fails_with(1)
+        .stdout()
+        .contains("./test: ./little_1: b85ff052083dea11bc843a9d910edb8219e166da -> ")
+        .stdout()
+        .contains("./test: accepted 0 files, 1 files left to check")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-s", "--accept", "--yes"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("./test: accepted 1 files, 0 files left to check")
+        .unwrap();
+
+    let now: DateTime<chrono::Local> = chrono::Local::now();
+    let to_check = fs::read_to_string(format!(
+        "testenvironment/to_check_{}_{}.txt",
+        now.month(),
+        now.year()
+    ))
+    .unwrap();
+    let report_exists = fs::metadata("testenvironment/to_check_test.txt").is_ok();
+
+    Assert::main_binary()
+        .with_args(&["-v", "test"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("test: checked: OK")
+        .unwrap();
+
+    teardown();
+    assert!(!report_exists);
+    assert!(!to_check.contains("./test"));
+}
+
+/// Tests gitignore-style patterns in .arkignore outside of subdir mode.
+///
+/// # Steps
+/// * Create testenvironment/test/cache/little_1
+/// * Create an .arkignore ignoring every middle_ file except middle_2 and every cache directory
+/// * Update testenvironment
+/// * Verify testenvironment
+///
+/// # Expected
+/// * sha1sum.txt lists middle_2 and the little files, but no other middle file and nothing in cache
+/// * the verification succeeds
+#[test]
+fn update_ignore_patterns_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+    fs::create_dir("testenvironment/test/cache").unwrap();
+    let mut f = fs::File::create("testenvironment/test/cache/little_1").unwrap();
+    f.write_all(b"Small file").unwrap();
+    let mut f = fs::File::create("testenvironment/.arkignore").unwrap();
+    f.write_all(b"# scratch files\nmiddle_*\n!middle_2\n**/cache/\n")
+        .unwrap();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    let sumfile = fs::read_to_string("testenvironment/sha1sum.txt").unwrap();
+    assert!(sumfile.contains("./test/little_1"));
+    assert!(sumfile.contains("./secondsecond/middle_2"));
+    assert!(!sumfile.contains("middle_1"));
+    assert!(!sumfile.contains("middle_4"));
+    assert!(!sumfile.contains("cache"));
+
+    Assert::main_binary()
+        .with_args(&["-v"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests an .arkignore in a subdirectory, which only applies to its own subtree.
+///
+/// # Steps
+/// * Create testenvironment/test/scratch/little_1 and testenvironment/secondsecond/scratch/little_1
+/// * Create testenvironment/test/.arkignore containing "scratch/"
+/// * Update testenvironment with loglevel debug
+///
+/// # Expected
+/// * the output names the pattern that excluded test/scratch
+/// * sha1sum.txt lists secondsecond/scratch/little_1, but not test/scratch/little_1
+#[test]
+fn update_nested_ignore_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+    for dir in &["test", "secondsecond"] {
+        fs::create_dir(format!("testenvironment/{}/scratch", dir)).unwrap();
+        let mut f = fs::File::create(format!("testenvironment/{}/scratch/little_1", dir)).unwrap();
+        f.write_all(b"Small file").unwrap();
+    }
+    let mut f = fs::File::create("testenvironment/test/.arkignore").unwrap();
+    f.write_all(b"scratch/\n").unwrap();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u", "--loglevel", "debug"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("ignoring ./test/scratch (./test/.arkignore:1: scratch/)")
+        .unwrap();
+
+    let sumfile = fs::read_to_string("testenvironment/sha1sum.txt").unwrap();
+    assert!(sumfile.contains("./secondsecond/scratch/little_1"));
+    assert!(!sumfile.contains("./test/scratch"));
+
+    teardown();
+}
+
+/// Tests the symlink policies on a MemoryFs containing a link to a file, a link to a parent directory
+/// and a link to a missing file.
+///
+/// # Steps
+/// * Update the directory with every symlink policy
+/// * Verify the directory updated with record without giving a policy
+/// * Point the link to the file somewhere else and verify the directory again
+///
+/// # Expected
+/// * skip only hashes the regular file
+/// * follow additionally hashes the linked file and does not walk the loop again
+/// * record hashes every link without following it
+/// * the first verify hashes the recorded links by their target path and succeeds
+/// * the verify after retargeting the link reports a mismatch for it
+#[test]
+fn symlinks_test() {
+    let directory = std::path::Path::new("archive");
+    let update = |symlinks| {
+        let (filesystem, mut config) = memory_config("archive");
+        filesystem.write_file("archive/photos/a.jpg", b"first picture");
+        filesystem.symlink("..", "archive/photos/loop");
+        filesystem.symlink("photos/a.jpg", "archive/latest.jpg");
+        filesystem.symlink("missing.jpg", "archive/broken.jpg");
+
+        config.symlinks = symlinks;
+        let report = arkhash::Updater::new(config.clone())
+            .update(directory)
+            .unwrap();
+        let mut hashed: Vec<String> = report.hashed.iter().map(|o| o.path.clone()).collect();
+        hashed.sort();
+
+        (filesystem, config, hashed)
+    };
+
+    let (_, _, hashed) = update(arkhash::SymlinkPolicy::Skip);
+    assert_eq!(hashed, vec!["./photos/a.jpg"]);
+
+    let (_, _, hashed) = update(arkhash::SymlinkPolicy::Follow);
+    assert_eq!(hashed, vec!["./latest.jpg", "./photos/a.jpg"]);
+
+    let (filesystem, config, hashed) = update(arkhash::SymlinkPolicy::Record);
+    assert_eq!(
+        hashed,
+        vec![
+            "./broken.jpg",
+            "./latest.jpg",
+            "./photos/a.jpg",
+            "./photos/loop"
+        ]
+    );
+
+    let mut config = config;
+    config.symlinks = arkhash::SymlinkPolicy::Skip;
+    let report = arkhash::Verifier::new(config.clone())
+        .verify(directory)
+        .unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.verified, 4);
+
+    filesystem.write_file("archive/photos/b.jpg", b"second picture");
+    filesystem.symlink("photos/b.jpg", "archive/latest.jpg");
+    let report = arkhash::Verifier::new(config).verify(directory).unwrap();
+    assert_eq!(report.mismatches.len(), 1);
+    assert_eq!(report.mismatches[0].path, "./latest.jpg");
+}
+
+/// Tests that following symbolic links walks every directory by its real path.
+///
+/// # Steps
+/// * Create a directory in a MemoryFs and two links to it, one next to it and one in another directory,
+///   both walked before the directory itself
+/// * Update the directory, following the links
+///
+/// # Expected
+/// * The file in the directory is only hashed by its real path
+/// * Both links are reported as leading to a walked directory
+#[test]
+fn follow_real_directories_first_test() {
+    let (filesystem, mut config) = memory_config("archive");
+    filesystem.write_file("archive/a/photos/a.jpg", b"first picture");
+    filesystem.symlink("photos", "archive/a/latest");
+    filesystem.symlink("../a/photos", "archive/b/link");
+    config.symlinks = arkhash::SymlinkPolicy::Follow;
+
+    let directory = std::path::Path::new("archive");
+
+    let reporter = SkippedReporter(Mutex::new(Vec::new()));
+    let report = arkhash::Updater::new(config)
+        .update_with(directory, &reporter)
+        .unwrap();
+    let hashed: Vec<&str> = report.hashed.iter().map(|h| h.path.as_str()).collect();
+    assert_eq!(hashed, vec!["./a/photos/a.jpg"]);
+    let mut skipped = reporter.0.lock().unwrap().clone();
+    skipped.sort();
+    assert_eq!(
+        skipped,
+        vec![
+            "archive/a/latest: leads to a directory that is already walked",
+            "archive/b/link: leads to a directory that is already walked"
+        ]
+    );
+}
+
+/// Tests that recorded symbolic links are ignored and excluded like files.
+///
+/// # Steps
+/// * Create a file and three links to it in a MemoryFs, one of them matched by .arkignore
+///   and one by an excluded glob
+/// * Update the directory, recording the links
+///
+/// # Expected
+/// * Only the files and the remaining link are hashed
+/// * The link matched by .arkignore is reported as ignored, the other one as excluded
+#[test]
+fn recorded_symlinks_selection_test() {
+    let (filesystem, mut config) = memory_config("archive");
+    filesystem.write_file("archive/a.jpg", b"first picture");
+    filesystem.write_file("archive/.arkignore", b"scratch.jpg\n");
+    filesystem.symlink("a.jpg", "archive/latest.jpg");
+    filesystem.symlink("a.jpg", "archive/scratch.jpg");
+    filesystem.symlink("a.jpg", "archive/old.tmp");
+    config.symlinks = arkhash::SymlinkPolicy::Record;
+    config.selection.exclude.push(String::from("*.tmp"));
+
+    let directory = std::path::Path::new("archive");
+
+    let reporter = SkippedReporter(Mutex::new(Vec::new()));
+    let report = arkhash::Updater::new(config)
+        .update_with(directory, &reporter)
+        .unwrap();
+    let mut hashed: Vec<&str> = report.hashed.iter().map(|h| h.path.as_str()).collect();
+    hashed.sort();
+    assert_eq!(hashed, vec!["./.arkignore", "./a.jpg", "./latest.jpg"]);
+    let mut skipped = reporter.0.lock().unwrap().clone();
+    skipped.sort();
+    assert_eq!(skipped.len(), 2);
+    assert!(skipped[0].starts_with("archive/old.tmp: "));
+    assert!(skipped[1].starts_with("archive/scratch.jpg: ignored by "));
+}
+
+/// Tests that FIFOs, sockets and device nodes are neither hashed by update nor opened by verify.
+///
+/// # Steps
+/// * Create a file and a FIFO in a MemoryFs and update them
+/// * List a device node in _algorithm_sum.txt and verify the directory
+///
+/// # Expected
+/// * update only hashes the file and reports the FIFO as skipped
+/// * verify reports the device node as a file that could not be hashed
+#[test]
+fn special_files_test() {
+    let (filesystem, config) = memory_config("archive");
+    filesystem.write_file("archive/a.jpg", b"first picture");
+    filesystem.special_file("archive/pipe", arkhash::SpecialFile::Fifo);
+
+    let directory = std::path::Path::new("archive");
+
+    let reporter = SkippedReporter(Mutex::new(Vec::new()));
+    let report = arkhash::Updater::new(config.clone())
+        .update_with(directory, &reporter)
+        .unwrap();
+    assert_eq!(report.hashed.len(), 1);
+    assert_eq!(*reporter.0.lock().unwrap(), vec!["archive/pipe: is a FIFO"]);
+
+    filesystem.special_file("archive/disk", arkhash::SpecialFile::BlockDevice);
+    let sumfile_path = config.sumfile_path(directory).unwrap();
+    let mut sumfile = arkhash::FileSystem::read(&*filesystem, &sumfile_path).unwrap();
+    sumfile.extend_from_slice(b"da39a3ee5e6b4b0d3255bfef95601890afd80709  ./disk\n");
+    filesystem.write_file(&sumfile_path, &sumfile);
+
+    let report = arkhash::Verifier::new(config).verify(directory).unwrap();
+    assert_eq!(report.verified, 1);
+    assert_eq!(report.failed.len(), 1);
+    assert_eq!(report.failed[0].path, "./disk");
+}
+
+/// Tests hashing files whose reads return fewer bytes than requested.
+///
+/// # Steps
+/// * Create two files with the same contents in a MemoryFs, reads of one of them return only 100 bytes
+/// * Update the directory and verify it
+///
+/// # Expected
+/// * both files are hashed completely and get the same hashsum
+/// * verify succeeds
+#[test]
+fn short_reads_test() {
+    let (filesystem, config) = memory_config("archive");
+    let contents = vec![7u8; 5000];
+    filesystem.write_file("archive/a.jpg", &contents);
+    filesystem.write_file("archive/b.jpg", &contents);
+    filesystem.short_reads("archive/b.jpg", 100).unwrap();
+
+    let directory = std::path::Path::new("archive");
+
+    let mut report = arkhash::Updater::new(config.clone())
+        .update(directory)
+        .unwrap();
+    assert!(report.failed.is_empty());
+    report.hashed.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(report.hashed.len(), 2);
+    assert_eq!(report.hashed[1].size, 5000);
+    assert_eq!(report.hashed[0].hash, report.hashed[1].hash);
+
+    let report = arkhash::Verifier::new(config).verify(directory).unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.verified, 2);
+}
+
+/// Tests that entries whose properties can not be read are reported instead of dropping their directory.
+///
+/// # Steps
+/// * Create three files in a MemoryFs and make reading the properties of one of them fail
+/// * Update the directory
+///
+/// # Expected
+/// * The other two files are hashed
+/// * The failing file is reported as skipped together with the error
+#[test]
+fn unreadable_entry_test() {
+    let (filesystem, config) = memory_config("archive");
+    filesystem.write_file("archive/a.jpg", b"first picture");
+    filesystem.write_file("archive/gone.jpg", b"second picture");
+    filesystem.write_file("archive/sub/c.jpg", b"third picture");
+    filesystem
+        .fail_metadata("archive/gone.jpg", std::io::ErrorKind::NotFound)
+        .unwrap();
+
+    let directory = std::path::Path::new("archive");
+
+    let reporter = SkippedReporter(Mutex::new(Vec::new()));
+    let report = arkhash::Updater::new(config)
+        .update_with(directory, &reporter)
+        .unwrap();
+    let mut hashed: Vec<&str> = report.hashed.iter().map(|h| h.path.as_str()).collect();
+    hashed.sort();
+    assert_eq!(hashed, vec!["./a.jpg", "./sub/c.jpg"]);
+    let skipped = reporter.0.lock().unwrap();
+    assert_eq!(skipped.len(), 1);
+    assert!(skipped[0].starts_with("archive/gone.jpg: could not be read: "));
+}
+
+/// Tests restricting update and verify by size and name.
+///
+/// # Steps
+/// * Update a MemoryFs containing a small file, large files and temporary files
+///   with a minimum size and an excluded glob
+/// * Verify the directory with an include glob that matches a lower and an upper case extension
+///
+/// # Expected
+/// * update only hashes the large files and ignores case when excluding
+/// * verify only hashes the included files and reports the other one as excluded
+#[test]
+fn selection_test() {
+    let (filesystem, mut config) = memory_config("archive");
+    filesystem.write_file("archive/small.jpg", b"tiny");
+    filesystem.write_file("archive/large.jpg", &[b'x'; 2048]);
+    filesystem.write_file("archive/large.cr2", &[b'y'; 2048]);
+    filesystem.write_file("archive/large.tmp", &[b'z'; 2048]);
+    filesystem.write_file("archive/IMG_0001.CR2", &[b'y'; 4096]);
+    filesystem.write_file("archive/LARGE.TMP", &[b'z'; 4096]);
+
+    config.selection.min_size = Some(1024);
+    config.selection.exclude.push(String::from("*.tmp"));
+    let directory = std::path::Path::new("archive");
+
+    let report = arkhash::Updater::new(config.clone())
+        .update(directory)
+        .unwrap();
+    let mut hashed: Vec<String> = report.hashed.iter().map(|o| o.path.clone()).collect();
+    hashed.sort();
+    assert_eq!(hashed, vec!["./IMG_0001.CR2", "./large.cr2", "./large.jpg"]);
+
+    config.selection = arkhash::Selection::default();
+    config.selection.include.push(String::from("*.cr2"));
+    let report = arkhash::Verifier::new(config).verify(directory).unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.verified, 2);
+    assert_eq!(report.excluded, vec!["./large.jpg"]);
+}
+
+/// Tests that update writes the hashsums sorted by path.
+///
+/// # Steps
+/// * Update a MemoryFs with many files using several threads, twice from scratch
+/// * Add files sorting before, between and after the listed ones and update again
+/// * Truncate the last line, make replacing _algorithm_sum.txt fail, add a file and update again
+///
+/// # Expected
+/// * both updates produce the same sorted _algorithm_sum.txt
+/// * the new lines are merged into their places
+/// * the last update warns that the truncated line could neither be repaired nor the new line be sorted
+#[test]
+fn sorted_sumfile_test() {
+    let directory = std::path::Path::new("archive");
+    let update = |filesystem: &std::sync::Arc<arkhash::MemoryFs>| {
+        let mut config = arkhash::Config::new("archive");
+        config.filesystem = filesystem.clone();
+        config.num_threads = 4;
+        arkhash::Updater::new(config.clone())
+            .update(directory)
+            .unwrap();
+        let sumfile =
+            arkhash::FileSystem::read(&**filesystem, &config.sumfile_path(directory).unwrap());
+        String::from_utf8(sumfile.unwrap()).unwrap()
+    };
+    let paths = |sumfile: &str| -> Vec<String> {
+        sumfile.lines().map(|line| line[42..].to_string()).collect()
+    };
+
+    let mut sumfiles = Vec::new();
+    for _ in 0..2 {
+        let filesystem = std::sync::Arc::new(arkhash::MemoryFs::new());
+        for i in 10..40 {
+            filesystem.write_file(format!("archive/dir/file_{}", i), &vec![b'x'; i * 100]);
+        }
+        sumfiles.push((update(&filesystem), filesystem));
+    }
+    assert_eq!(sumfiles[0].0, sumfiles[1].0);
+    let mut sorted = paths(&sumfiles[0].0);
+    sorted.sort();
+    assert_eq!(paths(&sumfiles[0].0), sorted);
+
+    let filesystem = &sumfiles[0].1;
+    filesystem.write_file("archive/a", b"before");
+    filesystem.write_file("archive/dir/file_25b", b"between");
+    filesystem.write_file("archive/z", b"after");
+    let sumfile = update(filesystem);
+    let mut sorted = paths(&sumfile);
+    sorted.sort();
+    assert_eq!(paths(&sumfile), sorted);
+    assert_eq!(sorted.len(), 33);
+
+    struct WarningReporter(Mutex<Vec<String>>);
+
+    impl arkhash::Reporter for WarningReporter {
+        fn report(&self, event: &arkhash::Event) {
+            if let arkhash::Event::Warning { message, .. } = *event {
+                self.0.lock().unwrap().push(message.to_string());
+            }
+        }
+    }
+
+    filesystem.write_file(
+        "archive/sha1sum.txt",
+        &sumfile.as_bytes()[..sumfile.len() - 5],
+    );
+    filesystem.fail_atomic_writes("archive/sha1sum.txt", std::io::ErrorKind::PermissionDenied);
+    filesystem.write_file("archive/b", b"unsorted");
+    let mut config = arkhash::Config::new("archive");
+    config.filesystem = filesystem.clone();
+    let reporter = WarningReporter(Mutex::new(Vec::new()));
+    arkhash::Updater::new(config)
+        .update_with(directory, &reporter)
+        .unwrap();
+    let warnings = reporter.0.lock().unwrap();
+    assert_eq!(warnings.len(), 2);
+    assert!(warnings[0].starts_with("could not repair archive/sha1sum.txt: "));
+    assert!(warnings[1].starts_with(
+        "did not sort the new lines of archive/sha1sum.txt, the line before them is truncated: "
+    ));
+}
+
+/// Tests that update does not hash files modified less than the settle time ago.
+///
+/// # Steps
+/// * Create a file modified an hour ago and a file modified just now in a MemoryFs
+/// * Update the directory with a settle time of 10 minutes, then without one
+///
+/// # Expected
+/// * the first update only hashes the old file and reports the new one as skipped
+/// * the second update hashes the new file
+#[test]
+fn settle_test() {
+    let (filesystem, mut config) = memory_config("archive");
+    filesystem.write_file("archive/old.mp4", b"finished video");
+    filesystem.write_file("archive/new.mp4", b"video being cop");
+    let an_hour_ago = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+    filesystem
+        .set_modified("archive/old.mp4", an_hour_ago)
+        .unwrap();
+
+    config.settle = Some(std::time::Duration::from_secs(600));
+    let directory = std::path::Path::new("archive");
+
+    let reporter = SkippedReporter(Mutex::new(Vec::new()));
+    let report = arkhash::Updater::new(config.clone())
+        .update_with(directory, &reporter)
+        .unwrap();
+    assert_eq!(report.hashed.len(), 1);
+    assert_eq!(report.hashed[0].path, "./old.mp4");
+    assert_eq!(
+        *reporter.0.lock().unwrap(),
+        vec!["./new.mp4: was modified less than 600 seconds ago and may still be written"]
+    );
+
+    config.settle = None;
+    let report = arkhash::Updater::new(config).update(directory).unwrap();
+    assert_eq!(report.hashed.len(), 1);
+    assert_eq!(report.hashed[0].path, "./new.mp4");
+}
+
+/// Tests that update reports listed files whose size or modification time changed, without hashing them.
+///
+/// # Steps
+/// * Update a MemoryFs, then truncate one file, touch another one and delete a third one
+/// * Update the directory again, twice
+/// * Rehash the truncated file and update again
+/// * Rehash the touched file and update again
+///
+/// # Expected
+/// * the second and third update report the three files and hash nothing
+/// * after the first rehash only the touched and the deleted file are reported
+/// * after the second rehash the touched file keeps its hashsum and only the deleted file is reported
+#[test]
+fn stat_cache_test() {
+    let (filesystem, config) = memory_config("archive");
+    filesystem.write_file("archive/a.jpg", b"first picture");
+    filesystem.write_file("archive/b.jpg", b"second picture");
+    filesystem.write_file("archive/c.jpg", b"third picture");
+    filesystem.write_file("archive/d.jpg", b"fourth picture");
+
+    let directory = std::path::Path::new("archive");
+    let updater = arkhash::Updater::new(config.clone());
+    let report = updater.update(directory).unwrap();
+    assert_eq!(report.hashed.len(), 4);
+    assert!(report.changed.is_empty());
+
+    filesystem.write_file("archive/a.jpg", b"");
+    let yesterday = std::time::SystemTime::now() - std::time::Duration::from_secs(86400);
+    filesystem.set_modified("archive/b.jpg", yesterday).unwrap();
+    arkhash::FileSystem::remove_file(&*filesystem, std::path::Path::new("archive/c.jpg")).unwrap();
+
+    let expected = vec![
+        (
+            String::from("./a.jpg"),
+            arkhash::StatChange::Resized {
+                recorded: 13,
+                current: 0,
+            },
+        ),
+        (String::from("./b.jpg"), arkhash::StatChange::Modified),
+        (String::from("./c.jpg"), arkhash::StatChange::Missing),
+    ];
+    for _ in 0..2 {
+        let report = updater.update(directory).unwrap();
+        assert!(report.hashed.is_empty());
+        assert_eq!(report.changed, expected);
+    }
+
+    let report = updater
+        .rehash(directory, &[String::from("./a.jpg")])
+        .unwrap();
+    assert_eq!(report.rehashed.len(), 1);
+    let report = updater.update(directory).unwrap();
+    assert_eq!(report.changed, expected[1..].to_vec());
+
+    let report = updater
+        .rehash(directory, &[String::from("./b.jpg")])
+        .unwrap();
+    assert_eq!(report.rehashed[0].hash, report.rehashed[0].cmp);
+    let report = updater.update(directory).unwrap();
+    assert_eq!(report.changed, expected[2..].to_vec());
+}
+
+/// Tests that a last line cut off in the middle of its path is not kept for another file.
+///
+/// # Steps
+/// * Update a MemoryFs containing the files a and ab
+/// * Cut the last line "./ab" to "./a", so that it names the listed file a with the hashsum of ab
+/// * Update and verify the directory
+/// * Cut off only the newline of the last line and update again
+///
+/// # Expected
+/// * the first repair removes the partial line and hashes ab again
+/// * the verify succeeds
+/// * the second repair keeps the complete line
+#[test]
+fn truncated_prefix_path_test() {
+    let (filesystem, config) = memory_config("archive");
+    filesystem.write_file("archive/a", b"first file");
+    filesystem.write_file("archive/ab", b"second file");
+
+    let directory = std::path::Path::new("archive");
+    let sumfile_path = config.sumfile_path(directory).unwrap();
+    let updater = arkhash::Updater::new(config.clone());
+    updater.update(directory).unwrap();
+
+    let sumfile = arkhash::FileSystem::read(&*filesystem, &sumfile_path).unwrap();
+    assert!(sumfile.ends_with(b"  ./ab\n"));
+    filesystem.write_file(&sumfile_path, &sumfile[..sumfile.len() - 2]);
+
+    let report = updater.update(directory).unwrap();
+    let (partial_line, kept) = report.repaired_line.unwrap();
+    assert!(partial_line.ends_with("  ./a"));
+    assert!(!kept);
+    assert_eq!(report.hashed.len(), 1);
+    assert_eq!(report.hashed[0].path, "./ab");
+
+    let report = arkhash::Verifier::new(config).verify(directory).unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.verified, 2);
+
+    filesystem.write_file(&sumfile_path, &sumfile[..sumfile.len() - 1]);
+    let report = updater.update(directory).unwrap();
+    assert!(report.repaired_line.unwrap().1);
+    assert!(report.hashed.is_empty());
+}
+
+/// Tests that symbolic links to directories in the working directory follow the symlink policy in subdir mode.
+///
+/// # Steps
+/// * Link a directory outside of testenvironment and the subdirectory test into testenvironment
+/// * Update in subdir mode, without and with following links
+///
+/// # Expected
+/// * without following links only the real subdirectories get a sha1sum.txt
+/// * with following links the outside directory gets one, the link to test is not updated twice
+#[cfg(unix)]
+#[test]
+fn subdir_symlinks_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+    fs::create_dir("testenvironment_outside").unwrap();
+    fs::write("testenvironment_outside/picture", b"outside").unwrap();
+    std::os::unix::fs::symlink("../testenvironment_outside", "testenvironment/outside").unwrap();
+    std::os::unix::fs::symlink("test", "testenvironment/again").unwrap();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-us", "--loglevel", "debug"])
+        .current_dir("testenvironment")
+        .stdout()
+        .doesnt_contain("./again")
+        .unwrap();
+    assert!(fs::metadata("testenvironment/test/sha1sum.txt").is_ok());
+    assert!(fs::metadata("testenvironment_outside/sha1sum.txt").is_err());
+
+    Assert::main_binary()
+        .with_args(&["-us", "--symlinks", "follow", "--loglevel", "debug"])
+        .current_dir("testenvironment")
+        .stdout()
+        .doesnt_contain("./again")
+        .unwrap();
+    assert!(fs::metadata("testenvironment_outside/sha1sum.txt").is_ok());
+
+    fs::remove_dir_all("testenvironment_outside").unwrap();
+    teardown();
+}
+
+/// Tests that values containing a - are not split like options.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Export checksums as csv to a file with a - in its name
+///
+/// # Expected
+/// * arkhash should return without failure
+/// * the csv export should be written to the given file
+#[test]
+fn option_values_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    Assert::main_binary()
+        .with_args(&["--export", "csv", "-o", "hash-export.csv"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    let export = fs::read_to_string("testenvironment/hash-export.csv").unwrap();
+    assert_eq!(export.lines().next(), Some("path,size,sha1"));
+
+    teardown();
+}
+
+/// Tests accept mode on a MemoryFs.
+///
+/// # Steps
+/// * Update a MemoryFs, change a file and list it in the reports of verify of this and an earlier month
+/// * Accept the changed file without asking
+/// * Verify the directory again
+///
+/// # Expected
+/// * every file was accepted and the reports of both months no longer list the directory or the file
+/// * verify finds no changed files
+#[test]
+fn accept_memoryfs_test() {
+    let (filesystem, config) = memory_config(".");
+    filesystem.write_file("archive/picture.jpg", b"original");
+    filesystem.write_file("archive/other.jpg", b"other");
+
+    let directory = std::path::Path::new("./archive");
+    arkhash::Updater::new(config.clone())
+        .update(directory)
+        .unwrap();
+
+    filesystem.write_file("archive/picture.jpg", b"edited");
+    let report_path = arkhash::cli::to_check_report_path(&config, directory);
+    let to_check_path = arkhash::cli::to_check_path(&config);
+    filesystem.write_file(&report_path, b"./picture.jpg\n");
+    filesystem.write_file(&to_check_path, b"./archive\n");
+    let earlier_path = std::path::Path::new("to_check_12_2000.txt");
+    filesystem.write_file(earlier_path, b"./other\n./archive\n");
+
+    let mut opts = arkhash::cli::Options::new(vec![
+        String::from("arkhash"),
+        String::from("--accept"),
+        String::from("-y"),
+        String::from("--quiet"),
+    ]);
+    opts.config = config.clone();
+    let accepted =
+        arkhash::cli::accept_directory(directory, &opts, &mut &b""[..]).unwrap();
+    assert!(accepted);
+
+    assert!(arkhash::FileSystem::metadata(&*filesystem, &report_path).is_err());
+    let to_check = arkhash::FileSystem::read(&*filesystem, &to_check_path).unwrap();
+    assert!(to_check.is_empty());
+    let earlier = arkhash::FileSystem::read(&*filesystem, earlier_path).unwrap();
+    assert_eq!(earlier, b"./other\n");
+
+    let report = arkhash::Verifier::new(config).verify(directory).unwrap();
+    assert!(report.is_ok());
+    assert_eq!(report.verified, 2);
+}
+
+/// Tests lint mode on a MemoryFs.
+///
+/// # Steps
+/// * Update a MemoryFs and remove one of the listed files
+/// * Lint the directory
+///
+/// # Expected
+/// * lint reports the missing file as the only problem
+#[test]
+fn lint_memoryfs_test() {
+    let (filesystem, config) = memory_config("archive");
+    filesystem.write_file("archive/picture.jpg", b"picture");
+    filesystem.write_file("archive/deleted.jpg", b"deleted");
+
+    let directory = std::path::Path::new("archive");
+    arkhash::Updater::new(config.clone())
+        .update(directory)
+        .unwrap();
+
+    let mut opts = arkhash::cli::Options::new(vec![
+        String::from("arkhash"),
+        String::from("--lint"),
+        String::from("--quiet"),
+    ]);
+    opts.config = config;
+    assert_eq!(arkhash::cli::lint_directory(directory, &opts), 0);
+
+    arkhash::FileSystem::remove_file(&*filesystem, &directory.join("deleted.jpg")).unwrap();
+    assert_eq!(arkhash::cli::lint_directory(directory, &opts), 1);
+}
+
+/// Tests that lint does not follow recorded symbolic links.
+///
+/// # Steps
+/// * Update a MemoryFs containing a file and a link to a missing file, recording the link
+/// * Lint the directory
+///
+/// # Expected
+/// * lint finds no problems, because the link itself exists
+#[test]
+fn lint_recorded_symlinks_test() {
+    let (filesystem, mut config) = memory_config("archive");
+    filesystem.write_file("archive/picture.jpg", b"picture");
+    filesystem.symlink("missing.jpg", "archive/broken.jpg");
+    config.symlinks = arkhash::SymlinkPolicy::Record;
+
+    let directory = std::path::Path::new("archive");
+    let report = arkhash::Updater::new(config.clone())
+        .update(directory)
+        .unwrap();
+    assert_eq!(report.hashed.len(), 2);
+
+    let mut opts = arkhash::cli::Options::new(vec![
+        String::from("arkhash"),
+        String::from("--lint"),
+        String::from("--quiet"),
+    ]);
+    opts.config = config;
+    assert_eq!(arkhash::cli::lint_directory(directory, &opts), 0);
+}
+
+/// Tests the exit code of update for files that changed since they were hashed.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Truncate a hashed file and update again
+///
+/// # Expected
+/// * the second update reports the truncated file and fails with exit code 1
+#[test]
+fn update_changed_exit_code_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    fs::File::create("testenvironment/test/middle_1").unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .fails_with(1)
+        .stderr()
+        .contains("./test/middle_1 has changed its size")
+        .unwrap();
+
+    teardown();
+}
+
+/// Tests a separate database directory for directories outside the folder to operate on.
+///
+/// # Steps
+/// * Update a subdirectory of the folder on a MemoryFs with the database in db
+/// * Update a directory outside the folder
+///
+/// # Expected
+/// * the _algorithm_sum.txt of the subdirectory is written to the same relative position below db
+/// * updating the directory outside the folder fails without writing anything below db
+#[test]
+fn db_dir_outside_root_test() {
+    let (filesystem, mut config) = memory_config("./archive");
+    config.db_dir = Some(std::path::PathBuf::from("db"));
+    filesystem.write_file("archive/sub/picture.jpg", b"picture");
+    filesystem.write_file("other/picture.jpg", b"picture");
+
+    let updater = arkhash::Updater::new(config.clone());
+    let report = updater.update(std::path::Path::new("./archive/sub")).unwrap();
+    assert_eq!(report.hashed.len(), 1);
+    assert!(arkhash::FileSystem::metadata(&*filesystem, "db/sub/sha1sum.txt".as_ref()).is_ok());
+
+    assert!(updater.update(std::path::Path::new("./other")).is_err());
+    assert!(arkhash::FileSystem::metadata(&*filesystem, "db/other".as_ref()).is_err());
+    assert!(arkhash::FileSystem::metadata(&*filesystem, "db/sha1sum.txt".as_ref()).is_err());
+}
+
+/// Tests the byte totals reported to the progressbars of update.
+///
+/// # Steps
+/// * Update a MemoryFs directory containing three files of different sizes
+///
+/// # Expected
+/// * the directory is started with the size of all files
+/// * every hashed file is followed by progress towards that size, ending with all bytes processed
+#[test]
+fn progress_events_test() {
+    let (filesystem, config) = memory_config("archive");
+    filesystem.write_file("archive/small.jpg", &[1; 10]);
+    filesystem.write_file("archive/medium.jpg", &[2; 200]);
+    filesystem.write_file("archive/large.mp4", &[3; 3000]);
+
+    struct BytesReporter(Mutex<Vec<(u64, u64)>>);
+
+    impl arkhash::Reporter for BytesReporter {
+        fn report(&self, event: &arkhash::Event) {
+            match *event {
+                arkhash::Event::DirectoryStarted { total_bytes, .. } => {
+                    self.0.lock().unwrap().push((0, total_bytes))
+                }
+                arkhash::Event::Progress {
+                    processed_bytes,
+                    total_bytes,
+                    ..
+                } => self.0.lock().unwrap().push((processed_bytes, total_bytes)),
+                _ => {}
+            }
+        }
+    }
+
+    let reporter = BytesReporter(Mutex::new(Vec::new()));
+    arkhash::Updater::new(config)
+        .update_with(std::path::Path::new("archive"), &reporter)
+        .unwrap();
+
+    let bytes = reporter.0.lock().unwrap();
+    assert_eq!(bytes.len(), 4);
+    assert_eq!(bytes[0], (0, 3210));
+    assert!(bytes.iter().all(|&(_, total)| total == 3210));
+    assert!(bytes.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert_eq!(bytes[3], (3210, 3210));
+}
+
+/// Tests the progressbars of update without a terminal.
+///
+/// # Steps
+/// * Update checksums for testenvironment
+/// * Cut off the end of the last line in the database
+/// * Update checksums for testenvironment with loglevel progress
+///
+/// # Expected
+/// * arkhash should return without failure
+/// * the overall line shows the processed bytes, throughput and ETA
+/// * the warning about the truncated line is printed on stdout together with the progressbars
+#[test]
+fn progress_loglevel_test() {
+    let _guard = MTX.lock().unwrap();
+
+    setup();
+
+    // test
+    Assert::main_binary()
+        .with_args(&["-u"])
+        .current_dir("testenvironment")
+        .unwrap();
+
+    let data = fs::read("testenvironment/sha1sum.txt").unwrap();
+    fs::write("testenvironment/sha1sum.txt", &data[..data.len() - 5]).unwrap();
+
+    Assert::main_binary()
+        .with_args(&["-u", "--loglevel", "progress"])
+        .current_dir("testenvironment")
+        .stdout()
+        .contains("total:")
+        .stdout()
+        .contains("/s, ETA 0h")
+        .stdout()
+        .contains("removed truncated last line")
+        .stderr()
+        .doesnt_contain("truncated")
+        .unwrap();
+
+    teardown();
+}
+
 /// Sets up the testenvironment for all tests.
 /// Creates the folders `testenvironment`, `testenvironment/test` and `testenvironment/secondsecond`.
 /// Populates both subdirectories with 10 small and 5 medium sized files. Adds another big file to secondsecond.